@@ -0,0 +1,916 @@
+//! `rkvr browse`: a full-screen view of the rmrf and bkup spaces. Bundles
+//! are listed newest first and narrowed by a fuzzy filter as it is typed.
+//! The selected bundle's metadata and file tree are previewed beside the
+//! list. From there the bundle can be restored, pinned, verified or purged,
+//! and files inside it restored or purged.
+
+use crate::contents;
+use crate::crypt;
+use crate::diff;
+use crate::index;
+use crate::listing::{self, BundleRecord};
+use crate::manifest::{EntryKind, FileEntry};
+use crate::matching::{MatchMode, Matcher};
+use crate::Scope;
+use eyre::{eyre, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::{execute, terminal};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Rows moved by PageUp and PageDown.
+const PAGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Rmrf,
+    Bkup,
+}
+
+impl Space {
+    fn label(self) -> &'static str {
+        match self {
+            Space::Rmrf => "rmrf",
+            Space::Bkup => "bkup",
+        }
+    }
+}
+
+/// One bundle in the list.
+struct Item {
+    space: Space,
+    record: BundleRecord,
+    /// The file tree recorded in its metadata.
+    contents: Option<String>,
+}
+
+/// The files inside one bundle, once asked for.
+struct Files {
+    bundle: PathBuf,
+    entries: Vec<FileEntry>,
+    state: ListState,
+    /// Paths marked for restoring or purging.
+    marked: BTreeSet<String>,
+}
+
+impl Files {
+    /// The marked paths, or the one under the cursor when none are.
+    fn picked(&self) -> Vec<String> {
+        if self.marked.is_empty() {
+            let current = self.state.selected().unwrap_or(0);
+            self.entries.get(current).map(|e| e.path.clone()).into_iter().collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Bundles,
+    Filter,
+    Files,
+    /// Waiting for `y` to purge the selected bundle.
+    ConfirmPurge,
+    /// Waiting for `y` to purge the picked files from the listed bundle.
+    ConfirmPurgeFiles,
+}
+
+/// Work a key asks for, which reads or changes bundles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Task {
+    /// Restore a whole bundle, removing it afterwards when `remove`, as
+    /// `rcvr` does for rmrf bundles.
+    Restore {
+        bundle: PathBuf,
+        remove: bool,
+    },
+    /// Restore these paths from inside a bundle under its `cwd`.
+    RestoreFiles {
+        bundle: PathBuf,
+        cwd: PathBuf,
+        paths: Vec<String>,
+    },
+    TogglePin(PathBuf),
+    Verify(PathBuf),
+    Purge(PathBuf),
+    /// Purge these paths from inside a bundle, keeping the rest.
+    PurgeFiles {
+        bundle: PathBuf,
+        paths: Vec<String>,
+    },
+    ListFiles(PathBuf),
+}
+
+impl Task {
+    fn bundle(&self) -> &Path {
+        match self {
+            Task::Restore { bundle, .. } | Task::RestoreFiles { bundle, .. } | Task::PurgeFiles { bundle, .. } => {
+                bundle
+            }
+            Task::TogglePin(bundle) | Task::Verify(bundle) | Task::Purge(bundle) | Task::ListFiles(bundle) => bundle,
+        }
+    }
+
+    /// Whether the task may prompt, for a sudo password or a passphrase,
+    /// and so needs the terminal back while it runs.
+    fn needs_terminal(&self, sudo: bool) -> bool {
+        let writes = matches!(self, Task::Restore { .. } | Task::Purge(_) | Task::PurgeFiles { .. });
+        (writes && sudo) || self.bundle().join(crypt::ENVELOPE_FILE).exists()
+    }
+
+    fn describe(&self) -> String {
+        let verb = match self {
+            Task::Restore { .. } | Task::RestoreFiles { .. } => "Restoring",
+            Task::TogglePin(_) => "Pinning",
+            Task::Verify(_) => "Verifying",
+            Task::Purge(_) | Task::PurgeFiles { .. } => "Purging",
+            Task::ListFiles(_) => "Reading",
+        };
+        format!("{} {}…", verb, name(self.bundle()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Quit,
+    Run(Task),
+}
+
+fn name(bundle: &Path) -> String {
+    bundle.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+struct App {
+    /// Every bundle, newest first.
+    items: Vec<Item>,
+    /// Indices into `items` that pass the filter, best match first.
+    visible: Vec<usize>,
+    filter: String,
+    threshold: i64,
+    list: ListState,
+    focus: Focus,
+    files: Option<Files>,
+    /// The outcome of the last task, shown until the next key, and whether
+    /// it failed.
+    status: Option<(String, bool)>,
+}
+
+impl App {
+    fn new(items: Vec<Item>, threshold: i64) -> Self {
+        let mut app = Self {
+            items: vec![],
+            visible: vec![],
+            filter: String::new(),
+            threshold,
+            list: ListState::default(),
+            focus: Focus::Bundles,
+            files: None,
+            status: None,
+        };
+        app.set_items(items);
+        app
+    }
+
+    /// Replace the bundles, staying on the selected one if it is still there.
+    fn set_items(&mut self, items: Vec<Item>) {
+        let selected = self.selected().map(|item| item.record.path.clone());
+        self.items = items;
+        self.apply_filter();
+        if let Some(position) =
+            selected.and_then(|path| self.visible.iter().position(|&i| self.items[i].record.path == path))
+        {
+            self.list.select(Some(position));
+        }
+        if self.files.as_ref().map(|f| &f.bundle) != self.selected().map(|item| &item.record.path) {
+            self.files = None;
+        }
+    }
+
+    /// Narrow the list to the bundles matching the filter, best first, and
+    /// select the first.
+    fn apply_filter(&mut self) {
+        let matcher = Matcher::new(MatchMode::Fuzzy, std::slice::from_ref(&self.filter), self.threshold)
+            .expect("fuzzy patterns always compile");
+        let mut visible = Vec::new();
+        for (i, item) in self.items.iter_mut().enumerate() {
+            item.record.matched = None;
+            if self.filter.is_empty() {
+                visible.push(i);
+            } else if let Some(matched) = matcher.best(&item.record, item.contents.as_deref()) {
+                item.record.matched = Some(matched);
+                visible.push(i);
+            }
+        }
+        visible.sort_by_key(|&i| std::cmp::Reverse(self.items[i].record.matched.as_ref().map(|m| m.score)));
+        self.visible = visible;
+        self.list.select((!self.visible.is_empty()).then_some(0));
+    }
+
+    fn selected(&self) -> Option<&Item> {
+        self.list
+            .selected()
+            .and_then(|position| self.visible.get(position))
+            .map(|&i| &self.items[i])
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0);
+        let last = self.visible.len() - 1;
+        self.list.select(Some(current.saturating_add_signed(delta).min(last)));
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Step {
+        self.status = None;
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Step::Quit;
+        }
+        match self.focus {
+            Focus::Filter => self.filter_key(key),
+            Focus::ConfirmPurge => {
+                self.focus = Focus::Bundles;
+                match self.selected() {
+                    Some(item) if key.code == KeyCode::Char('y') => Step::Run(Task::Purge(item.record.path.clone())),
+                    _ => {
+                        self.status = Some(("Purge cancelled".to_string(), false));
+                        Step::Continue
+                    }
+                }
+            }
+            Focus::ConfirmPurgeFiles => {
+                self.focus = Focus::Files;
+                match self.files.as_ref() {
+                    Some(files) if key.code == KeyCode::Char('y') => Step::Run(Task::PurgeFiles {
+                        bundle: files.bundle.clone(),
+                        paths: files.picked(),
+                    }),
+                    _ => {
+                        self.status = Some(("Purge cancelled".to_string(), false));
+                        Step::Continue
+                    }
+                }
+            }
+            Focus::Files => self.files_key(key),
+            Focus::Bundles => self.bundles_key(key),
+        }
+    }
+
+    fn filter_key(&mut self, key: KeyEvent) -> Step {
+        match key.code {
+            KeyCode::Enter => self.focus = Focus::Bundles,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.apply_filter();
+                self.focus = Focus::Bundles;
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.apply_filter();
+            }
+            KeyCode::Up => self.move_by(-1),
+            KeyCode::Down => self.move_by(1),
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.apply_filter();
+            }
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    fn bundles_key(&mut self, key: KeyEvent) -> Step {
+        match key.code {
+            KeyCode::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.apply_filter();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Step::Quit,
+            KeyCode::Char('/') => self.focus = Focus::Filter,
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-(PAGE as isize)),
+            KeyCode::PageDown => self.move_by(PAGE as isize),
+            KeyCode::Home => self.move_by(isize::MIN),
+            KeyCode::End => self.move_by(isize::MAX),
+            KeyCode::Enter | KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => {
+                let Some(item) = self.selected() else {
+                    return Step::Continue;
+                };
+                if self.files.as_ref().is_some_and(|f| f.bundle == item.record.path) {
+                    self.focus = Focus::Files;
+                } else {
+                    return Step::Run(Task::ListFiles(item.record.path.clone()));
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(item) = self.selected() {
+                    return Step::Run(Task::Restore {
+                        bundle: item.record.path.clone(),
+                        remove: item.space == Space::Rmrf,
+                    });
+                }
+            }
+            _ => return self.bundle_key(key),
+        }
+        Step::Continue
+    }
+
+    fn files_key(&mut self, key: KeyEvent) -> Step {
+        let cwd = self.selected().and_then(|item| item.record.cwd.clone());
+        let Some(files) = self.files.as_mut() else {
+            self.focus = Focus::Bundles;
+            return Step::Continue;
+        };
+        let last = files.entries.len().saturating_sub(1);
+        let current = files.state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') => return Step::Quit,
+            KeyCode::Esc | KeyCode::Tab | KeyCode::Left | KeyCode::Char('h') => self.focus = Focus::Bundles,
+            KeyCode::Up | KeyCode::Char('k') => files.state.select(Some(current.saturating_sub(1))),
+            KeyCode::Down | KeyCode::Char('j') => files.state.select(Some((current + 1).min(last))),
+            KeyCode::PageUp => files.state.select(Some(current.saturating_sub(PAGE))),
+            KeyCode::PageDown => files.state.select(Some((current + PAGE).min(last))),
+            KeyCode::Home => files.state.select(Some(0)),
+            KeyCode::End => files.state.select(Some(last)),
+            KeyCode::Char(' ') => {
+                if let Some(entry) = files.entries.get(current) {
+                    if !files.marked.remove(&entry.path) {
+                        files.marked.insert(entry.path.clone());
+                    }
+                    files.state.select(Some((current + 1).min(last)));
+                }
+            }
+            KeyCode::Char('r') => {
+                let paths = files.picked();
+                let Some(cwd) = cwd else {
+                    self.status = Some((
+                        "Its metadata cannot be read, so where the files belong is unknown".into(),
+                        true,
+                    ));
+                    return Step::Continue;
+                };
+                if !paths.is_empty() {
+                    return Step::Run(Task::RestoreFiles {
+                        bundle: files.bundle.clone(),
+                        cwd,
+                        paths,
+                    });
+                }
+            }
+            // Here `d` purges the picked files; the whole bundle is only
+            // purged from the list.
+            KeyCode::Char('d') => {
+                if !files.picked().is_empty() {
+                    self.focus = Focus::ConfirmPurgeFiles;
+                }
+            }
+            _ => return self.bundle_key(key),
+        }
+        Step::Continue
+    }
+
+    /// Keys that act on the selected bundle whichever pane has focus.
+    fn bundle_key(&mut self, key: KeyEvent) -> Step {
+        let Some(item) = self.selected() else {
+            return Step::Continue;
+        };
+        let bundle = item.record.path.clone();
+        match key.code {
+            KeyCode::Char('p') => Step::Run(Task::TogglePin(bundle)),
+            KeyCode::Char('v') => Step::Run(Task::Verify(bundle)),
+            KeyCode::Char('d') => {
+                self.focus = Focus::ConfirmPurge;
+                Step::Continue
+            }
+            _ => Step::Continue,
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [top, main, bottom] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(main);
+
+        let mut filter = vec!["/".bold(), Span::raw(self.filter.clone())];
+        if self.focus == Focus::Filter {
+            filter.push("█".into());
+        }
+        frame.render_widget(Paragraph::new(Line::from(filter)), top);
+
+        let now = SystemTime::now();
+        let rows: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let record = &self.items[i].record;
+                let age = crate::bundle_age(&record.path, now)
+                    .map(listing::format_age)
+                    .unwrap_or_else(|| "-".to_string());
+                let mut line = vec![
+                    Span::raw(format!("{:>4} ", age)),
+                    Span::raw(format!("{} ", self.items[i].space.label())).dim(),
+                    Span::raw(record.name.clone()).blue(),
+                    Span::raw(format!(" {}", record.targets.join(", "))),
+                ];
+                if record.pinned {
+                    line.push(" pinned".yellow());
+                }
+                ListItem::new(Line::from(line))
+            })
+            .collect();
+        let title = format!(" {} of {} bundles ", self.visible.len(), self.items.len());
+        let list = List::new(rows)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, left, &mut self.list);
+
+        let selected = self.selected().map(|item| item.record.path.clone());
+        match self.files.as_mut().filter(|f| Some(&f.bundle) == selected.as_ref()) {
+            Some(files) => {
+                let rows: Vec<ListItem> = files
+                    .entries
+                    .iter()
+                    .map(|entry| {
+                        let mark = if files.marked.contains(&entry.path) { "+" } else { " " };
+                        let kind = match entry.kind {
+                            EntryKind::File => "-",
+                            EntryKind::Dir => "d",
+                            EntryKind::Symlink => "l",
+                            EntryKind::Other => "?",
+                        };
+                        ListItem::new(format!(
+                            "{}{} {:>6} {}",
+                            mark,
+                            kind,
+                            listing::format_size(entry.size),
+                            entry.path
+                        ))
+                    })
+                    .collect();
+                let title = format!(" {} entries, {} marked ", files.entries.len(), files.marked.len());
+                let mut list = List::new(rows).block(Block::bordered().title(title));
+                if self.focus == Focus::Files {
+                    list = list.highlight_style(Style::new().reversed());
+                }
+                frame.render_stateful_widget(list, right, &mut files.state);
+            }
+            None => {
+                let preview = self.selected().map(preview).unwrap_or_default();
+                frame.render_widget(Paragraph::new(preview).block(Block::bordered()), right);
+            }
+        }
+
+        let footer = match (&self.status, self.focus) {
+            (Some((text, true)), _) => Line::from(text.clone()).red(),
+            (Some((text, false)), _) => Line::from(text.clone()),
+            (None, Focus::ConfirmPurge) => {
+                let bundle = self.selected().map(|item| item.record.name.clone()).unwrap_or_default();
+                Line::from(format!("Purge {} for good? y/n", bundle)).yellow()
+            }
+            (None, Focus::ConfirmPurgeFiles) => {
+                let (count, bundle) = self
+                    .files
+                    .as_ref()
+                    .map(|files| (files.picked().len(), name(&files.bundle)))
+                    .unwrap_or_default();
+                Line::from(format!("Purge {} entries from {} for good? y/n", count, bundle)).yellow()
+            }
+            (None, Focus::Filter) => Line::from("type to filter  ↑↓ move  enter done  esc clear").dim(),
+            (None, Focus::Files) => {
+                Line::from("space mark  r restore  d purge  p pin  v verify  esc back  q quit").dim()
+            }
+            (None, Focus::Bundles) => {
+                Line::from("/ filter  ↑↓ move  enter files  r restore  p pin  v verify  d purge  q quit").dim()
+            }
+        };
+        frame.render_widget(Paragraph::new(footer), bottom);
+    }
+}
+
+/// What the preview pane shows of a bundle: its metadata, why it matched
+/// the filter, and its file tree.
+fn preview(item: &Item) -> Vec<Line<'static>> {
+    let record = &item.record;
+    let field = |label: &str, value: String| Line::from(vec![format!("{:>9} ", label).dim(), Span::raw(value)]);
+    let mut lines = vec![
+        Line::from(vec![
+            Span::raw(record.name.clone()).blue().bold(),
+            format!(" ({})", item.space.label()).dim(),
+        ]),
+        field("path", record.path.display().to_string()),
+    ];
+    if let Some(timestamp) = record.timestamp {
+        lines.push(field("created", timestamp.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+    let by = match (&record.user, &record.host) {
+        (Some(user), Some(host)) => Some(format!("{}@{}", user, host)),
+        (user, host) => user.clone().or(host.clone()),
+    };
+    if let Some(by) = by {
+        lines.push(field("by", by));
+    }
+    lines.push(field(
+        "cwd",
+        record
+            .cwd
+            .as_ref()
+            .map(|c| c.display().to_string())
+            .unwrap_or_else(|| "? (metadata unreadable)".to_string()),
+    ));
+    lines.push(field("targets", record.targets.join(", ")));
+    let mut size = format!("{} on disk", listing::format_size(record.size));
+    if let Some(original) = record.original_size {
+        size += &format!(", {} archived", listing::format_size(original));
+    }
+    if let Some(files) = record.files {
+        size += &format!(", {} files", files);
+    }
+    lines.push(field("size", size));
+    let pinned = match (crate::is_pinned(&record.path), record.pinned) {
+        (true, _) => "yes, by hand",
+        (false, true) => "yes, a later incremental bkup builds on it",
+        (false, false) => "no",
+    };
+    lines.push(field("pinned", pinned.to_string()));
+    if let Some(matched) = &record.matched {
+        lines.push(field("filter", listing::explanation(matched)));
+    }
+    if let Some(contents) = item.contents.as_deref().filter(|c| !c.trim().is_empty()) {
+        lines.push(Line::default());
+        lines.extend(contents.lines().map(|line| Line::from(line.to_string())));
+    }
+    lines
+}
+
+/// The browser's surroundings: where bundles live and how to touch them.
+struct Browser {
+    spaces: Vec<(Space, PathBuf)>,
+    sudo: bool,
+    scope: Scope,
+}
+
+impl Browser {
+    /// Every bundle in scope, newest first.
+    fn load(&self) -> Result<Vec<Item>> {
+        let mut items = Vec::new();
+        for (space, path) in &self.spaces {
+            items.extend(
+                index::bundles(path)?
+                    .into_iter()
+                    .filter(|bundle| self.scope.owns(bundle.record.uid))
+                    .map(|bundle| Item {
+                        space: *space,
+                        record: bundle.record,
+                        contents: bundle.contents,
+                    }),
+            );
+        }
+        items.sort_by_key(|item| std::cmp::Reverse(crate::bundle_name::chronological(&item.record.path)));
+        Ok(items)
+    }
+
+    /// Do `task`, returning what to report, and whether the bundles need
+    /// loading again.
+    fn run(&self, app: &mut App, task: Task) -> Result<(String, bool)> {
+        match task {
+            Task::Restore { bundle, remove } => {
+                if remove {
+                    crate::recover(&[], std::slice::from_ref(&bundle), self.sudo, self.scope)?;
+                } else {
+                    crate::restore_chain(&bundle, self.sudo)?;
+                }
+                Ok((format!("Restored {}", name(&bundle)), remove))
+            }
+            Task::RestoreFiles { bundle, cwd, paths } => {
+                let written = contents::extract(&bundle, &paths, &cwd)?;
+                if let Some(files) = app.files.as_mut() {
+                    files.marked.clear();
+                }
+                Ok((format!("Restored {} entries under {}", written, cwd.display()), false))
+            }
+            Task::TogglePin(bundle) => {
+                let pinned = !crate::is_pinned(&bundle);
+                crate::set_pin(&bundle, pinned)?;
+                let verb = if pinned { "Pinned" } else { "Unpinned" };
+                Ok((format!("{} {}", verb, name(&bundle)), true))
+            }
+            Task::Verify(bundle) => {
+                crate::verify_bundle(&bundle)?;
+                Ok((format!("{} is intact", name(&bundle)), false))
+            }
+            Task::Purge(bundle) => {
+                if crate::is_pinned(&bundle) {
+                    eyre::bail!("{} is pinned; unpin it first", name(&bundle));
+                }
+                if crate::has_children(&bundle) {
+                    eyre::bail!("later incremental bkups build on {}", name(&bundle));
+                }
+                crate::remove_directory_with_sudo(&bundle, self.sudo)?;
+                index::remove(&bundle);
+                if let Some(space) = bundle.parent() {
+                    crate::collect_chunks(space);
+                }
+                Ok((format!("Purged {}", name(&bundle)), true))
+            }
+            Task::PurgeFiles { bundle, paths } => {
+                let purged = crate::purge::purge_files(&bundle, &paths, self.sudo)?;
+                if let Some(space) = bundle.parent() {
+                    crate::collect_chunks(space);
+                }
+                let entries = diff::Snapshot::archived(&bundle)?.entries;
+                if let Some(files) = app.files.as_mut() {
+                    let last = entries.len().saturating_sub(1);
+                    files
+                        .state
+                        .select(files.state.selected().map(|current| current.min(last)));
+                    files.entries = entries;
+                    files.marked.clear();
+                }
+                Ok((format!("Purged {} entries from {}", purged, name(&bundle)), true))
+            }
+            Task::ListFiles(bundle) => {
+                let entries = diff::Snapshot::archived(&bundle)?.entries;
+                if entries.is_empty() {
+                    return Err(eyre!("{} holds no files", name(&bundle)));
+                }
+                app.files = Some(Files {
+                    bundle,
+                    entries,
+                    state: ListState::default().with_selected(Some(0)),
+                    marked: BTreeSet::new(),
+                });
+                app.focus = Focus::Files;
+                Ok((String::new(), false))
+            }
+        }
+    }
+}
+
+/// Hand the terminal back to the shell while `run` runs, for prompts and
+/// the output of the commands it starts.
+fn with_terminal<T>(terminal: &mut DefaultTerminal, run: impl FnOnce() -> T) -> Result<T> {
+    terminal::disable_raw_mode()?;
+    execute!(io::stdout(), terminal::LeaveAlternateScreen)?;
+    let result = run();
+    execute!(io::stdout(), terminal::EnterAlternateScreen)?;
+    terminal::enable_raw_mode()?;
+    terminal.clear()?;
+    Ok(result)
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, browser: &Browser, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let task = match app.handle_key(key) {
+            Step::Continue => continue,
+            Step::Quit => return Ok(()),
+            Step::Run(task) => task,
+        };
+
+        app.status = Some((task.describe(), false));
+        terminal.draw(|frame| app.draw(frame))?;
+        let outcome = if task.needs_terminal(browser.sudo) {
+            with_terminal(terminal, || browser.run(app, task))?
+        } else {
+            browser.run(app, task)
+        };
+        app.status = match outcome {
+            Ok((report, reload)) => {
+                if reload {
+                    app.set_items(browser.load()?);
+                }
+                (!report.is_empty()).then_some((report, false))
+            }
+            Err(error) => Some((format!("{:#}", error), true)),
+        };
+    }
+}
+
+/// `rkvr browse`: browse the bundles of `spaces` within `scope` until the
+/// user quits.
+pub fn browse(spaces: Vec<(Space, PathBuf)>, sudo: bool, threshold: i64, scope: Scope) -> Result<()> {
+    let browser = Browser { spaces, sudo, scope };
+    let mut app = App::new(browser.load()?, threshold);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &browser, &mut app);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn item(space: Space, name: &str, target: &str, contents: &str) -> Item {
+        Item {
+            space,
+            record: BundleRecord {
+                name: name.into(),
+                path: PathBuf::from("/var/tmp").join(space.label()).join(name),
+                timestamp: None,
+                user: Some("me".into()),
+                uid: Some(1000),
+                host: None,
+                cwd: Some(PathBuf::from("/home/me")),
+                targets: vec![target.into()],
+                size: 4096,
+                original_size: None,
+                files: Some(2),
+                pinned: false,
+                score: None,
+                matched: None,
+            },
+            contents: Some(contents.into()),
+        }
+    }
+
+    fn app() -> App {
+        App::new(
+            vec![
+                item(Space::Rmrf, "2026-06-03-120000-000", "notes", "notes\n└── todo.txt\n"),
+                item(
+                    Space::Bkup,
+                    "2026-06-02-120000-000",
+                    "website",
+                    "website\n└── index.html\n",
+                ),
+                item(Space::Rmrf, "2026-06-01-120000-000", "build", "build\n"),
+            ],
+            70,
+        )
+    }
+
+    fn press(app: &mut App, keys: &str) -> Step {
+        let mut step = Step::Continue;
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            step = app.handle_key(KeyEvent::from(code));
+        }
+        step
+    }
+
+    fn selected(app: &App) -> &str {
+        &app.selected().unwrap().record.name
+    }
+
+    #[test]
+    fn test_filter_narrows_as_typed() {
+        let mut app = app();
+        assert_eq!(app.visible.len(), 3);
+        press(&mut app, "j");
+        assert_eq!(selected(&app), "2026-06-02-120000-000");
+
+        // Letters go to the filter while it has focus, not to commands.
+        press(&mut app, "/todo");
+        assert_eq!(app.visible.len(), 1);
+        assert_eq!(selected(&app), "2026-06-03-120000-000");
+        assert_eq!(
+            app.selected().unwrap().record.matched.as_ref().unwrap().text,
+            "todo.txt"
+        );
+        press(&mut app, "\n");
+        assert_eq!(press(&mut app, "/zzz\n"), Step::Continue);
+        assert!(app.selected().is_none());
+        assert_eq!(press(&mut app, "r"), Step::Continue, "nothing to restore");
+
+        // Esc clears the filter, and quits once it is clear.
+        assert_eq!(press(&mut app, "\x1b"), Step::Continue);
+        assert_eq!(app.visible.len(), 3);
+        assert_eq!(press(&mut app, "\x1b"), Step::Quit);
+    }
+
+    #[test]
+    fn test_keys_ask_for_tasks() {
+        let mut app = app();
+        let rmrf = app.selected().unwrap().record.path.clone();
+        assert_eq!(
+            press(&mut app, "r"),
+            Step::Run(Task::Restore {
+                bundle: rmrf.clone(),
+                remove: true
+            })
+        );
+        press(&mut app, "j");
+        let bkup = app.selected().unwrap().record.path.clone();
+        assert_eq!(
+            press(&mut app, "r"),
+            Step::Run(Task::Restore {
+                bundle: bkup.clone(),
+                remove: false
+            }),
+            "bkup bundles are kept"
+        );
+        assert_eq!(press(&mut app, "p"), Step::Run(Task::TogglePin(bkup.clone())));
+        assert_eq!(press(&mut app, "v"), Step::Run(Task::Verify(bkup.clone())));
+
+        // Purging asks first.
+        assert_eq!(press(&mut app, "d"), Step::Continue);
+        assert_eq!(press(&mut app, "n"), Step::Continue);
+        assert_eq!(app.status.as_ref().unwrap().0, "Purge cancelled");
+        assert_eq!(press(&mut app, "dy"), Step::Run(Task::Purge(bkup.clone())));
+
+        // Files are read on first opening, then marked and restored.
+        assert_eq!(press(&mut app, "\n"), Step::Run(Task::ListFiles(bkup.clone())));
+        let entry = |path: &str, kind| FileEntry {
+            path: path.into(),
+            kind,
+            size: 0,
+            mtime: 0,
+            mode: 0o644,
+            uid: 1000,
+            gid: 1000,
+            hash: None,
+            link: None,
+        };
+        app.files = Some(Files {
+            bundle: bkup.clone(),
+            entries: vec![
+                entry("website", EntryKind::Dir),
+                entry("website/index.html", EntryKind::File),
+            ],
+            state: ListState::default().with_selected(Some(0)),
+            marked: BTreeSet::new(),
+        });
+        app.focus = Focus::Files;
+        assert_eq!(
+            press(&mut app, "r"),
+            Step::Run(Task::RestoreFiles {
+                bundle: bkup.clone(),
+                cwd: PathBuf::from("/home/me"),
+                paths: vec!["website".into()],
+            }),
+            "without marks, the entry under the cursor"
+        );
+        // Space marks the entry under the cursor.
+        assert_eq!(
+            press(&mut app, "j r"),
+            Step::Run(Task::RestoreFiles {
+                bundle: bkup.clone(),
+                cwd: PathBuf::from("/home/me"),
+                paths: vec!["website/index.html".into()],
+            })
+        );
+        assert_eq!(
+            press(&mut app, "v"),
+            Step::Run(Task::Verify(bkup.clone())),
+            "acts on the bundle"
+        );
+        assert_eq!(press(&mut app, "d"), Step::Continue, "asks first");
+        assert_eq!(app.focus, Focus::ConfirmPurgeFiles);
+        assert_eq!(press(&mut app, "n"), Step::Continue);
+        assert_eq!(app.focus, Focus::Files);
+        assert_eq!(
+            press(&mut app, "dy"),
+            Step::Run(Task::PurgeFiles {
+                bundle: bkup.clone(),
+                paths: vec!["website/index.html".into()],
+            }),
+            "purges the files, never the whole bundle"
+        );
+        assert_eq!(app.focus, Focus::Files);
+        press(&mut app, "\x1b");
+        assert_eq!(app.focus, Focus::Bundles);
+    }
+
+    #[test]
+    fn test_draw_lists_and_previews() {
+        let mut app = app();
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(120)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>() + "\n")
+            .collect();
+        assert!(screen.contains("3 of 3 bundles"), "{}", screen);
+        assert!(screen.contains("bkup 2026-06-02-120000-000 website"), "{}", screen);
+        assert!(screen.contains("targets notes"), "{}", screen);
+        assert!(screen.contains("└── todo.txt"), "{}", screen);
+        assert!(screen.contains("/ filter"), "{}", screen);
+    }
+}
@@ -0,0 +1,154 @@
+//! Bundle directory names, which encode when a bundle was created. rkvr has
+//! named bundles three ways over its lifetime:
+//!
+//! - EPOCH: digits counting seconds, millis, micros or nanos (10, 13, 16 or
+//!   19 digits) since the epoch.
+//! - ISO-T: `YYYY-MM-DDTHH-MM-SS-NNN`.
+//! - current: `YYYY-MM-DD-HHMMSS-NNN` (see [`crate::next_bundle_dir`]).
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scheme {
+    Epoch,
+    IsoT,
+    Current,
+}
+
+/// A parsed bundle name. Orders by creation time, then by the index that
+/// tells apart bundles created in the same second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BundleName {
+    /// Local time the bundle was created.
+    pub created: NaiveDateTime,
+    pub index: usize,
+    pub scheme: Scheme,
+}
+
+impl BundleName {
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some((time, index)) = name.rsplit_once('-') {
+            let index = (index.len() >= 3 && index.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| index.parse().ok())
+                .flatten();
+            if let Some(index) = index {
+                for (format, scheme) in [
+                    (crate::BUNDLE_TIMESTAMP, Scheme::Current),
+                    ("%Y-%m-%dT%H-%M-%S", Scheme::IsoT),
+                ] {
+                    if let Ok(created) = NaiveDateTime::parse_from_str(time, format) {
+                        return Some(Self { created, index, scheme });
+                    }
+                }
+            }
+        }
+
+        if name.bytes().all(|b| b.is_ascii_digit()) {
+            let scale = name.len().checked_sub(10).filter(|s| s % 3 == 0 && *s <= 9)?;
+            let count: i64 = name.parse().ok()?;
+            let per_second = 10i64.pow(scale as u32);
+            let nanos = (count % per_second) * (1_000_000_000 / per_second);
+            let time = DateTime::from_timestamp(count / per_second, nanos as u32)?;
+            return Some(Self {
+                created: time.with_timezone(&Local).naive_local(),
+                index: 0,
+                scheme: Scheme::Epoch,
+            });
+        }
+
+        None
+    }
+
+    /// The parsed name of the bundle directory at `path`.
+    pub fn of(path: &Path) -> Option<Self> {
+        Self::parse(path.file_name()?.to_str()?)
+    }
+
+    pub fn created_time(&self) -> Option<SystemTime> {
+        Local
+            .from_local_datetime(&self.created)
+            .earliest()
+            .map(SystemTime::from)
+    }
+
+    /// A readable creation time, for names that are not already one.
+    pub fn display_date(&self) -> Option<String> {
+        (self.scheme != Scheme::Current).then(|| self.created.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+}
+
+/// Sort key putting bundle directories in creation order. Names in no known
+/// scheme come first, by name.
+pub fn chronological(path: &Path) -> (Option<BundleName>, OsString) {
+    (
+        BundleName::of(path),
+        path.file_name().unwrap_or_default().to_os_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_each_scheme() {
+        let current = BundleName::parse("2024-03-05-070809-002").unwrap();
+        assert_eq!(current.scheme, Scheme::Current);
+        assert_eq!(current.index, 2);
+        assert_eq!(current.display_date(), None);
+
+        let iso = BundleName::parse("2024-03-05T07-08-09-001").unwrap();
+        assert_eq!(iso.scheme, Scheme::IsoT);
+        assert_eq!(iso.created, current.created);
+        assert_eq!(iso.display_date().as_deref(), Some("2024-03-05 07:08:09"));
+
+        let seconds = BundleName::parse("1700000000").unwrap();
+        assert_eq!(seconds.scheme, Scheme::Epoch);
+        assert_eq!(BundleName::parse("1700000000000").unwrap().created, seconds.created);
+        assert_eq!(BundleName::parse("1700000000000000").unwrap().created, seconds.created);
+        assert_eq!(
+            BundleName::parse("1700000000500000000").unwrap().created - seconds.created,
+            chrono::Duration::milliseconds(500)
+        );
+        assert_eq!(
+            seconds.created_time().unwrap(),
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)
+        );
+
+        for name in ["12345", "notes", "2024-03-05-070809", ".chunks"] {
+            assert_eq!(BundleName::parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_chronological_order_across_schemes() {
+        let mut bundles: Vec<PathBuf> = [
+            "2024-03-05-070809-000",
+            "1700000000",
+            "2024-03-05T07-08-09-001",
+            "stray",
+        ]
+        .iter()
+        .map(|n| Path::new("/var/tmp/rmrf").join(n))
+        .collect();
+        bundles.sort_by_key(|b| chronological(b));
+
+        let names: Vec<_> = bundles
+            .iter()
+            .map(|b| b.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "stray",
+                "1700000000",
+                "2024-03-05-070809-000",
+                "2024-03-05T07-08-09-001"
+            ]
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use crate::compress::Codec;
+use crate::listing::{ListFilters, ListFormat, ListSort};
+use crate::matching::MatchMode;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -16,12 +19,73 @@ fn get_log_file_path_for_help() -> String {
     version = env!("GIT_DESCRIBE"),
     author = "Scott A. Idler <scott.a.idler@gmail.com>",
     arg_required_else_help = true,
-    after_help = format!("Logs are written to: {}", get_log_file_path_for_help())
+    after_help = format!(
+        "Exit status is 0 on success, 1 on failure and 2 when only some targets were processed.\n\
+         Logs are written to: {}",
+        get_log_file_path_for_help()
+    )
 )]
 pub struct Cli {
     #[arg(short, long, help = "Path to config file")]
     pub config: Option<PathBuf>,
 
+    #[arg(
+        long,
+        global = true,
+        overrides_with = "keep_going",
+        help = "Stop at the first target that cannot be archived or removed"
+    )]
+    pub fail_fast: bool,
+
+    #[arg(
+        long,
+        global = true,
+        overrides_with = "fail_fast",
+        help = "Process every target that can be handled and report the rest [default]"
+    )]
+    pub keep_going: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Follow symlinks: archive and remove what they point to instead of the links"
+    )]
+    pub follow: bool,
+
+    #[arg(long, global = true, value_enum, help = "Compression codec for new bundles")]
+    pub compress: Option<Codec>,
+
+    #[arg(long, global = true, help = "Compression level for new bundles")]
+    pub level: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Store only what changed since the previous bundle of each directory"
+    )]
+    pub incremental: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Encrypt new bundles (key from --keyfile, the config, or a passphrase)"
+    )]
+    pub encrypt: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Key file for encrypting new bundles and opening encrypted ones"
+    )]
+    pub keyfile: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Include every user's bundles in listing, recovery, verify and cleanup (root only)"
+    )]
+    pub all_users: bool,
+
     #[arg(name = "targets")]
     pub targets: Vec<String>,
 
@@ -35,6 +99,82 @@ pub struct Args {
     pub targets: Vec<String>,
 }
 
+#[derive(Parser, Clone, Debug)]
+pub struct ListArgs {
+    #[arg(name = "targets")]
+    pub targets: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t, help = "Output format")]
+    pub format: ListFormat,
+
+    #[arg(long, help = "Show each bundle's metadata and file tree instead of one line")]
+    pub long: bool,
+
+    #[arg(long, value_enum, default_value_t, help = "Order of listed bundles")]
+    pub sort: ListSort,
+
+    #[arg(long, value_name = "N", help = "List at most N bundles")]
+    pub limit: Option<usize>,
+
+    #[arg(
+        long = "match",
+        value_enum,
+        help = "How patterns match the name, targets, cwd and file tree [default: fuzzy, or `match` in rmrf.cfg]"
+    )]
+    pub match_mode: Option<MatchMode>,
+
+    #[arg(
+        long,
+        value_name = "SCORE",
+        value_parser = clap::value_parser!(i64).range(0..=100),
+        help = "Lowest fuzzy score that counts as a match, as a percentage of a perfect match [default: `threshold` in rmrf.cfg]"
+    )]
+    pub threshold: Option<i64>,
+
+    #[arg(long, help = "Show which field each bundle matched and its score")]
+    pub explain: bool,
+
+    #[command(flatten)]
+    pub filters: ListFilters,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct MigrateArgs {
+    #[arg(long, help = "Perform the migration instead of only reporting it")]
+    pub apply: bool,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct FindArgs {
+    #[arg(help = "Inner path to look for: a substring, or a shell pattern matching the whole path")]
+    pub pattern: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Only files with a line matching REGEX, printed with its number"
+    )]
+    pub grep: Option<regex::Regex>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct ShowArgs {
+    #[arg(help = "Bundle name, a unique prefix of one, or @N for the Nth newest")]
+    pub bundle: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct DiffArgs {
+    #[arg(help = "Bundle name, a unique prefix of one, or @N for the Nth newest")]
+    pub bundle: String,
+
+    #[arg(help = "A later bundle to compare with, instead of the files under the first's cwd")]
+    pub other: Option<String>,
+
+    #[arg(short, long, help = "Show a unified diff of each modified text file")]
+    pub patch: bool,
+}
+
 #[derive(Subcommand, Clone, Debug)]
 pub enum Action {
     #[command(about = "bkup files")]
@@ -44,11 +184,46 @@ pub enum Action {
     #[command(about = "recover rmrf|bkup files")]
     Rcvr(Args),
     #[command(about = "list bkup files")]
-    LsBkup(Args),
+    LsBkup(ListArgs),
     #[command(about = "list rmrf files")]
-    LsRmrf(Args),
+    LsRmrf(ListArgs),
     #[command(about = "bkup files and rmrf the local files")]
     BkupRmrf(Args),
+    #[command(about = "verify that bundles are intact and readable [default: all]")]
+    Verify(Args),
+    #[command(about = "upgrade bundles written by older rkvr releases [default: dry-run]")]
+    Migrate(MigrateArgs),
+    #[command(about = "find files inside rmrf and bkup bundles by path or contents")]
+    Find(FindArgs),
+    #[command(about = "show a bundle's provenance, payloads, files and verification status")]
+    Show(ShowArgs),
+    #[command(about = "compare a bundle with the files now under its cwd, or with another bundle")]
+    Diff(DiffArgs),
+    #[command(about = "browse, filter and act on rmrf and bkup bundles interactively")]
+    Browse,
+    #[command(about = "rebuild the index that listing and find read bundles from")]
+    Reindex,
+}
+
+impl Action {
+    /// The subcommand name, as recorded in bundle provenance.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Bkup(_) => "bkup",
+            Action::Rmrf(_) => "rmrf",
+            Action::Rcvr(_) => "rcvr",
+            Action::LsBkup(_) => "ls-bkup",
+            Action::LsRmrf(_) => "ls-rmrf",
+            Action::BkupRmrf(_) => "bkup-rmrf",
+            Action::Verify(_) => "verify",
+            Action::Migrate(_) => "migrate",
+            Action::Find(_) => "find",
+            Action::Show(_) => "show",
+            Action::Diff(_) => "diff",
+            Action::Browse => "browse",
+            Action::Reindex => "reindex",
+        }
+    }
 }
 
 impl Default for Action {
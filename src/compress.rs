@@ -0,0 +1,195 @@
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+/// Compression applied to bundle tarballs. Every codec other than `None` is
+/// run by tar through `--use-compress-program`, so the matching binary must be
+/// on `PATH`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    Gzip,
+    Zstd,
+    Xz,
+    Lz4,
+    None,
+}
+
+impl Codec {
+    /// File extension for a tarball compressed with this codec.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "tar.gz",
+            Codec::Zstd => "tar.zst",
+            Codec::Xz => "tar.xz",
+            Codec::Lz4 => "tar.lz4",
+            Codec::None => "tar",
+        }
+    }
+
+    fn program(self) -> Option<&'static str> {
+        match self {
+            Codec::Gzip => Some("gzip"),
+            Codec::Zstd => Some("zstd"),
+            Codec::Xz => Some("xz"),
+            Codec::Lz4 => Some("lz4"),
+            Codec::None => None,
+        }
+    }
+
+    fn levels(self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            Codec::Gzip => 1..=9,
+            Codec::Zstd => 1..=19,
+            Codec::Xz => 0..=9,
+            Codec::Lz4 => 1..=12,
+            Codec::None => 0..=0,
+        }
+    }
+
+    /// Identify the codec of an existing tarball from its magic bytes. A file
+    /// that matches none of the known signatures is treated as plain tar.
+    pub fn detect(path: &Path) -> Result<Codec> {
+        let mut magic = [0u8; 6];
+        let mut file = File::open(path)?;
+        let len = file.read(&mut magic)?;
+        Ok(Self::from_magic(&magic[..len]))
+    }
+
+    fn from_magic(magic: &[u8]) -> Codec {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Codec::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Codec::Zstd
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Codec::Xz
+        } else if magic.starts_with(&[0x04, 0x22, 0x4d, 0x18]) {
+            Codec::Lz4
+        } else {
+            Codec::None
+        }
+    }
+
+    /// Arguments that make tar decompress this codec when extracting.
+    pub fn tar_extract_args(self) -> Vec<String> {
+        self.program()
+            .map(|prog| vec![format!("--use-compress-program={prog}")])
+            .unwrap_or_default()
+    }
+
+    /// A filter that compresses stdin to stdout with this codec at its
+    /// default level, for writing tarballs without tar.
+    pub fn compress_command(self) -> Option<Command> {
+        self.program().map(|prog| {
+            let mut command = Command::new(prog);
+            command.arg("-c");
+            command
+        })
+    }
+
+    /// A filter that decompresses this codec from stdin to stdout, for
+    /// reading tarballs without tar.
+    pub fn decompress_command(self) -> Option<Command> {
+        self.program().map(|prog| {
+            let mut command = Command::new(prog);
+            command.arg("-dc");
+            command
+        })
+    }
+}
+
+/// A codec plus an optional level; `None` uses the codec's own default.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Compression {
+    #[serde(default)]
+    pub codec: Codec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+}
+
+impl Compression {
+    /// Fail unless the level is one the codec accepts.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(level) = self.level.filter(|_| self.codec.program().is_some()) {
+            let levels = self.codec.levels();
+            if !levels.contains(&level) {
+                eyre::bail!(
+                    "{:?} compression level must be between {} and {}, got {}",
+                    self.codec,
+                    levels.start(),
+                    levels.end(),
+                    level
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Arguments that make tar compress with this codec and level when creating.
+    pub fn tar_create_args(&self) -> Result<Vec<String>> {
+        let Some(prog) = self.codec.program() else {
+            return Ok(vec![]);
+        };
+        self.validate()?;
+        let mut command = prog.to_string();
+        if let Some(level) = self.level {
+            command += &format!(" -{level}");
+        }
+        if matches!(self.codec, Codec::Zstd | Codec::Xz) {
+            command += " -T0";
+        }
+        Ok(vec![format!("--use-compress-program={command}")])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect_codec_from_magic() {
+        let temp_dir = TempDir::new().unwrap();
+        let cases: &[(&[u8], Codec)] = &[
+            (&[0x1f, 0x8b, 0x08, 0x00], Codec::Gzip),
+            (&[0x28, 0xb5, 0x2f, 0xfd, 0x00], Codec::Zstd),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Codec::Xz),
+            (&[0x04, 0x22, 0x4d, 0x18, 0x64], Codec::Lz4),
+            (b"file.txt\0\0\0", Codec::None),
+            (b"", Codec::None),
+        ];
+        for (i, (bytes, expected)) in cases.iter().enumerate() {
+            let path = temp_dir.path().join(format!("bundle-{i}"));
+            std::fs::write(&path, bytes).unwrap();
+            assert_eq!(Codec::detect(&path).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_tar_create_args() {
+        let zstd = Compression {
+            codec: Codec::Zstd,
+            level: Some(19),
+        };
+        assert_eq!(
+            zstd.tar_create_args().unwrap(),
+            vec!["--use-compress-program=zstd -19 -T0"]
+        );
+
+        let none = Compression {
+            codec: Codec::None,
+            level: None,
+        };
+        assert!(none.tar_create_args().unwrap().is_empty());
+
+        let bad = Compression {
+            codec: Codec::Gzip,
+            level: Some(12),
+        };
+        assert!(bad.tar_create_args().is_err(), "Out-of-range level should be rejected");
+    }
+}
@@ -1,3 +1,7 @@
+use crate::compress::{Codec, Compression};
+use crate::crypt::EncryptionConfig;
+use crate::space::PermissionPolicy;
+use crate::store::StoreKind;
 use eyre::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -40,12 +44,59 @@ pub struct Config {
 
     #[serde(default = "default_archive_location")]
     pub archive_location: String,
+
+    /// What to do when an existing archive space is accessible to others.
+    #[serde(default)]
+    pub space_permissions: PermissionPolicy,
+
+    /// Keep each user's bundles in a private `<space>/<uid>/` subtree of a
+    /// shared space. Without it the space belongs to whoever created it, and
+    /// nobody else can use it.
+    #[serde(default = "default_per_user_spaces")]
+    pub per_user_spaces: bool,
+
+    /// Tarball compression for every space unless overridden below.
+    #[serde(default)]
+    pub compression: Compression,
+
+    /// Encrypt new bundles in every space; `{}` uses a passphrase.
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
+
+    #[serde(default)]
+    pub rmrf: SpaceConfig,
+
+    #[serde(default)]
+    pub bkup: SpaceConfig,
+}
+
+/// Settings that can differ between the rmrf and bkup spaces.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SpaceConfig {
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// `chunked` stores payloads deduplicated in a content-addressed store
+    /// shared by the space's bundles; unreferenced chunks are removed by cleanup.
+    #[serde(default)]
+    pub store: StoreKind,
+
+    /// Store each directory as the changes since its previous bundle.
+    #[serde(default)]
+    pub incremental: bool,
+
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
 }
 
 fn default_cleanup_days() -> usize {
     30
 }
 
+fn default_per_user_spaces() -> bool {
+    true
+}
+
 fn default_archive_location() -> String {
     xdg_data_dir()
         .map(|d| d.join("rkvr").join("archive"))
@@ -59,11 +110,56 @@ impl Default for Config {
             cleanup_days: default_cleanup_days(),
             auto_cleanup: false,
             archive_location: default_archive_location(),
+            space_permissions: PermissionPolicy::default(),
+            per_user_spaces: default_per_user_spaces(),
+            compression: Compression::default(),
+            encryption: None,
+            rmrf: SpaceConfig::default(),
+            bkup: SpaceConfig::default(),
         }
     }
 }
 
 impl Config {
+    /// The compression to use for `space`, with `codec`/`level` from the
+    /// command line taking precedence over the space and global settings.
+    /// A configured level only carries over when the codec is unchanged, since
+    /// levels are not portable between codecs. Fails on a level the codec
+    /// does not accept.
+    pub fn compression_for(
+        &self,
+        space: &SpaceConfig,
+        codec: Option<Codec>,
+        level: Option<u32>,
+    ) -> Result<Compression> {
+        let configured = space.compression.unwrap_or(self.compression);
+        let codec = codec.unwrap_or(configured.codec);
+        let compression = Compression {
+            codec,
+            level: level.or(configured.level.filter(|_| codec == configured.codec)),
+        };
+        compression.validate()?;
+        Ok(compression)
+    }
+
+    /// How to encrypt new bundles in `space`, if at all. Encryption is on
+    /// when configured or asked for with `--encrypt`; `--keyfile` only
+    /// chooses the key.
+    pub fn encryption_for(
+        &self,
+        space: &SpaceConfig,
+        encrypt: bool,
+        keyfile: Option<PathBuf>,
+    ) -> Option<EncryptionConfig> {
+        let configured = space.encryption.as_ref().or(self.encryption.as_ref()).cloned();
+        match (configured, encrypt) {
+            (None, false) => None,
+            (configured, _) => Some(EncryptionConfig {
+                keyfile: keyfile.or(configured.and_then(|config| config.keyfile)),
+            }),
+        }
+    }
+
     pub fn load(config_path: Option<PathBuf>) -> Result<Self> {
         let config_file = match config_path {
             Some(path) => path,
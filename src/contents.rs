@@ -0,0 +1,490 @@
+//! What a bundle holds, read back from its payloads: tarballs (plain,
+//! sealed or in the chunk store) and loose copies. Payloads are streamed, so
+//! nothing is extracted to disk.
+
+use crate::compress::Codec;
+use crate::crypt::{self, BundleKey, Envelope};
+use crate::manifest::{EntryKind, FileEntry, FileManifest, FILE_MANIFEST};
+use crate::store::{ChunkManifest, ChunkStore, StoreKind};
+use eyre::{eyre, Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, PipeWriter, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+/// One file, directory or link inside a bundle.
+pub struct Entry<'a> {
+    /// The payload holding it: a tarball or loose copy in the bundle, or a
+    /// payload in the chunk store.
+    pub payload: &'a str,
+    /// Stat of the entry as archived. Its path is relative to the bundle's
+    /// `cwd`; tarballs record mtimes to the second and no hashes.
+    pub file: FileEntry,
+    /// Contents of a regular file; empty for other kinds.
+    pub contents: &'a mut dyn Read,
+}
+
+/// Call `visit` for every entry of every payload in `bundle`, payload by
+/// payload in the order recovery restores them.
+pub fn walk(bundle: &Path, visit: &mut dyn FnMut(&mut Entry) -> Result<()>) -> Result<()> {
+    let meta = crate::load_metadata(bundle)?;
+    let key = match Envelope::load(bundle)? {
+        Some(envelope) => Some(BundleKey::open(&envelope.key)?),
+        None => None,
+    };
+
+    if meta.store == StoreKind::Chunked {
+        let store = ChunkStore::for_bundle(bundle)?;
+        for payload in ChunkManifest::load(bundle)?.payloads {
+            tar_stream(
+                Codec::None,
+                |out| store.restore(&payload, out),
+                |stream| walk_tar(&payload.name, stream, visit),
+            )
+            .wrap_err_with(|| format!("payload {}", payload.name))?;
+        }
+    }
+
+    let mut names: Vec<String> = fs::read_dir(bundle)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| !crate::is_bookkeeping(name))
+        .collect();
+    names.sort();
+    for name in names {
+        let path = bundle.join(&name);
+        if meta.targets.contains(&name) {
+            walk_loose(&name, &path, visit)?;
+            continue;
+        }
+        let codec = match key {
+            Some(_) => meta.compression,
+            None => Codec::detect(&path).unwrap_or(meta.compression),
+        };
+        tar_stream(
+            codec,
+            |out| {
+                let file = BufReader::new(File::open(&path)?);
+                match &key {
+                    Some(key) => key.decrypt(file, out),
+                    None => Ok(io::copy(&mut { file }, out).map(|_| ())?),
+                }
+            },
+            |stream| walk_tar(&name, stream, visit),
+        )
+        .wrap_err_with(|| format!("payload {}", name))?;
+    }
+    Ok(())
+}
+
+/// Write the entries of `bundle` at `paths`, and everything below them,
+/// under `dest`, replacing what is there. Returns how many were written;
+/// hard links and special files are left out.
+///
+/// Incremental bundles only hold what changed, so the chain is read from
+/// its full bkup on, later versions winning, and only what the bundle's
+/// file manifest lists is written: files deleted since a parent stay out.
+///
+/// Tar members are not trusted to stay under `dest`: absolute members, ones
+/// with `..`, and ones below a symlink this extraction wrote are refused.
+pub fn extract(bundle: &Path, paths: &[String], dest: &Path) -> Result<usize> {
+    let listed: Option<HashSet<String>> = if crypt::bundle_file_exists(bundle, FILE_MANIFEST) {
+        Some(
+            FileManifest::load(bundle)?
+                .entries
+                .into_iter()
+                .map(|e| e.path)
+                .collect(),
+        )
+    } else {
+        None
+    };
+    let mut written = HashSet::new();
+    let mut links: Vec<PathBuf> = Vec::new();
+    for (dir, _) in crate::bundle_chain(bundle)? {
+        walk(&dir, &mut |entry| {
+            let listed = listed.as_ref().is_none_or(|listed| listed.contains(&entry.file.path));
+            if !listed || !under_any(&entry.file.path, paths) {
+                return Ok(());
+            }
+            let path = PathBuf::from(&entry.file.path);
+            if path
+                .components()
+                .any(|c| matches!(c, Component::RootDir | Component::Prefix(_) | Component::ParentDir))
+            {
+                eyre::bail!(
+                    "refusing to restore {}: it leads outside {}",
+                    path.display(),
+                    dest.display()
+                );
+            }
+            if let Some(link) = links.iter().find(|link| path.starts_with(link) && path != **link) {
+                eyre::bail!(
+                    "refusing to restore {}: it lies below the symlink {} restored before it",
+                    path.display(),
+                    link.display()
+                );
+            }
+
+            let target = dest.join(&path);
+            if write_entry(entry, &target).wrap_err_with(|| format!("restoring {}", target.display()))? {
+                written.insert(entry.file.path.clone());
+                links.retain(|link| *link != path);
+                if entry.file.kind == EntryKind::Symlink {
+                    links.push(path);
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(written.len())
+}
+
+/// Whether `path` is one of `paths` or lies below one of them.
+pub fn under_any(path: &str, paths: &[String]) -> bool {
+    paths.iter().any(|p| {
+        path.strip_prefix(p.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Put `entry` at `target`, with its mode and, for files, its mtime.
+/// Returns false for kinds that are not written.
+fn write_entry(entry: &mut Entry, target: &Path) -> Result<bool> {
+    let file = &entry.file;
+    if !matches!(file.kind, EntryKind::File | EntryKind::Dir | EntryKind::Symlink) {
+        return Ok(false);
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::symlink_metadata(target) {
+        Ok(existing) if existing.is_dir() && file.kind != EntryKind::Dir => {
+            eyre::bail!("a directory is in the way")
+        }
+        Ok(existing) if !existing.is_dir() => fs::remove_file(target)?,
+        _ => {}
+    }
+
+    match file.kind {
+        EntryKind::Dir => {
+            fs::create_dir_all(target)?;
+            fs::set_permissions(target, fs::Permissions::from_mode(file.mode))?;
+        }
+        EntryKind::Symlink => {
+            let link = file.link.as_ref().ok_or_else(|| eyre!("symlink without a target"))?;
+            std::os::unix::fs::symlink(link, target)?;
+        }
+        _ => {
+            let mut out = File::create(target)?;
+            io::copy(&mut entry.contents, &mut out)?;
+            out.set_permissions(fs::Permissions::from_mode(file.mode))?;
+            if let Ok(nanos) = u64::try_from(file.mtime) {
+                out.set_modified(SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos))?;
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Feed a tar stream compressed with `codec` from `produce`, on a thread of
+/// its own, and hand it to `consume` decompressed.
+pub fn tar_stream<T>(
+    codec: Codec,
+    produce: impl FnOnce(&mut PipeWriter) -> Result<()> + Send,
+    consume: impl FnOnce(&mut dyn Read) -> Result<T>,
+) -> Result<T> {
+    let (mut reader, mut writer) = io::pipe()?;
+    std::thread::scope(|scope| {
+        let producer = scope.spawn(move || produce(&mut writer));
+        let consumed = match codec.decompress_command() {
+            None => {
+                let consumed = consume(&mut reader);
+                drop(reader);
+                consumed
+            }
+            Some(mut command) => {
+                let mut child = command
+                    .stdin(Stdio::from(reader))
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                // The command holds our copy of the pipe's read end; close it
+                // so the producer sees the pipe break if the child exits early.
+                drop(command);
+                let mut stdout = child
+                    .stdout
+                    .take()
+                    .ok_or_else(|| eyre!("decompressor produced no output stream"))?;
+                let consumed = consume(&mut stdout);
+                drop(stdout);
+                let output = child.wait_with_output()?;
+                if consumed.is_ok() && !output.status.success() {
+                    eyre::bail!(
+                        "decompression failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                consumed
+            }
+        };
+        let produced = producer.join().map_err(|_| eyre!("payload reader panicked"))?;
+        let consumed = consumed?;
+        produced?;
+        Ok(consumed)
+    })
+}
+
+/// Path of a tar member as rkvr records paths: no `./` and no trailing `/`.
+pub fn member_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    path.trim_end_matches('/').to_string()
+}
+
+fn walk_tar(payload: &str, stream: &mut dyn Read, visit: &mut dyn FnMut(&mut Entry) -> Result<()>) -> Result<()> {
+    let mut archive = tar::Archive::new(stream);
+    for member in archive.entries()? {
+        let mut member = member?;
+        let header = member.header();
+        let kind = match header.entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => EntryKind::File,
+            tar::EntryType::Directory => EntryKind::Dir,
+            tar::EntryType::Symlink => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        };
+        let file = FileEntry {
+            path: member_path(&member.path()?),
+            kind,
+            size: if kind == EntryKind::Dir { 0 } else { member.size() },
+            mtime: header.mtime()? as i64 * 1_000_000_000,
+            mode: header.mode()? & 0o7777,
+            uid: header.uid()? as u32,
+            gid: header.gid()? as u32,
+            hash: None,
+            link: if kind == EntryKind::Symlink {
+                member.link_name()?.map(|l| l.into_owned())
+            } else {
+                None
+            },
+        };
+        visit(&mut Entry {
+            payload,
+            file,
+            contents: &mut member,
+        })?;
+    }
+    // Drain the end-of-archive padding so the producer finishes cleanly.
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(())
+}
+
+/// A loose copy, `name` in the bundle, and everything below it.
+fn walk_loose(name: &str, root: &Path, visit: &mut dyn FnMut(&mut Entry) -> Result<()>) -> Result<()> {
+    let mut pending = vec![(root.to_path_buf(), name.to_string())];
+    while let Some((path, rel)) = pending.pop() {
+        let file = FileEntry::stat(&path, rel)?;
+        if file.kind == EntryKind::Dir {
+            let mut children: Vec<(PathBuf, String)> = fs::read_dir(&path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))?
+                .filter_map(|e| e.ok())
+                .map(|e| {
+                    let child = format!("{}/{}", file.path, e.file_name().to_string_lossy());
+                    (e.path(), child)
+                })
+                .collect();
+            children.sort_by(|a, b| b.1.cmp(&a.1));
+            pending.extend(children);
+        }
+        let mut contents: Box<dyn Read> = match file.kind {
+            EntryKind::File => Box::new(BufReader::new(
+                File::open(&path).wrap_err_with(|| format!("Failed to read {}", path.display()))?,
+            )),
+            _ => Box::new(io::empty()),
+        };
+        visit(&mut Entry {
+            payload: name,
+            file,
+            contents: &mut contents,
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// Every entry of `bundle` with the contents of its regular files.
+    fn entries(bundle: &Path) -> Vec<(String, EntryKind, String)> {
+        let mut found = Vec::new();
+        walk(bundle, &mut |entry| {
+            let mut contents = String::new();
+            entry.contents.read_to_string(&mut contents)?;
+            found.push((entry.file.path.clone(), entry.file.kind, contents));
+            Ok(())
+        })
+        .unwrap();
+        found
+    }
+
+    /// A bundle of `work/project` as a tarball and `notes` as a loose copy.
+    fn sample_bundle(root: &Path) -> PathBuf {
+        let cwd = root.join("work");
+        fs::create_dir_all(cwd.join("project/src")).unwrap();
+        fs::write(cwd.join("project/src/main.rs"), "fn main() {}\n").unwrap();
+        std::os::unix::fs::symlink("src/main.rs", cwd.join("project/entry")).unwrap();
+
+        let bundle = root.join("bundle");
+        fs::create_dir_all(bundle.join("notes")).unwrap();
+        fs::write(bundle.join("notes/todo.txt"), "water plants\n").unwrap();
+        let status = Command::new("tar")
+            .args(["-czf", bundle.join("work.tar.gz").to_str().unwrap(), "-C"])
+            .arg(&cwd)
+            .arg("project")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::write(
+            bundle.join("metadata.yml"),
+            format!("cwd: {}\ntargets:\n- notes\ncontents: ''\n", cwd.display()),
+        )
+        .unwrap();
+        bundle
+    }
+
+    #[test]
+    fn test_walk_reads_tarballs_and_loose_copies() {
+        let temp_dir = TempDir::new().unwrap();
+        let bundle = sample_bundle(temp_dir.path());
+
+        let mut found = entries(&bundle);
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        let summary: Vec<(&str, EntryKind, &str)> = found
+            .iter()
+            .map(|(path, kind, c)| (path.as_str(), *kind, c.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("notes", EntryKind::Dir, ""),
+                ("notes/todo.txt", EntryKind::File, "water plants\n"),
+                ("project", EntryKind::Dir, ""),
+                ("project/entry", EntryKind::Symlink, ""),
+                ("project/src", EntryKind::Dir, ""),
+                ("project/src/main.rs", EntryKind::File, "fn main() {}\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_writes_selected_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let bundle = sample_bundle(temp_dir.path());
+        let dest = temp_dir.path().join("restored");
+        fs::create_dir_all(dest.join("project")).unwrap();
+        std::os::unix::fs::symlink("/nonexistent", dest.join("project/entry")).unwrap();
+
+        let paths = [
+            "project/src".to_string(),
+            "project/entry".to_string(),
+            "notes/todo.txt".to_string(),
+        ];
+        assert_eq!(extract(&bundle, &paths, &dest).unwrap(), 4);
+        assert_eq!(
+            fs::read_to_string(dest.join("project/src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert_eq!(
+            fs::read_link(dest.join("project/entry")).unwrap(),
+            Path::new("src/main.rs")
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("notes/todo.txt")).unwrap(),
+            "water plants\n"
+        );
+
+        // A path only matches whole components.
+        assert_eq!(extract(&bundle, &["project/sr".to_string()], &dest).unwrap(), 0);
+    }
+
+    /// A bundle whose one payload, `crafted.tar`, holds `members` as given:
+    /// (path, link target for symlinks, contents for files).
+    fn crafted_bundle(root: &Path, members: &[(&str, Option<&str>, &str)]) -> PathBuf {
+        let bundle = root.join("bundle");
+        fs::create_dir_all(&bundle).unwrap();
+        let mut builder = tar::Builder::new(File::create(bundle.join("crafted.tar")).unwrap());
+        for (path, link, contents) in members {
+            // Raw names, since the tar crate refuses to write `..` itself.
+            let mut header = tar::Header::new_gnu();
+            let old = header.as_old_mut();
+            old.name[..path.len()].copy_from_slice(path.as_bytes());
+            match link {
+                Some(link) => {
+                    old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                }
+                None => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(contents.len() as u64);
+                }
+            }
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        fs::write(bundle.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: ''\n").unwrap();
+        bundle
+    }
+
+    #[test]
+    fn test_extract_stays_under_dest() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("dest");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&dest).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let absolute = format!("{}/absolute.txt", outside.display());
+        let bundle = crafted_bundle(
+            temp_dir.path(),
+            &[
+                ("../outside/climbed.txt", None, "climbed"),
+                (&absolute, None, "absolute"),
+                ("project/link", Some(outside.to_str().unwrap()), ""),
+                ("project/link/planted.txt", None, "planted"),
+                ("project/kept.txt", None, "kept"),
+            ],
+        );
+
+        for path in ["../outside/climbed.txt", absolute.as_str(), "project"] {
+            let error = extract(&bundle, &[path.to_string()], &dest).unwrap_err();
+            assert!(format!("{:#}", error).contains("refusing to restore"), "{:#}", error);
+        }
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0, "nothing escaped dest");
+
+        // Members beside them are fine.
+        assert_eq!(extract(&bundle, &["project/kept.txt".to_string()], &dest).unwrap(), 1);
+        assert_eq!(fs::read_to_string(dest.join("project/kept.txt")).unwrap(), "kept");
+    }
+
+    #[test]
+    fn test_walk_reports_corrupt_payload() {
+        let temp_dir = TempDir::new().unwrap();
+        let bundle = temp_dir.path();
+        fs::write(bundle.join("work.tar.gz"), b"\x1f\x8bnot really gzip").unwrap();
+        fs::write(bundle.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: ''\n").unwrap();
+
+        let error = walk(bundle, &mut |_| Ok(())).unwrap_err();
+        assert!(format!("{:#}", error).contains("payload work.tar.gz"), "{:#}", error);
+    }
+}
@@ -0,0 +1,493 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{KeyInit, OsRng};
+use chacha20poly1305::XChaCha20Poly1305;
+use eyre::{eyre, Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use zeroize::Zeroizing;
+
+/// Plaintext part of an encrypted bundle: how its key was derived, plus the
+/// links cleanup needs without asking for that key.
+pub const ENVELOPE_FILE: &str = "encryption.yml";
+
+/// Suffix of every encrypted file in a bundle.
+pub const ENCRYPTED_EXT: &str = "enc";
+
+/// Environment variable consulted for the passphrase before prompting.
+pub const PASSPHRASE_ENV: &str = "RKVR_PASSPHRASE";
+
+const MAGIC: &[u8; 8] = b"RKVRENC1";
+const NONCE_LEN: usize = 19;
+const SEGMENT: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+/// Key material, wiped from memory when dropped.
+type Key = Zeroizing<[u8; 32]>;
+type Secret = Zeroizing<Vec<u8>>;
+
+/// Where the key of new bundles comes from.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq)]
+pub struct EncryptionConfig {
+    /// Derive the key from this file's contents. Without it a passphrase is
+    /// read from `$RKVR_PASSPHRASE` or prompted for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<PathBuf>,
+}
+
+/// How to re-derive the key of an encrypted bundle. Holds no secret.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyRef {
+    pub cipher: String,
+    pub kdf: String,
+    pub salt: String,
+    /// Fingerprint of the derived key, to reject a wrong passphrase early.
+    pub key_id: String,
+    /// Key file used at bkup time; absent for passphrase-derived keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub key: KeyRef,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Uid the bundle belongs to, so scoping works without the key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<u32>,
+}
+
+impl Envelope {
+    /// The envelope of `bundle`, or `None` if it is not encrypted.
+    pub fn load(bundle: &Path) -> Result<Option<Self>> {
+        let path = bundle.join(ENVELOPE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path).wrap_err_with(|| format!("reading {}", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .map(Some)
+            .wrap_err_with(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, bundle: &Path) -> Result<()> {
+        let yaml = serde_yaml::to_string(self).wrap_err("Failed to serialize encryption envelope")?;
+        fs::write(bundle.join(ENVELOPE_FILE), yaml).wrap_err("Failed to write encryption envelope")
+    }
+}
+
+/// A derived key together with the reference recorded next to its bundles.
+pub struct BundleKey {
+    key: Key,
+    pub reference: KeyRef,
+}
+
+impl std::fmt::Debug for BundleKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BundleKey")
+            .field("key_id", &self.reference.key_id)
+            .finish()
+    }
+}
+
+/// `--keyfile` from the command line, which beats any recorded key file.
+static KEYFILE_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Secrets and derived keys are cached so one invocation prompts at most
+/// once per key, however many bundles it opens.
+static PASSPHRASE: Mutex<Option<Secret>> = Mutex::new(None);
+static DERIVED: Mutex<Option<HashMap<String, Key>>> = Mutex::new(None);
+
+pub fn set_keyfile_override(path: PathBuf) {
+    let _ = KEYFILE_OVERRIDE.set(path);
+}
+
+impl BundleKey {
+    /// A fresh key for new bundles, with a new random salt.
+    pub fn create(config: &EncryptionConfig) -> Result<Self> {
+        let keyfile = KEYFILE_OVERRIDE.get().cloned().or_else(|| config.keyfile.clone());
+        let secret = match &keyfile {
+            Some(path) => read_keyfile(path)?,
+            None => passphrase(true)?,
+        };
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive(&secret, &salt)?;
+        let key_id = key_id(&key);
+        Ok(Self {
+            key,
+            reference: KeyRef {
+                cipher: "xchacha20poly1305".to_string(),
+                kdf: "argon2id".to_string(),
+                salt: to_hex(&salt),
+                key_id,
+                keyfile,
+            },
+        })
+    }
+
+    /// Re-derive the key described by `reference`, from `--keyfile`, the
+    /// recorded key file, `$RKVR_PASSPHRASE` or a prompt, in that order.
+    pub fn open(reference: &KeyRef) -> Result<Self> {
+        if let Some(key) = DERIVED
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|cache| cache.get(&reference.key_id))
+        {
+            return Ok(Self {
+                key: key.clone(),
+                reference: reference.clone(),
+            });
+        }
+
+        let salt = from_hex(&reference.salt)?;
+        // A recorded key file may be gone since, and the passphrase is tried
+        // instead; a mistyped --keyfile must fail rather than fall back.
+        let keyfile = match KEYFILE_OVERRIDE.get() {
+            Some(path) => Some(path),
+            None => reference.keyfile.as_ref().filter(|path| expand_home(path).exists()),
+        };
+        let secret = match keyfile {
+            Some(path) => read_keyfile(path)?,
+            None => passphrase(false)?,
+        };
+        let key = derive(&secret, &salt)?;
+        if key_id(&key) != reference.key_id {
+            if keyfile.is_none() {
+                *PASSPHRASE.lock().unwrap() = None;
+            }
+            eyre::bail!("Wrong passphrase or key file for key {}", reference.key_id);
+        }
+        DERIVED
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(reference.key_id.clone(), key.clone());
+        Ok(Self {
+            key,
+            reference: reference.clone(),
+        })
+    }
+
+    /// Wrap `out` so everything written to it is encrypted; call
+    /// [`Encryptor::finish`] to seal the stream.
+    pub fn encryptor<W: Write>(&self, mut out: W) -> Result<Encryptor<W>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        out.write_all(MAGIC)?;
+        out.write_all(&nonce)?;
+        let cipher = XChaCha20Poly1305::new((&*self.key).into());
+        Ok(Encryptor {
+            stream: Some(EncryptorBE32::from_aead(cipher, (&nonce).into())),
+            buffer: Vec::with_capacity(SEGMENT),
+            out,
+        })
+    }
+
+    /// Decrypt a stream written by [`BundleKey::encryptor`] into `out`,
+    /// failing if it was modified or truncated.
+    pub fn decrypt(&self, mut source: impl Read, out: &mut impl Write) -> Result<()> {
+        let mut magic = [0u8; 8];
+        source.read_exact(&mut magic).wrap_err("Encrypted file is truncated")?;
+        if &magic != MAGIC {
+            eyre::bail!("Not an rkvr encrypted file");
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        source.read_exact(&mut nonce).wrap_err("Encrypted file is truncated")?;
+        let cipher = XChaCha20Poly1305::new((&*self.key).into());
+        let mut stream = DecryptorBE32::from_aead(cipher, (&nonce).into());
+
+        // A segment is the last one exactly when nothing follows it.
+        let mut current = vec![0u8; SEGMENT + TAG_LEN];
+        let mut len = read_full(&mut source, &mut current)?;
+        loop {
+            let mut next = vec![0u8; SEGMENT + TAG_LEN];
+            let next_len = if len == current.len() {
+                read_full(&mut source, &mut next)?
+            } else {
+                0
+            };
+            if next_len == 0 {
+                let plain = stream
+                    .decrypt_last(&current[..len])
+                    .map_err(|_| eyre!("Encrypted file is corrupt or was tampered with"))?;
+                out.write_all(&plain)?;
+                return Ok(());
+            }
+            let plain = stream
+                .decrypt_next(&current[..len])
+                .map_err(|_| eyre!("Encrypted file is corrupt or was tampered with"))?;
+            out.write_all(&plain)?;
+            current = next;
+            len = next_len;
+        }
+    }
+
+    pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut encryptor = self.encryptor(Vec::new())?;
+        encryptor.write_all(data)?;
+        encryptor.finish()
+    }
+
+    pub fn unseal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut plain = Vec::new();
+        self.decrypt(data, &mut plain)?;
+        Ok(plain)
+    }
+}
+
+pub struct Encryptor<W: Write> {
+    stream: Option<EncryptorBE32<XChaCha20Poly1305>>,
+    buffer: Vec<u8>,
+    out: W,
+}
+
+impl<W: Write> Encryptor<W> {
+    /// Encrypt the final segment and return the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        let stream = self.stream.take().expect("encryptor already finished");
+        let sealed = stream
+            .encrypt_last(self.buffer.as_slice())
+            .map_err(|_| eyre!("Encryption failed"))?;
+        self.out.write_all(&sealed)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        // Keep a tail back: the last segment is sealed differently by finish().
+        while self.buffer.len() > SEGMENT {
+            let stream = self.stream.as_mut().expect("encryptor already finished");
+            let sealed = stream
+                .encrypt_next(&self.buffer[..SEGMENT])
+                .map_err(|_| io::Error::other("Encryption failed"))?;
+            self.out.write_all(&sealed)?;
+            self.buffer.drain(..SEGMENT);
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Write a bookkeeping file into `bundle`, encrypted as `<name>.enc` when a
+/// key is given.
+pub fn write_bundle_file(bundle: &Path, name: &str, data: &[u8], key: Option<&BundleKey>) -> Result<()> {
+    match key {
+        Some(key) => {
+            let path = bundle.join(format!("{name}.{ENCRYPTED_EXT}"));
+            fs::write(&path, key.seal(data)?).wrap_err_with(|| format!("Failed to write {}", path.display()))
+        }
+        None => {
+            let path = bundle.join(name);
+            fs::write(&path, data).wrap_err_with(|| format!("Failed to write {}", path.display()))
+        }
+    }
+}
+
+/// Read a bookkeeping file from `bundle`, decrypting it if needed.
+pub fn read_bundle_file(bundle: &Path, name: &str) -> Result<Vec<u8>> {
+    let plain = bundle.join(name);
+    if plain.exists() {
+        return fs::read(&plain).wrap_err_with(|| format!("reading {}", plain.display()));
+    }
+    let sealed = bundle.join(format!("{name}.{ENCRYPTED_EXT}"));
+    let envelope = Envelope::load(bundle)?.ok_or_else(|| eyre!("{} not found", plain.display()))?;
+    let data = fs::read(&sealed).wrap_err_with(|| format!("reading {}", sealed.display()))?;
+    BundleKey::open(&envelope.key)?
+        .unseal(&data)
+        .wrap_err_with(|| format!("decrypting {}", sealed.display()))
+}
+
+/// A fresh random file name for an encrypted payload, so the bundle does not
+/// give away what was archived. Readers take every file that is not
+/// bookkeeping as a payload, whatever its name.
+pub fn payload_name() -> String {
+    let mut id = [0u8; 8];
+    OsRng.fill_bytes(&mut id);
+    format!("{}.{ENCRYPTED_EXT}", to_hex(&id))
+}
+
+/// Whether `bundle` holds `name`, in plain or encrypted form.
+pub fn bundle_file_exists(bundle: &Path, name: &str) -> bool {
+    bundle.join(name).exists() || bundle.join(format!("{name}.{ENCRYPTED_EXT}")).exists()
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+fn read_keyfile(path: &Path) -> Result<Secret> {
+    let path = expand_home(path);
+    debug!("Reading key file {}", path.display());
+    let mut secret = Secret::default();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_end(&mut secret))
+        .wrap_err_with(|| format!("Failed to read key file {}", path.display()))?;
+    if secret.is_empty() {
+        eyre::bail!("Key file {} is empty", path.display());
+    }
+    Ok(secret)
+}
+
+fn passphrase(confirm: bool) -> Result<Secret> {
+    if let Some(cached) = PASSPHRASE.lock().unwrap().clone() {
+        return Ok(cached);
+    }
+    let secret = match std::env::var(PASSPHRASE_ENV) {
+        Ok(value) => Zeroizing::new(value),
+        Err(_) => {
+            let first = rpassword::prompt_password("rkvr passphrase: ")
+                .map(Zeroizing::new)
+                .wrap_err("Failed to read passphrase")?;
+            if confirm {
+                let second = rpassword::prompt_password("Repeat passphrase: ")
+                    .map(Zeroizing::new)
+                    .wrap_err("Failed to read passphrase")?;
+                if first != second {
+                    eyre::bail!("Passphrases do not match");
+                }
+            }
+            first
+        }
+    };
+    if secret.is_empty() {
+        eyre::bail!("Empty passphrase");
+    }
+    let secret = Secret::new(secret.as_bytes().to_vec());
+    *PASSPHRASE.lock().unwrap() = Some(secret.clone());
+    Ok(secret)
+}
+
+fn derive(secret: &[u8], salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut *key)
+        .map_err(|e| eyre!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn key_id(key: &[u8; 32]) -> String {
+    let digest = Sha256::new().chain_update(b"rkvr key id").chain_update(key).finalize();
+    to_hex(&digest[..8])
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        eyre::bail!("Invalid hex string {:?}", hex);
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| eyre!("Invalid hex string {:?}: {}", hex, e)))
+        .collect()
+}
+
+/// Fill `buf` as far as the source allows, returning how much was read.
+fn read_full(source: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match source.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_key(secret: &[u8]) -> BundleKey {
+        let salt = [7u8; 16];
+        let key = derive(secret, &salt).unwrap();
+        let key_id = key_id(&key);
+        BundleKey {
+            key,
+            reference: KeyRef {
+                cipher: "xchacha20poly1305".to_string(),
+                kdf: "argon2id".to_string(),
+                salt: to_hex(&salt),
+                key_id,
+                keyfile: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_stream_round_trip_at_segment_boundaries() {
+        let key = test_key(b"correct horse");
+        for len in [0, 1, SEGMENT - 1, SEGMENT, SEGMENT + 1, 3 * SEGMENT] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let sealed = key.seal(&data).unwrap();
+            assert_ne!(&sealed[MAGIC.len() + NONCE_LEN..], data.as_slice());
+            assert_eq!(key.unseal(&sealed).unwrap(), data, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_tampering_and_wrong_key_are_rejected() {
+        let key = test_key(b"correct horse");
+        let mut sealed = key.seal(&vec![1u8; 2 * SEGMENT]).unwrap();
+
+        assert!(test_key(b"battery staple").unseal(&sealed).is_err());
+
+        let truncated = &sealed[..sealed.len() - (SEGMENT / 2)];
+        assert!(key.unseal(truncated).is_err(), "Truncation must be detected");
+
+        let middle = sealed.len() / 2;
+        sealed[middle] ^= 1;
+        assert!(key.unseal(&sealed).is_err(), "Bit flips must be detected");
+    }
+
+    #[test]
+    fn test_bundle_files_are_sealed_with_keyfile() {
+        let temp_dir = TempDir::new().unwrap();
+        let keyfile = temp_dir.path().join("rkvr.key");
+        fs::write(&keyfile, "0123456789abcdef").unwrap();
+        let bundle = temp_dir.path().join("bundle");
+        fs::create_dir_all(&bundle).unwrap();
+
+        let key = BundleKey::create(&EncryptionConfig {
+            keyfile: Some(keyfile.clone()),
+        })
+        .unwrap();
+        Envelope {
+            key: key.reference.clone(),
+            parent: None,
+            owner: None,
+        }
+        .save(&bundle)
+        .unwrap();
+        write_bundle_file(&bundle, "metadata.yml", b"cwd: /secret\n", Some(&key)).unwrap();
+
+        assert!(!bundle.join("metadata.yml").exists());
+        let raw = fs::read(bundle.join("metadata.yml.enc")).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"secret"));
+        assert!(bundle_file_exists(&bundle, "metadata.yml"));
+        assert_eq!(read_bundle_file(&bundle, "metadata.yml").unwrap(), b"cwd: /secret\n");
+    }
+}
@@ -0,0 +1,642 @@
+//! `rkvr diff`: what changed between a bundle and the files it was made
+//! from, as they are now, or between two bundles.
+//!
+//! A bundle is described by its file manifest when it has one (incremental
+//! bkups do) and otherwise by what its payloads hold, plus the links and
+//! special files its metadata records. Tar headers keep mtimes to the second
+//! and no hashes, so such bundles are compared less finely.
+
+use crate::contents;
+use crate::index;
+use crate::manifest::{self, EntryKind, FileEntry, FileManifest, FILE_MANIFEST};
+use crate::{crypt, Scope};
+use colored::*;
+use eyre::{Context, Result};
+use similar::TextDiff;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The files one side of a comparison holds.
+pub struct Snapshot {
+    pub entries: Vec<FileEntry>,
+    /// Whether mtimes are exact; tar headers only keep whole seconds.
+    pub exact: bool,
+    /// Paths that could not be read, with why. What is below an unreadable
+    /// directory is unknown, not gone.
+    pub unreadable: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    /// What `bundle` recorded of its targets.
+    pub fn archived(bundle: &Path) -> Result<Self> {
+        if crypt::bundle_file_exists(bundle, FILE_MANIFEST) {
+            return Ok(Self {
+                entries: FileManifest::load(bundle)?.entries,
+                exact: true,
+                unreadable: BTreeMap::new(),
+            });
+        }
+        let meta = crate::load_metadata(bundle)?;
+        let mut entries = index::files(bundle)?;
+        entries.extend(meta.links.iter().map(|link| FileEntry {
+            path: link.path.clone(),
+            kind: EntryKind::Symlink,
+            size: 0,
+            mtime: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            hash: None,
+            link: Some(link.target.clone()),
+        }));
+        entries.extend(meta.specials.iter().map(|special| FileEntry {
+            path: special.path.clone(),
+            kind: EntryKind::Other,
+            size: 0,
+            mtime: 0,
+            mode: special.mode,
+            uid: special.uid,
+            gid: special.gid,
+            hash: None,
+            link: None,
+        }));
+        Ok(Self {
+            entries,
+            exact: false,
+            unreadable: BTreeMap::new(),
+        })
+    }
+
+    /// The `targets` under `cwd` as they are now. Files are only hashed
+    /// where `archived` has a hash to compare with and their size and
+    /// mtime no longer tell. Paths that cannot be read are recorded as such
+    /// rather than ending the walk.
+    pub fn live(cwd: &Path, targets: &[String], archived: &Snapshot) -> Result<Self> {
+        let recorded: HashMap<&str, &FileEntry> = archived.entries.iter().map(|e| (e.path.as_str(), e)).collect();
+        let mut entries = Vec::new();
+        let mut unreadable = BTreeMap::new();
+        let mut pending: Vec<PathBuf> = targets.iter().rev().map(|t| cwd.join(t)).collect();
+        while let Some(path) = pending.pop() {
+            let rel = path
+                .strip_prefix(cwd)
+                .map(|rel| rel.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| path.display().to_string());
+            match fs::symlink_metadata(&path) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => {
+                    unreadable.insert(rel, error.to_string());
+                    continue;
+                }
+                Ok(_) => {}
+            }
+            let mut entry = match FileEntry::stat(&path, rel.clone()) {
+                Ok(entry) => entry,
+                Err(error) => {
+                    unreadable.insert(rel, format!("{:#}", error));
+                    continue;
+                }
+            };
+            match entry.kind {
+                EntryKind::Dir => match fs::read_dir(&path) {
+                    Ok(dir) => {
+                        let mut children: Vec<PathBuf> = dir.filter_map(|e| e.ok().map(|e| e.path())).collect();
+                        children.sort_by(|a, b| b.cmp(a));
+                        pending.extend(children);
+                    }
+                    Err(error) => {
+                        unreadable.insert(rel, error.to_string());
+                        continue;
+                    }
+                },
+                EntryKind::File => {
+                    if let Some(old) = recorded.get(entry.path.as_str()).filter(|old| old.hash.is_some()) {
+                        if old.size == entry.size && old.mtime == entry.mtime {
+                            entry.hash = old.hash.clone();
+                        } else {
+                            match manifest::hash_file(&path) {
+                                Ok(hash) => entry.hash = Some(hash),
+                                Err(error) => {
+                                    unreadable.insert(rel, format!("{:#}", error));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                }
+                EntryKind::Symlink | EntryKind::Other => {}
+            }
+            entries.push(entry);
+        }
+        Ok(Self {
+            entries,
+            exact: archived.exact,
+            unreadable,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Different type, size, mtime, contents or link target.
+    Modified,
+    /// Only the mode or owner changed.
+    Permissions,
+    /// Could not be read, so whether it changed is unknown.
+    Unreadable,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (mark, color) = match self {
+            ChangeKind::Added => ("A", Color::Green),
+            ChangeKind::Removed => ("D", Color::Red),
+            ChangeKind::Modified => ("M", Color::Yellow),
+            ChangeKind::Permissions => ("P", Color::Cyan),
+            ChangeKind::Unreadable => ("?", Color::Magenta),
+        };
+        write!(f, "{}", mark.color(color))
+    }
+}
+
+/// How one path differs between two snapshots.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// What differs, e.g. `size 3 → 5` or `mode 0644 → 0755`.
+    pub details: Vec<String>,
+    /// Both sides are regular files, so their contents can be diffed.
+    pub files: bool,
+}
+
+fn kind_name(kind: EntryKind) -> &'static str {
+    match kind {
+        EntryKind::File => "file",
+        EntryKind::Dir => "directory",
+        EntryKind::Symlink => "symlink",
+        EntryKind::Other => "special file",
+    }
+}
+
+/// How `after` differs from `before`, path by path in path order.
+/// Directories differ only in type and permissions: their mtimes change with
+/// every file added or removed inside them. A path either side could not
+/// read is reported as such, and nothing below it.
+pub fn compare(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let exact = before.exact && after.exact;
+    let mut paths: BTreeMap<&str, (Option<&FileEntry>, Option<&FileEntry>)> = BTreeMap::new();
+    for entry in &before.entries {
+        paths.entry(&entry.path).or_default().0 = Some(entry);
+    }
+    for entry in &after.entries {
+        paths.entry(&entry.path).or_default().1 = Some(entry);
+    }
+
+    let unreadable: BTreeMap<&str, &str> = before
+        .unreadable
+        .iter()
+        .chain(&after.unreadable)
+        .map(|(path, error)| (path.as_str(), error.as_str()))
+        .collect();
+    for path in unreadable.keys() {
+        paths.entry(path).or_default();
+    }
+    let below_unreadable = |path: &str| {
+        Path::new(path)
+            .ancestors()
+            .skip(1)
+            .any(|parent| unreadable.contains_key(parent.to_string_lossy().as_ref()))
+    };
+
+    let mut changes = Vec::new();
+    for (path, sides) in paths {
+        let change = |kind, details| Change {
+            path: path.to_string(),
+            kind,
+            details,
+            files: false,
+        };
+        if below_unreadable(path) {
+            continue;
+        }
+        if let Some(error) = unreadable.get(path) {
+            changes.push(change(ChangeKind::Unreadable, vec![error.to_string()]));
+            continue;
+        }
+        let (old, new) = match sides {
+            (None, Some(_)) => {
+                changes.push(change(ChangeKind::Added, vec![]));
+                continue;
+            }
+            (Some(_), None) => {
+                changes.push(change(ChangeKind::Removed, vec![]));
+                continue;
+            }
+            (Some(old), Some(new)) => (old, new),
+            (None, None) => continue,
+        };
+
+        let mut modified = Vec::new();
+        if old.kind != new.kind {
+            modified.push(format!("type {} → {}", kind_name(old.kind), kind_name(new.kind)));
+        } else if old.kind == EntryKind::Symlink {
+            if old.link != new.link {
+                let show = |link: &Option<PathBuf>| link.as_ref().map(|l| l.display().to_string()).unwrap_or_default();
+                modified.push(format!("link {} → {}", show(&old.link), show(&new.link)));
+            }
+        } else if old.kind == EntryKind::File {
+            if old.size != new.size {
+                modified.push(format!("size {} → {}", old.size, new.size));
+            }
+            let (old_mtime, new_mtime) = match exact {
+                true => (old.mtime, new.mtime),
+                false => (old.mtime.div_euclid(1_000_000_000), new.mtime.div_euclid(1_000_000_000)),
+            };
+            if old_mtime != new_mtime {
+                modified.push("mtime".to_string());
+            }
+            if let (Some(a), Some(b)) = (&old.hash, &new.hash) {
+                if a != b {
+                    modified.push("contents".to_string());
+                }
+            }
+        }
+
+        // Links have no permissions of their own.
+        let mut permissions = Vec::new();
+        if old.kind == new.kind && old.kind != EntryKind::Symlink {
+            if old.mode != new.mode {
+                permissions.push(format!("mode {:04o} → {:04o}", old.mode, new.mode));
+            }
+            if (old.uid, old.gid) != (new.uid, new.gid) {
+                permissions.push(format!("owner {}:{} → {}:{}", old.uid, old.gid, new.uid, new.gid));
+            }
+        }
+
+        if !modified.is_empty() {
+            modified.extend(permissions);
+            changes.push(Change {
+                files: old.kind == EntryKind::File && new.kind == EntryKind::File,
+                ..change(ChangeKind::Modified, modified)
+            });
+        } else if !permissions.is_empty() {
+            changes.push(change(ChangeKind::Permissions, permissions));
+        }
+    }
+    changes
+}
+
+/// One line per change, then a count of each kind.
+pub fn write_changes(changes: &[Change], out: &mut dyn Write) -> io::Result<()> {
+    for change in changes {
+        if change.details.is_empty() {
+            writeln!(out, "{}  {}", change.kind, change.path)?;
+        } else {
+            let details = format!("({})", change.details.join(", "));
+            writeln!(out, "{}  {}  {}", change.kind, change.path, details.dimmed())?;
+        }
+    }
+    if changes.is_empty() {
+        return writeln!(out, "No changes");
+    }
+    let count = |kind| changes.iter().filter(|c| c.kind == kind).count();
+    write!(
+        out,
+        "{} added, {} removed, {} modified, {} with new permissions",
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::Modified),
+        count(ChangeKind::Permissions)
+    )?;
+    match count(ChangeKind::Unreadable) {
+        0 => writeln!(out),
+        unreadable => writeln!(out, ", {} unreadable", unreadable),
+    }
+}
+
+/// A unified diff of `path` from `old` to `new`, or a note that they are
+/// binary.
+pub fn write_patch(path: &str, old: &[u8], new: &[u8], out: &mut dyn Write) -> io::Result<()> {
+    let (a, b) = (format!("a/{}", path), format!("b/{}", path));
+    if old.contains(&0) || new.contains(&0) {
+        return writeln!(out, "Binary files {} and {} differ", a, b);
+    }
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let diff = TextDiff::from_lines(old.as_ref(), new.as_ref());
+    for line in diff.unified_diff().context_radius(3).header(&a, &b).to_string().lines() {
+        let line = if line.starts_with("+++") || line.starts_with("---") {
+            line.bold()
+        } else if line.starts_with('+') {
+            line.green()
+        } else if line.starts_with('-') {
+            line.red()
+        } else if line.starts_with("@@") {
+            line.cyan()
+        } else {
+            line.normal()
+        };
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Contents of the regular files at `paths` as `bundle` restores them:
+/// incremental bundles only hold what changed, so their parents are read
+/// first and later versions win.
+pub fn archived_contents(bundle: &Path, paths: &HashSet<&str>) -> Result<HashMap<String, Vec<u8>>> {
+    let mut found = HashMap::new();
+    for (dir, _) in crate::bundle_chain(bundle)? {
+        contents::walk(&dir, &mut |entry| {
+            if entry.file.kind == EntryKind::File && paths.contains(entry.file.path.as_str()) {
+                let mut data = Vec::new();
+                entry.contents.read_to_end(&mut data)?;
+                found.insert(entry.file.path.clone(), data);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(found)
+}
+
+/// The bundle `spec` names, which must be within `scope`.
+fn resolve(spaces: &[PathBuf], spec: &str, scope: Scope) -> Result<PathBuf> {
+    let bundle = crate::find_bundle(spaces, spec, scope)?;
+    if !scope.includes(&bundle) {
+        eyre::bail!("{} belongs to another user; root can use --all-users", bundle.display());
+    }
+    Ok(bundle)
+}
+
+/// Modified regular files, whose contents a patch compares.
+fn patched(changes: &[Change]) -> HashSet<&str> {
+    changes.iter().filter(|c| c.files).map(|c| c.path.as_str()).collect()
+}
+
+/// A unified diff of each modified file found on both sides whose contents
+/// do differ.
+fn write_patches(
+    changes: &[Change],
+    old: &HashMap<String, Vec<u8>>,
+    new: &HashMap<String, Vec<u8>>,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    for change in changes.iter().filter(|c| c.files) {
+        if let (Some(old), Some(new)) = (old.get(&change.path), new.get(&change.path)) {
+            if old != new {
+                write_patch(&change.path, old, new, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `rkvr diff <bundle>`: what changed under the bundle's `cwd` since it was
+/// made, with a unified diff of each modified text file when `patch`.
+pub fn diff_live(spaces: &[PathBuf], spec: &str, patch: bool, scope: Scope) -> Result<()> {
+    let bundle = resolve(spaces, spec, scope)?;
+    let meta = crate::load_metadata(&bundle)?;
+    let archived = Snapshot::archived(&bundle)?;
+    let live = Snapshot::live(&meta.cwd, &meta.targets, &archived)?;
+    let changes = compare(&archived, &live);
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{} {} {}",
+        bundle.display().to_string().bright_blue().bold(),
+        "→".dimmed(),
+        meta.cwd.display().to_string().bright_red()
+    )?;
+    write_changes(&changes, &mut out)?;
+    if patch {
+        let paths = patched(&changes);
+        let old = archived_contents(&bundle, &paths)?;
+        let mut new = HashMap::new();
+        for path in paths {
+            let live = meta.cwd.join(path);
+            let data = fs::read(&live).wrap_err_with(|| format!("reading {}", live.display()))?;
+            new.insert(path.to_string(), data);
+        }
+        write_patches(&changes, &old, &new, &mut out)?;
+    }
+    Ok(())
+}
+
+/// `rkvr diff <before> <after>`: what changed from one bundle to another,
+/// typically two bkups of the same directory, with a unified diff of each
+/// modified text file when `patch`.
+pub fn diff_bundles(spaces: &[PathBuf], before: &str, after: &str, patch: bool, scope: Scope) -> Result<()> {
+    let (before, after) = (resolve(spaces, before, scope)?, resolve(spaces, after, scope)?);
+    let changes = compare(&Snapshot::archived(&before)?, &Snapshot::archived(&after)?);
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{} {} {}",
+        before.display().to_string().bright_blue().bold(),
+        "→".dimmed(),
+        after.display().to_string().bright_blue().bold()
+    )?;
+    let (old_cwd, new_cwd) = (crate::load_metadata(&before)?.cwd, crate::load_metadata(&after)?.cwd);
+    if old_cwd != new_cwd {
+        writeln!(
+            out,
+            "{}",
+            format!(
+                "(paths are relative to {} and {} respectively)",
+                old_cwd.display(),
+                new_cwd.display()
+            )
+            .yellow()
+        )?;
+    }
+    write_changes(&changes, &mut out)?;
+    if patch {
+        let paths = patched(&changes);
+        let old = archived_contents(&before, &paths)?;
+        let new = archived_contents(&after, &paths)?;
+        write_patches(&changes, &old, &new, &mut out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn summary(changes: &[Change]) -> Vec<(ChangeKind, &str, String)> {
+        changes
+            .iter()
+            .map(|c| (c.kind, c.path.as_str(), c.details.join(", ")))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_live_tree_with_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let cwd = temp_dir.path();
+        let project = cwd.join("project");
+        fs::create_dir_all(&project).unwrap();
+        for (name, text) in [
+            ("same", "same\n"),
+            ("grown", "a\n"),
+            ("touched", "t\n"),
+            ("edited", "abc\n"),
+        ] {
+            fs::write(project.join(name), text).unwrap();
+        }
+        fs::write(project.join("gone"), "bye\n").unwrap();
+        fs::write(project.join("script"), "#!/bin/sh\n").unwrap();
+        let archived = Snapshot {
+            entries: FileManifest::scan(&project, cwd, None).unwrap().entries,
+            exact: true,
+            unreadable: BTreeMap::new(),
+        };
+
+        sleep(Duration::from_millis(10));
+        fs::write(project.join("grown"), "a\nb\n").unwrap();
+        fs::write(project.join("edited"), "xyz\n").unwrap();
+        fs::write(project.join("touched"), "t\n").unwrap();
+        fs::remove_file(project.join("gone")).unwrap();
+        fs::write(project.join("new"), "hi\n").unwrap();
+        fs::set_permissions(project.join("script"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let live = Snapshot::live(cwd, &["project".to_string()], &archived).unwrap();
+        let changes = compare(&archived, &live);
+        assert_eq!(
+            summary(&changes),
+            [
+                (ChangeKind::Modified, "project/edited", "mtime, contents".to_string()),
+                (ChangeKind::Removed, "project/gone", String::new()),
+                (
+                    ChangeKind::Modified,
+                    "project/grown",
+                    "size 2 → 4, mtime, contents".to_string()
+                ),
+                (ChangeKind::Added, "project/new", String::new()),
+                (
+                    ChangeKind::Permissions,
+                    "project/script",
+                    "mode 0644 → 0755".to_string()
+                ),
+                (ChangeKind::Modified, "project/touched", "mtime".to_string()),
+            ]
+        );
+        assert!(changes[0].files);
+
+        colored::control::set_override(false);
+        let mut out = Vec::new();
+        write_changes(&changes, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(
+            text.starts_with("M  project/edited  (mtime, contents)\nD  project/gone\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.ends_with("1 added, 1 removed, 3 modified, 1 with new permissions\n"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_tar_mtimes_compare_to_the_second() {
+        let entry = |mtime, hash: Option<&str>| FileEntry {
+            path: "f".into(),
+            kind: EntryKind::File,
+            size: 1,
+            mtime,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            hash: hash.map(String::from),
+            link: None,
+        };
+        let tar = Snapshot {
+            entries: vec![entry(5_000_000_000, None)],
+            exact: false,
+            unreadable: BTreeMap::new(),
+        };
+        let live = Snapshot {
+            entries: vec![entry(5_123_456_789, None)],
+            exact: true,
+            unreadable: BTreeMap::new(),
+        };
+        assert!(compare(&tar, &live).is_empty());
+        let later = Snapshot {
+            entries: vec![entry(6_000_000_000, None)],
+            exact: true,
+            unreadable: BTreeMap::new(),
+        };
+        assert_eq!(
+            summary(&compare(&tar, &later)),
+            [(ChangeKind::Modified, "f", "mtime".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_unreadable_paths_are_reported_not_fatal() {
+        let temp_dir = TempDir::new().unwrap();
+        let cwd = temp_dir.path();
+        let project = cwd.join("project");
+        fs::create_dir_all(project.join("locked")).unwrap();
+        fs::write(project.join("locked/inside"), "hidden\n").unwrap();
+        fs::write(project.join("secret"), "v1\n").unwrap();
+        fs::write(project.join("open"), "open\n").unwrap();
+        let archived = Snapshot {
+            entries: FileManifest::scan(&project, cwd, None).unwrap().entries,
+            exact: true,
+            unreadable: BTreeMap::new(),
+        };
+        fs::write(project.join("secret"), "version 2\n").unwrap();
+        fs::write(project.join("new"), "hi\n").unwrap();
+        for (path, mode) in [(cwd, 0o755), (project.as_path(), 0o755), (&project.join("new"), 0o644)] {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        fs::set_permissions(project.join("locked"), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(project.join("secret"), fs::Permissions::from_mode(0o600)).unwrap();
+
+        // Run as someone else, to whom `locked` and `secret` are closed.
+        let passed = crate::store::tests::unprivileged(|| {
+            let live = Snapshot::live(cwd, &["project".to_string()], &archived).unwrap();
+            let changes = compare(&archived, &live);
+            let kinds: Vec<(ChangeKind, &str)> = changes.iter().map(|c| (c.kind, c.path.as_str())).collect();
+            kinds
+                == [
+                    (ChangeKind::Unreadable, "project/locked"),
+                    (ChangeKind::Added, "project/new"),
+                    (ChangeKind::Unreadable, "project/secret"),
+                ]
+                && changes[0].details[0].contains("denied")
+        });
+        assert!(passed, "unreadable paths should be reported and the rest compared");
+    }
+
+    #[test]
+    fn test_write_patch() {
+        colored::control::set_override(false);
+        let mut out = Vec::new();
+        write_patch("notes.txt", b"one\ntwo\nthree\n", b"one\n2\nthree\n", &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+
+        let mut out = Vec::new();
+        write_patch("blob", b"\0\x01", b"\0\x02", &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Binary files a/blob and b/blob differ\n"
+        );
+    }
+}
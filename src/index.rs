@@ -0,0 +1,413 @@
+//! A per-space SQLite cache of what listings and `find` read from bundles:
+//! their listing records, their file trees and the files inside them.
+//!
+//! The index is only ever a cache. Each lookup compares a bundle's stamp
+//! (the newest mtime of its directory and `metadata.yml`) with the one
+//! recorded and reads bundles that changed afresh, so a stale or missing
+//! index costs time, never correctness. `archive`, `recover` and `cleanup`
+//! keep it current as they go and `rkvr reindex` rebuilds it. Encrypted
+//! bundles are never cached, so nothing of theirs is kept in plaintext.
+
+use crate::contents;
+use crate::crypt;
+use crate::listing::BundleRecord;
+use crate::manifest::FileEntry;
+use colored::*;
+use eyre::{eyre, Context, Result};
+use log::{debug, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The index file, at the top of each space.
+pub const INDEX_FILE: &str = ".index.db";
+
+/// Layout of the tables below. An index with another is dropped and
+/// rebuilt as bundles are looked up.
+const INDEX_VERSION: i32 = 2;
+
+const SCHEMA: &str = "
+    CREATE TABLE bundles (
+        name TEXT PRIMARY KEY,
+        stamp INTEGER NOT NULL,
+        parent TEXT,
+        record TEXT NOT NULL,
+        contents TEXT,
+        files_indexed INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE files (
+        bundle TEXT NOT NULL,
+        path TEXT NOT NULL,
+        entry TEXT NOT NULL
+    );
+    CREATE INDEX files_by_bundle ON files (bundle);
+";
+
+/// What the index keeps about one bundle.
+#[derive(Debug, Clone)]
+pub struct Indexed {
+    pub record: BundleRecord,
+    /// The file tree recorded in the metadata, for pattern matching;
+    /// absent when the metadata cannot be read.
+    pub contents: Option<String>,
+    /// Name of the bundle this one was bkup'd incrementally against.
+    parent: Option<String>,
+}
+
+impl Indexed {
+    /// Read straight from the bundle.
+    pub fn read(bundle: &Path) -> Self {
+        let metadata = crate::load_metadata(bundle).ok();
+        Self {
+            record: BundleRecord::from_metadata(bundle, metadata.as_ref()),
+            contents: metadata.map(|m| m.contents),
+            parent: crate::bundle_parent(bundle),
+        }
+    }
+}
+
+/// Mark every bundle another one in `bundles` builds on as pinned, besides
+/// those pinned by hand.
+fn set_pinned(bundles: &mut [Indexed]) {
+    let parents: HashSet<String> = bundles.iter().filter_map(|b| b.parent.clone()).collect();
+    for bundle in bundles {
+        bundle.record.pinned |= parents.contains(&bundle.record.name);
+    }
+}
+
+/// Newest mtime, in nanoseconds, of the bundle directory and its metadata.
+fn stamp(bundle: &Path) -> Option<i64> {
+    [bundle.to_path_buf(), bundle.join("metadata.yml")]
+        .iter()
+        .filter_map(|path| fs::symlink_metadata(path).ok())
+        .map(|meta| meta.mtime() * 1_000_000_000 + meta.mtime_nsec())
+        .max()
+}
+
+fn is_encrypted(bundle: &Path) -> bool {
+    bundle.join(crypt::ENVELOPE_FILE).exists()
+}
+
+fn bundle_name(bundle: &Path) -> Result<&str> {
+    bundle
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| eyre!("{} is not a bundle", bundle.display()))
+}
+
+/// Every entry inside `bundle`, read from its payloads.
+fn read_files(bundle: &Path) -> Result<Vec<FileEntry>> {
+    let mut files = Vec::new();
+    contents::walk(bundle, &mut |entry| {
+        files.push(entry.file.clone());
+        Ok(())
+    })?;
+    Ok(files)
+}
+
+pub struct Index {
+    db: Connection,
+    space: PathBuf,
+}
+
+impl Index {
+    pub fn open(space: &Path) -> Result<Self> {
+        let path = space.join(INDEX_FILE);
+        let created = !path.exists();
+        let db = Connection::open(&path).wrap_err_with(|| format!("opening {}", path.display()))?;
+        db.busy_timeout(Duration::from_secs(5))?;
+        // Root indexing a user's subtree leaves the index to that user.
+        if created && crate::current_uid() == 0 {
+            let owner = fs::metadata(space)?.uid();
+            std::os::unix::fs::chown(&path, Some(owner), None)?;
+        }
+
+        let version: i32 = db.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != INDEX_VERSION {
+            debug!("Creating index {} (found layout {})", path.display(), version);
+            db.execute_batch("DROP TABLE IF EXISTS files; DROP TABLE IF EXISTS bundles;")?;
+            db.execute_batch(SCHEMA)?;
+            db.pragma_update(None, "user_version", INDEX_VERSION)?;
+        }
+        Ok(Self {
+            db,
+            space: space.to_path_buf(),
+        })
+    }
+
+    /// Every bundle in the space, oldest first: from the index where it is
+    /// current and read afresh (and recorded) where not. Bundles that are
+    /// gone are forgotten.
+    pub fn bundles(&self) -> Result<Vec<Indexed>> {
+        let tx = self.db.unchecked_transaction()?;
+        let mut found = Vec::new();
+        for bundle in crate::bundle_dirs(&self.space)? {
+            found.push(self.bundle(&bundle)?);
+        }
+
+        let live: HashSet<&str> = found.iter().map(|b| b.record.name.as_str()).collect();
+        let stored: Vec<String> = self
+            .db
+            .prepare("SELECT name FROM bundles")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for name in stored.iter().filter(|name| !live.contains(name.as_str())) {
+            self.forget(name)?;
+        }
+        tx.commit()?;
+
+        set_pinned(&mut found);
+        Ok(found)
+    }
+
+    /// One bundle of the space, as [`Index::bundles`] gets it. Whether an
+    /// incremental bkup pins it is left unset.
+    pub fn bundle(&self, bundle: &Path) -> Result<Indexed> {
+        let name = bundle_name(bundle)?;
+        let Some(stamp) = stamp(bundle).filter(|_| !is_encrypted(bundle)) else {
+            return Ok(Indexed::read(bundle));
+        };
+
+        let cached = self
+            .db
+            .query_row(
+                "SELECT record, contents, parent FROM bundles WHERE name = ?1 AND stamp = ?2",
+                params![name, stamp],
+                |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        if let Some((record, contents, parent)) = cached {
+            let mut record: BundleRecord = serde_json::from_str(&record)?;
+            record.path = bundle.to_path_buf();
+            return Ok(Indexed {
+                record,
+                contents,
+                parent,
+            });
+        }
+
+        debug!("Indexing {}", bundle.display());
+        let indexed = Indexed::read(bundle);
+        // What was recorded of the files inside may have changed too.
+        self.db.execute("DELETE FROM files WHERE bundle = ?1", [name])?;
+        self.db.execute(
+            "INSERT OR REPLACE INTO bundles (name, stamp, parent, record, contents, files_indexed)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+            params![
+                name,
+                stamp,
+                indexed.parent,
+                serde_json::to_string(&indexed.record)?,
+                indexed.contents
+            ],
+        )?;
+        Ok(indexed)
+    }
+
+    /// Every file, directory and link inside `bundle`: from the index when
+    /// current, else read from its payloads and recorded.
+    pub fn files(&self, bundle: &Path) -> Result<Vec<FileEntry>> {
+        if is_encrypted(bundle) {
+            return read_files(bundle);
+        }
+        let name = bundle_name(bundle)?;
+        self.bundle(bundle)?;
+
+        let indexed: bool = self
+            .db
+            .query_row("SELECT files_indexed FROM bundles WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .unwrap_or(false);
+        if indexed {
+            return self
+                .db
+                .prepare("SELECT entry FROM files WHERE bundle = ?1 ORDER BY rowid")?
+                .query_map([name], |row| row.get::<_, String>(0))?
+                .map(|entry| Ok(serde_json::from_str(&entry?)?))
+                .collect();
+        }
+
+        let files = read_files(bundle)?;
+        let tx = self.db.unchecked_transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT INTO files (bundle, path, entry) VALUES (?1, ?2, ?3)")?;
+            for file in &files {
+                insert.execute(params![name, file.path, serde_json::to_string(file)?])?;
+            }
+        }
+        tx.execute("UPDATE bundles SET files_indexed = 1 WHERE name = ?1", [name])?;
+        tx.commit()?;
+        Ok(files)
+    }
+
+    fn forget(&self, name: &str) -> Result<()> {
+        self.db.execute("DELETE FROM files WHERE bundle = ?1", [name])?;
+        self.db.execute("DELETE FROM bundles WHERE name = ?1", [name])?;
+        Ok(())
+    }
+
+    /// Drop everything and index every bundle in the space again, files
+    /// included. Returns how many bundles were indexed and how many of
+    /// those could not have their files read.
+    pub fn rebuild(&self) -> Result<(usize, usize)> {
+        self.db.execute_batch("DELETE FROM files; DELETE FROM bundles;")?;
+        let bundles = crate::bundle_dirs(&self.space)?;
+        let mut failed = 0;
+        for bundle in &bundles {
+            if let Err(error) = self.files(bundle) {
+                failed += 1;
+                warn!("Cannot index the files in {}: {:#}", bundle.display(), error);
+            }
+        }
+        Ok((bundles.len(), failed))
+    }
+}
+
+/// Every bundle in `space`, as [`Index::bundles`] gets them, or read
+/// straight from the bundles if the index cannot be used.
+pub fn bundles(space: &Path) -> Result<Vec<Indexed>> {
+    match Index::open(space).and_then(|index| index.bundles()) {
+        Ok(found) => Ok(found),
+        Err(error) => {
+            warn!("Not using the index of {}: {:#}", space.display(), error);
+            let mut found: Vec<Indexed> = crate::bundle_dirs(space)?.iter().map(|b| Indexed::read(b)).collect();
+            set_pinned(&mut found);
+            Ok(found)
+        }
+    }
+}
+
+/// The files inside `bundle`, as [`Index::files`] gets them, or read from
+/// its payloads if the index cannot be used.
+pub fn files(bundle: &Path) -> Result<Vec<FileEntry>> {
+    let space = bundle.parent().unwrap_or(Path::new("."));
+    match Index::open(space) {
+        Ok(index) => index.files(bundle),
+        Err(error) => {
+            warn!("Not using the index of {}: {:#}", space.display(), error);
+            read_files(bundle)
+        }
+    }
+}
+
+/// `rkvr reindex`: rebuild the index of each of `spaces`.
+pub fn reindex(spaces: &[PathBuf]) -> Result<()> {
+    let mut failed = 0;
+    for space in spaces {
+        let (indexed, unreadable) = Index::open(space)?.rebuild()?;
+        println!("{}: {} bundles indexed", space.display(), indexed);
+        if unreadable > 0 {
+            eprintln!(
+                "{} {} bundles in {} could not be read (see the log)",
+                "WARNING".yellow(),
+                unreadable,
+                space.display()
+            );
+        }
+        failed += unreadable;
+    }
+    if failed > 0 {
+        eyre::bail!("the files in {} bundles could not be indexed", failed);
+    }
+    Ok(())
+}
+
+/// Record a bundle that was just written. The index is a cache, so a
+/// failure here is only logged.
+pub fn update(bundle: &Path) {
+    let space = bundle.parent().unwrap_or(Path::new("."));
+    if let Err(error) = Index::open(space).and_then(|index| index.bundle(bundle)) {
+        warn!("Cannot index {}: {:#}", bundle.display(), error);
+    }
+}
+
+/// Forget a bundle that was just removed. Failures are only logged.
+pub fn remove(bundle: &Path) {
+    let space = bundle.parent().unwrap_or(Path::new("."));
+    let forgotten = bundle_name(bundle).and_then(|name| Index::open(space)?.forget(name));
+    if let Err(error) = forgotten {
+        warn!("Cannot remove {} from the index: {:#}", bundle.display(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use tempfile::TempDir;
+
+    fn write_bundle(space: &Path, name: &str, metadata: &str) -> PathBuf {
+        let bundle = space.join(name);
+        fs::create_dir_all(bundle.join("notes")).unwrap();
+        fs::write(bundle.join("notes/todo.txt"), "water plants\n").unwrap();
+        fs::write(bundle.join("metadata.yml"), metadata).unwrap();
+        bundle
+    }
+
+    #[test]
+    fn test_index_caches_and_refreshes_bundles() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+        let full = write_bundle(
+            space,
+            "2024-03-05-070809-000",
+            "cwd: /home/me\ntargets:\n- notes\ncontents: ''\n",
+        );
+        write_bundle(
+            space,
+            "2024-03-06-070809-000",
+            "cwd: /home/me\ntargets:\n- notes\nparent: 2024-03-05-070809-000\ncontents: ''\n",
+        );
+
+        let index = Index::open(space).unwrap();
+        let bundles = index.bundles().unwrap();
+        let names: Vec<&str> = bundles.iter().map(|b| b.record.name.as_str()).collect();
+        assert_eq!(names, ["2024-03-05-070809-000", "2024-03-06-070809-000"]);
+        assert!(bundles[0].record.pinned, "the incremental bkup builds on it");
+        assert!(!bundles[1].record.pinned);
+        assert_eq!(bundles[0].contents.as_deref(), Some(""));
+
+        // A cached record is served even if the bundle's contents change
+        // behind the index's back, as long as its stamp does not.
+        let files = index.files(&full).unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["notes", "notes/todo.txt"]);
+        fs::write(full.join("notes/later.txt"), "").unwrap();
+        assert_eq!(index.files(&full).unwrap().len(), 2);
+
+        // Rewriting the metadata changes the stamp.
+        sleep(Duration::from_millis(10));
+        fs::write(
+            full.join("metadata.yml"),
+            "cwd: /srv\ntargets:\n- notes\ncontents: ''\n",
+        )
+        .unwrap();
+        let bundles = index.bundles().unwrap();
+        assert_eq!(bundles[0].record.cwd, Some(PathBuf::from("/srv")));
+        assert_eq!(index.files(&full).unwrap().len(), 3);
+
+        // Removed bundles are forgotten.
+        fs::remove_dir_all(space.join("2024-03-06-070809-000")).unwrap();
+        let bundles = index.bundles().unwrap();
+        assert_eq!(bundles.len(), 1);
+        assert!(!bundles[0].record.pinned);
+        let rows: i64 = index
+            .db
+            .query_row("SELECT COUNT(*) FROM bundles", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+
+        // Pinning by hand touches the bundle directory, so it shows.
+        sleep(Duration::from_millis(10));
+        crate::set_pin(&full, true).unwrap();
+        assert!(index.bundles().unwrap()[0].record.pinned);
+        assert_eq!(index.files(&full).unwrap().len(), 3, "the marker is not a payload");
+
+        assert_eq!(index.rebuild().unwrap(), (1, 0));
+    }
+}
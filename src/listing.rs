@@ -0,0 +1,569 @@
+use crate::matching::Match;
+use crate::Metadata;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use colored::*;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How `ls-rmrf` and `ls-bkup` print bundles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ListFormat {
+    /// Colored, for people.
+    #[default]
+    Text,
+    /// One JSON array of records.
+    Json,
+    /// One JSON record per line.
+    Jsonl,
+    /// A YAML sequence of records.
+    Yaml,
+    /// Tab-separated values with a header line.
+    Tsv,
+}
+
+/// Order of listed bundles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ListSort {
+    /// Newest first.
+    #[default]
+    Time,
+    /// Largest on disk first.
+    Size,
+    /// By working directory, newest first within each.
+    Cwd,
+}
+
+/// Structured filters for listings; a bundle is listed only if it passes
+/// all that are given (and matches the search patterns, if any).
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ListFilters {
+    #[arg(long, value_name = "WHEN", value_parser = parse_when, help = "Only bundles created at or after WHEN (e.g. 2d, 12h, 2026-06-01)")]
+    pub since: Option<DateTime<Local>>,
+
+    #[arg(long, value_name = "WHEN", value_parser = parse_when, help = "Only bundles created before WHEN")]
+    pub until: Option<DateTime<Local>>,
+
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, help = "Only bundles taking at least SIZE on disk (e.g. 10M)")]
+    pub min_size: Option<u64>,
+
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, help = "Only bundles taking at most SIZE on disk")]
+    pub max_size: Option<u64>,
+
+    #[arg(long, value_name = "PREFIX", help = "Only bundles made in PREFIX or below it")]
+    pub cwd: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Only bundles with a target whose name or path matches GLOB"
+    )]
+    pub target: Option<String>,
+
+    #[arg(long, value_name = "USER", help = "Only bundles belonging to USER (name or uid)")]
+    pub user: Option<String>,
+
+    #[arg(long, value_name = "HOST", help = "Only bundles made on HOST")]
+    pub host: Option<String>,
+}
+
+impl ListFilters {
+    /// These filters with a relative `--cwd` taken from `cwd`.
+    pub fn resolved(&self, cwd: &Path) -> Self {
+        Self {
+            cwd: self.cwd.as_ref().map(|prefix| cwd.join(prefix)),
+            ..self.clone()
+        }
+    }
+
+    pub fn matches(&self, r: &BundleRecord) -> bool {
+        let created = r.timestamp;
+        self.since.is_none_or(|since| created.is_some_and(|t| t >= since))
+            && self.until.is_none_or(|until| created.is_some_and(|t| t < until))
+            && self.min_size.is_none_or(|min| r.size >= min)
+            && self.max_size.is_none_or(|max| r.size <= max)
+            && self
+                .cwd
+                .as_ref()
+                .is_none_or(|prefix| r.cwd.as_ref().is_some_and(|cwd| cwd.starts_with(prefix)))
+            && self.target.as_ref().is_none_or(|glob| {
+                r.targets.iter().any(|t| {
+                    let path = r.cwd.as_ref().map(|cwd| cwd.join(t).display().to_string());
+                    glob_matches(glob, t) || path.is_some_and(|p| glob_matches(glob, &p))
+                })
+            })
+            && self.user.as_ref().is_none_or(|user| {
+                r.user.as_ref() == Some(user) || user.parse::<u32>().is_ok_and(|uid| r.uid == Some(uid))
+            })
+            && self.host.as_ref().is_none_or(|host| r.host.as_ref() == Some(host))
+    }
+}
+
+/// Shell-style wildcard match, as `fnmatch(3)` does it.
+pub fn glob_matches(glob: &str, text: &str) -> bool {
+    let (Ok(glob), Ok(text)) = (CString::new(glob), CString::new(text)) else {
+        return false;
+    };
+    unsafe { libc::fnmatch(glob.as_ptr(), text.as_ptr(), 0) == 0 }
+}
+
+/// A point in time: a span back from now (`90s`, `30m`, `12h`, `2d`, `1w`),
+/// a date (`2026-06-01`, local midnight) or a local date and time
+/// (`2026-06-01 12:30[:00]`, or with a `T`).
+pub fn parse_when(value: &str) -> Result<DateTime<Local>, String> {
+    let value = value.trim();
+    let span = value
+        .char_indices()
+        .last()
+        .and_then(|(i, unit)| Some((value[..i].parse::<i64>().ok()?, unit)));
+    if let Some((count, unit)) = span.filter(|(_, unit)| unit.is_ascii_alphabetic()) {
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            _ => return Err(format!("unknown unit {:?} in {:?}; use s, m, h, d or w", unit, value)),
+        };
+        return Ok(Local::now() - chrono::Duration::seconds(count * seconds));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    })
+    .ok_or_else(|| format!("cannot read {:?} as a time; try 2d or 2026-06-01", value))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("{:?} does not exist in the local time zone", value))
+}
+
+/// A size in bytes, with an optional binary suffix: `512`, `10K`, `1.5G`.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => value.split_at(i),
+        None => (value, ""),
+    };
+    let scale: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("unknown size unit in {:?}; use K, M, G or T", value)),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid size {:?}", value))?;
+    if number < 0.0 {
+        return Err(format!("invalid size {:?}", value));
+    }
+    Ok((number * scale as f64) as u64)
+}
+
+/// Put `records`, which arrive newest first, in `sort` order.
+pub fn sort_records(records: &mut [BundleRecord], sort: ListSort) {
+    match sort {
+        ListSort::Time => {}
+        ListSort::Size => records.sort_by_key(|r| std::cmp::Reverse(r.size)),
+        ListSort::Cwd => records.sort_by(|a, b| a.cwd.cmp(&b.cwd)),
+    }
+}
+
+/// What a listing reports about one bundle.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleRecord {
+    pub name: String,
+    pub path: PathBuf,
+    /// When the bundle was created: from its name, else its mtime.
+    pub timestamp: Option<DateTime<Local>>,
+    /// Who the bundle belongs to (the sudo caller under sudo).
+    pub user: Option<String>,
+    pub uid: Option<u32>,
+    pub host: Option<String>,
+    /// Absent when the metadata cannot be read, e.g. without the key.
+    pub cwd: Option<PathBuf>,
+    pub targets: Vec<String>,
+    /// Bytes the bundle occupies on disk.
+    pub size: u64,
+    /// Bytes the targets held when archived, if recorded.
+    pub original_size: Option<u64>,
+    /// Files archived, if recorded.
+    pub files: Option<u64>,
+    /// Whether cleanup keeps this bundle however old: it was pinned by hand,
+    /// or a later incremental bkup builds on it, which only the index, seeing
+    /// the whole space, can tell.
+    pub pinned: bool,
+    /// Best score of the search patterns, when there were any.
+    pub score: Option<i64>,
+    /// Where the best score came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<Match>,
+}
+
+impl BundleRecord {
+    /// The record of `bundle`, whose metadata has already been read (or
+    /// could not be).
+    pub fn from_metadata(bundle: &Path, metadata: Option<&Metadata>) -> Self {
+        let invoker = metadata.and_then(|m| m.provenance.user.as_ref());
+        let uid = crate::bundle_owner(bundle);
+        Self {
+            name: bundle.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            path: bundle.to_path_buf(),
+            timestamp: crate::bundle_created(bundle).map(DateTime::from),
+            user: invoker
+                .map(|i| i.owner_name().to_string())
+                .or_else(|| uid.and_then(crate::user::user_name)),
+            uid,
+            host: metadata.and_then(|m| m.provenance.host.clone()),
+            cwd: metadata.map(|m| m.cwd.clone()),
+            targets: metadata.map(|m| m.targets.clone()).unwrap_or_default(),
+            size: disk_usage(bundle),
+            original_size: metadata.and_then(|m| m.bytes),
+            files: metadata.and_then(|m| m.files),
+            pinned: crate::is_pinned(bundle),
+            score: None,
+            matched: None,
+        }
+    }
+}
+
+/// Bytes allocated to `path` and everything under it.
+pub fn disk_usage(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    let own = meta.blocks() * 512;
+    if !meta.is_dir() {
+        return own;
+    }
+    own + fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| disk_usage(&entry.path()))
+        .sum::<u64>()
+}
+
+/// Write `records` in a machine-readable `format`. Text listings are laid
+/// out by `format_directory` instead, and never reach here.
+pub fn write_records(records: &[BundleRecord], format: ListFormat, out: &mut impl Write) -> Result<()> {
+    match format {
+        ListFormat::Text => unreachable!("text listings are written by format_directory, not write_records"),
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)?;
+        }
+        ListFormat::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut *out, record)?;
+                writeln!(out)?;
+            }
+        }
+        ListFormat::Yaml => serde_yaml::to_writer(&mut *out, records)?,
+        ListFormat::Tsv => {
+            writeln!(
+                out,
+                "name\ttimestamp\tcwd\ttargets\tsize\toriginal_size\tfiles\tpinned\tscore\tuser\thost"
+            )?;
+            for r in records {
+                let fields = [
+                    r.name.clone(),
+                    r.timestamp.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                    r.cwd.as_ref().map(|c| c.display().to_string()).unwrap_or_default(),
+                    r.targets.join(","),
+                    r.size.to_string(),
+                    r.original_size.map(|s| s.to_string()).unwrap_or_default(),
+                    r.files.map(|f| f.to_string()).unwrap_or_default(),
+                    r.pinned.to_string(),
+                    r.score.map(|s| s.to_string()).unwrap_or_default(),
+                    r.user.clone().unwrap_or_default(),
+                    r.host.clone().unwrap_or_default(),
+                ];
+                let escaped: Vec<String> = fields.iter().map(|f| tsv_escape(f)).collect();
+                writeln!(out, "{}", escaped.join("\t"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write `records` as a table, one line per bundle, each followed by what
+/// its search pattern matched when `explain` is set.
+pub fn write_table(records: &[BundleRecord], now: SystemTime, explain: bool, out: &mut impl Write) -> io::Result<()> {
+    let header = ["AGE", "TIMESTAMP", "SIZE", "FILES", "CWD", "TARGETS"];
+    let rows: Vec<[String; 6]> = records
+        .iter()
+        .map(|r| {
+            [
+                crate::bundle_age(&r.path, now)
+                    .map(format_age)
+                    .unwrap_or_else(|| "-".to_string()),
+                r.name.clone(),
+                format_size(r.size),
+                r.files.map(|f| f.to_string()).unwrap_or_else(|| "-".to_string()),
+                r.cwd
+                    .as_ref()
+                    .map(|c| c.display().to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+                r.targets.join(", "),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: [String; 6]| {
+        let [age, timestamp, size, files, cwd, targets] = cells;
+        format!(
+            "{:>w0$}  {:<w1$}  {:>w2$}  {:>w3$}  {:<w4$}  {}",
+            age,
+            timestamp,
+            size,
+            files,
+            cwd,
+            targets,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        )
+    };
+    writeln!(out, "{}", line(header.map(str::to_string)).trim_end().bold())?;
+    for (record, row) in records.iter().zip(rows) {
+        writeln!(out, "{}", line(row).trim_end())?;
+        if let Some(matched) = record.matched.as_ref().filter(|_| explain) {
+            writeln!(out, "{:>w$}  {}", "", explanation(matched).dimmed(), w = widths[0])?;
+        }
+    }
+    Ok(())
+}
+
+/// What `--explain` says about a bundle's match.
+pub fn explanation(matched: &Match) -> String {
+    format!(
+        "matched {} {} (pattern {:?}, score {})",
+        matched.field, matched.text, matched.pattern, matched.score
+    )
+}
+
+/// The largest whole unit of `age`, e.g. `3d` or `40m`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// `size` in binary units with one decimal, like `ls -lh`.
+pub fn format_size(size: u64) -> String {
+    let mut value = size as f64;
+    for unit in ["", "K", "M", "G", "T"] {
+        if value < 1024.0 {
+            return match unit {
+                "" => format!("{}", size),
+                _ => format!("{:.1}{}", value, unit),
+            };
+        }
+        value /= 1024.0;
+    }
+    format!("{:.1}P", value)
+}
+
+/// Keep a field on one line and in one column.
+fn tsv_escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> BundleRecord {
+        BundleRecord {
+            name: "2026-06-14-153045-000".to_string(),
+            path: PathBuf::from("/var/tmp/rmrf/2026-06-14-153045-000"),
+            timestamp: None,
+            user: Some("me".to_string()),
+            uid: Some(1000),
+            host: Some("build01".to_string()),
+            cwd: Some(PathBuf::from("/home/me/odd\tdir")),
+            targets: vec!["a.txt".to_string(), "b.txt".to_string()],
+            size: 8192,
+            original_size: Some(12),
+            files: Some(2),
+            pinned: false,
+            score: Some(88),
+            matched: None,
+        }
+    }
+
+    #[test]
+    fn test_jsonl_has_one_typed_record_per_line() {
+        let mut out = Vec::new();
+        write_records(&[record(), record()], ListFormat::Jsonl, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["targets"][1], "b.txt");
+        assert_eq!(value["size"], 8192);
+        assert_eq!(value["score"], 88);
+        assert!(value["timestamp"].is_null());
+    }
+
+    #[test]
+    fn test_table_has_one_line_per_bundle() {
+        let mut small = record();
+        small.name = "2026-06-14-153046-000".to_string();
+        small.size = 100;
+        small.files = None;
+        let mut records = vec![record(), small];
+        sort_records(&mut records, ListSort::Size);
+
+        let mut out = Vec::new();
+        write_table(&records, SystemTime::now(), false, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("AGE") && lines[0].contains("TARGETS"));
+        assert!(lines[1].contains("2026-06-14-153045-000") && lines[1].contains("8.0K"));
+        assert!(lines[1].ends_with("a.txt, b.txt"));
+        assert!(lines[2].contains(" 100 "));
+        assert_eq!(lines[1].find("/home"), lines[2].find("/home"), "Columns line up");
+
+        records[0].matched = Some(Match {
+            field: crate::matching::Field::Cwd,
+            text: "/home/me".to_string(),
+            pattern: "home".to_string(),
+            score: 100,
+        });
+        let mut out = Vec::new();
+        write_table(&records, SystemTime::now(), true, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains(r#"matched cwd /home/me (pattern "home", score 100)"#));
+    }
+
+    #[test]
+    fn test_filters_combine() {
+        let mut r = record();
+        r.cwd = Some(PathBuf::from("/home/me/project"));
+        r.timestamp = Some(Local::now() - chrono::Duration::days(3));
+
+        assert!(ListFilters::default().matches(&r));
+        let filters = ListFilters {
+            since: Some(parse_when("1w").unwrap()),
+            until: Some(parse_when("1d").unwrap()),
+            min_size: Some(parse_size("4K").unwrap()),
+            max_size: Some(parse_size("1M").unwrap()),
+            cwd: Some(PathBuf::from("/home/me")),
+            target: Some("*.txt".to_string()),
+            user: Some("1000".to_string()),
+            host: Some("build01".to_string()),
+        };
+        assert!(filters.matches(&r));
+
+        for narrowed in [
+            ListFilters {
+                since: Some(parse_when("2d").unwrap()),
+                ..filters.clone()
+            },
+            ListFilters {
+                min_size: Some(parse_size("1M").unwrap()),
+                ..filters.clone()
+            },
+            ListFilters {
+                cwd: Some(PathBuf::from("/home/m")),
+                ..filters.clone()
+            },
+            ListFilters {
+                target: Some("/home/me/project/c*".to_string()),
+                ..filters.clone()
+            },
+            ListFilters {
+                user: Some("you".to_string()),
+                ..filters.clone()
+            },
+            ListFilters {
+                host: Some("build02".to_string()),
+                ..filters.clone()
+            },
+        ] {
+            assert!(!narrowed.matches(&r), "{:?}", narrowed);
+        }
+        let by_path = ListFilters {
+            target: Some("/home/me/project/a*".to_string()),
+            ..Default::default()
+        };
+        assert!(by_path.matches(&r));
+    }
+
+    #[test]
+    fn test_parse_when_and_size() {
+        let day = parse_when("2026-06-01").unwrap();
+        assert_eq!(day.format("%F %T").to_string(), "2026-06-01 00:00:00");
+        assert_eq!(
+            parse_when("2026-06-01T12:30").unwrap().format("%H:%M").to_string(),
+            "12:30"
+        );
+        let ago = Local::now() - parse_when("2d").unwrap();
+        assert!((ago - chrono::Duration::days(2)).num_seconds().abs() < 5);
+        assert!(parse_when("2y").is_err());
+        assert!(parse_when("yesterday").is_err());
+
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10K").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+        assert!(parse_size("10Q").is_err());
+    }
+
+    #[test]
+    fn test_human_units() {
+        assert_eq!(format_age(Duration::from_secs(59)), "59s");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_age(Duration::from_secs(86400 * 3 + 5)), "3d");
+        assert_eq!(format_size(512), "512");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0G");
+    }
+
+    #[test]
+    fn test_tsv_escapes_fields() {
+        let mut out = Vec::new();
+        write_records(&[record()], ListFormat::Tsv, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> = text.lines().map(|l| l.split('\t').collect()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), rows[1].len());
+        assert_eq!(rows[1][2], "/home/me/odd\\tdir");
+        assert_eq!(rows[1][3], "a.txt,b.txt");
+        assert_eq!(rows[1][5], "12");
+    }
+}
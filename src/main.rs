@@ -1,12 +1,12 @@
 // src/main.rs
 use libc::getuid;
 use log::{debug, info};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::OpenOptions;
-use std::fs::{self, DirEntry, File};
+use std::fs::{self, File};
 use std::io::{self, BufWriter, ErrorKind, Write};
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path::{Path, PathBuf};
 use std::process::{ChildStdin, Command, Stdio};
 use std::time::SystemTime;
@@ -14,21 +14,46 @@ use which::which;
 
 // Third-party crate imports
 use atty::Stream;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::*;
 use configparser::ini::Ini;
 use env_logger::Target;
 use eyre::{eyre, Context, Result};
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
 use serde::{Deserialize, Serialize};
 
 // Local modules
+mod browse;
+mod bundle_name;
 mod cli;
+mod compress;
 mod config;
-
+mod contents;
+mod crypt;
+mod diff;
+mod index;
+mod listing;
+mod manifest;
+mod matching;
+mod migrate;
+mod purge;
+mod schema;
+mod search;
+mod show;
+mod space;
+mod store;
+mod user;
+
+use bundle_name::BundleName;
+use cli::ListArgs;
 use cli::{Action, Cli};
+use compress::{Codec, Compression};
 use config::Config;
+use crypt::{BundleKey, EncryptionConfig, Envelope};
+use listing::{BundleRecord, ListFormat};
+use manifest::{FileManifest, FILE_MANIFEST};
+use matching::{MatchMode, Matcher};
+use store::{ChunkManifest, ChunkStore, StoreKind};
+use user::{Invoker, Provenance};
 
 static EZA_ARGS: &[&str] = &[
     "--tree",
@@ -42,14 +67,249 @@ static EZA_ARGS: &[&str] = &[
     "--ignore-glob=incremental",
 ];
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Flags that make tar capture everything it can about a file beyond its
+/// contents: pax headers (sub-second mtime, atime, long names), holes in
+/// sparse files, extended attributes, POSIX ACLs and SELinux labels. Hard
+/// links inside one tarball are always stored once.
+static TAR_CREATE_ARGS: &[&str] = &[
+    "--format=posix",
+    "--sparse",
+    "--xattrs",
+    "--xattrs-include=*",
+    "--acls",
+    "--selinux",
+];
+
+/// The extraction counterpart of [`TAR_CREATE_ARGS`]; `-p` keeps the archived
+/// mode bits instead of masking them with the caller's umask.
+static TAR_EXTRACT_ARGS: &[&str] = &["-p", "--xattrs", "--xattrs-include=*", "--acls", "--selinux"];
+
+/// YYYY-MM-DD-HHMMSS: colon-free so bundle directory names are safe on every
+/// filesystem (Windows/macOS included). Same-second collisions are resolved
+/// by the `-NNN` bundle suffix (see next_bundle_dir).
+const BUNDLE_TIMESTAMP: &str = "%Y-%m-%d-%H%M%S";
+
+/// Exit code used when some targets were processed and others failed.
+const EXIT_PARTIAL: i32 = 2;
+
+/// Bundle files that describe the bundle rather than hold archived data.
+static BOOKKEEPING_FILES: &[&str] = &[
+    "metadata.yml",
+    store::MANIFEST_FILE,
+    FILE_MANIFEST,
+    crypt::ENVELOPE_FILE,
+    PIN_FILE,
+];
+
+/// Marker of a bundle pinned by hand, which cleanup keeps however old.
+const PIN_FILE: &str = "pinned";
+
+/// Whether a bundle entry is bookkeeping, in plain or encrypted form.
+fn is_bookkeeping(name: &str) -> bool {
+    let name = name
+        .strip_suffix(crypt::ENCRYPTED_EXT)
+        .and_then(|n| n.strip_suffix('.'))
+        .unwrap_or(name);
+    BOOKKEEPING_FILES.contains(&name)
+}
+
+/// Whether `bundle` was pinned by hand.
+fn is_pinned(bundle: &Path) -> bool {
+    bundle.join(PIN_FILE).exists()
+}
+
+/// Pin `bundle` so cleanup keeps it, or unpin it.
+fn set_pin(bundle: &Path, pinned: bool) -> Result<()> {
+    let marker = bundle.join(PIN_FILE);
+    if pinned {
+        File::create(&marker).wrap_err_with(|| format!("pinning {}", bundle.display()))?;
+    } else if marker.exists() {
+        fs::remove_file(&marker).wrap_err_with(|| format!("unpinning {}", bundle.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 struct Metadata {
+    /// Layout version; see [`schema::SCHEMA_VERSION`].
+    #[serde(default)]
+    schema_version: u32,
     cwd: PathBuf,
     #[serde(default)]
     targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    links: Vec<LinkEntry>,
+    /// Targets that are hard links of one another, stored once per group.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hardlinks: Vec<Vec<String>>,
+    /// Targets with holes, stored sparsely rather than as runs of zeros.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sparse: Vec<String>,
+    /// FIFOs, sockets and device nodes, recorded here instead of being read.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    specials: Vec<SpecialEntry>,
+    /// Codec of the bundle's tarballs; bundles predating this field are gzip.
+    #[serde(default)]
+    compression: Codec,
+    #[serde(default, skip_serializing_if = "StoreKind::is_default")]
+    store: StoreKind,
+    /// Bundle this incremental bkup builds on; recovery replays the chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    /// Paths (relative to `cwd`) present in the parent but gone since.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deleted: Vec<String>,
+    /// Canonical paths of the targets, in `targets` order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<PathBuf>,
+    /// Files among the targets and their total size when archived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    files: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    #[serde(flatten)]
+    provenance: Provenance,
     contents: String,
 }
 
+/// A target that was archived as a symlink rather than through it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct LinkEntry {
+    /// Link name, relative to the bundle's `cwd`.
+    path: String,
+    /// Link contents exactly as returned by `readlink`.
+    target: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SpecialKind {
+    Fifo,
+    Socket,
+    Char,
+    Block,
+}
+
+impl SpecialKind {
+    fn of(file_type: &fs::FileType) -> Option<Self> {
+        if file_type.is_fifo() {
+            Some(Self::Fifo)
+        } else if file_type.is_socket() {
+            Some(Self::Socket)
+        } else if file_type.is_char_device() {
+            Some(Self::Char)
+        } else if file_type.is_block_device() {
+            Some(Self::Block)
+        } else {
+            None
+        }
+    }
+}
+
+/// A special file stored as metadata only: reading a FIFO can block forever
+/// and sockets and device nodes have no contents worth keeping.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SpecialEntry {
+    /// Path relative to the bundle's `cwd`.
+    path: String,
+    kind: SpecialKind,
+    /// Permission bits, without the file type.
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    major: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    minor: Option<u32>,
+}
+
+fn special_kind(path: &Path) -> Option<SpecialKind> {
+    fs::symlink_metadata(path)
+        .ok()
+        .and_then(|m| SpecialKind::of(&m.file_type()))
+}
+
+/// Resolve a target to the absolute path rkvr operates on. Without `follow`
+/// only the parent directory is canonicalized, so a symlink stays a symlink;
+/// with `follow` the whole path is resolved to whatever the link points at.
+fn resolve_target(target: &Path, follow: bool) -> io::Result<PathBuf> {
+    if follow {
+        return fs::canonicalize(target);
+    }
+
+    let name = match target.file_name() {
+        Some(name) => name,
+        None => return fs::canonicalize(target),
+    };
+    let parent = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => env::current_dir()?,
+    };
+
+    let resolved = parent.join(name);
+    fs::symlink_metadata(&resolved)?;
+    Ok(resolved)
+}
+
+/// `path` relative to `cwd` where possible, for recording in metadata.
+fn relative_name(path: &Path, cwd: &Path) -> String {
+    path.strip_prefix(cwd)
+        .map(|rel| rel.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.display().to_string())
+}
+
+/// A regular file whose allocated blocks cover less than its length.
+fn is_sparse(meta: &fs::Metadata) -> bool {
+    meta.file_type().is_file() && meta.blocks() * 512 < meta.len()
+}
+
+/// Group `paths` that share an inode. Only groups of two or more are returned.
+fn hard_link_groups(paths: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    let mut by_inode: HashMap<(u64, u64), Vec<PathBuf>> = HashMap::new();
+    for path in paths {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            if meta.file_type().is_file() && meta.nlink() > 1 {
+                by_inode.entry((meta.dev(), meta.ino())).or_default().push(path.clone());
+            }
+        }
+    }
+    let mut groups: Vec<_> = by_inode.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+    groups
+}
+
+/// `path` with its parent canonicalized; the last component is kept as is so
+/// a symlink target is recorded as the link, not what it points to.
+fn canonical_target(path: &Path) -> PathBuf {
+    match (path.parent().and_then(|p| p.canonicalize().ok()), path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Non-directory entries at or under `path`, without following symlinks,
+/// and their total size. Unreadable parts are skipped.
+fn count_files(path: &Path) -> (u64, u64) {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    if !meta.is_dir() {
+        return (1, meta.len());
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| count_files(&entry.path()))
+        .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
 fn as_paths(paths: &[String]) -> Vec<PathBuf> {
     paths
         .iter()
@@ -114,7 +374,7 @@ fn current_uid() -> u32 {
 }
 
 fn file_uid(path: &Path) -> eyre::Result<u32> {
-    Ok(fs::metadata(path)?.uid())
+    Ok(fs::symlink_metadata(path)?.uid())
 }
 
 fn remove_file_with_sudo(path: &Path, sudo: bool) -> Result<()> {
@@ -167,12 +427,26 @@ fn remove_directory_with_sudo(path: &Path, sudo: bool) -> Result<()> {
     Ok(())
 }
 
-fn cleanup(dir_path: &std::path::Path, days: usize, sudo: bool) -> Result<()> {
+/// When the bundle (or stray file) at `path` was created: from its name
+/// when it follows a known scheme, else from its modification time.
+fn bundle_created(path: &Path) -> Option<SystemTime> {
+    BundleName::of(path)
+        .and_then(|name| name.created_time())
+        .or_else(|| fs::symlink_metadata(path).and_then(|m| m.modified()).ok())
+}
+
+/// How long ago the bundle (or stray file) at `path` was created.
+fn bundle_age(path: &Path, now: SystemTime) -> Option<std::time::Duration> {
+    now.duration_since(bundle_created(path)?).ok()
+}
+
+fn cleanup(dir_path: &std::path::Path, days: usize, sudo: bool, scope: Scope) -> Result<()> {
     info!(
-        "fn cleanup: dir_path={} days={} sudo={}",
+        "fn cleanup: dir_path={} days={} sudo={} scope={:?}",
         dir_path.to_string_lossy(),
         days,
-        sudo
+        sudo,
+        scope
     );
 
     let now = SystemTime::now();
@@ -181,6 +455,23 @@ fn cleanup(dir_path: &std::path::Path, days: usize, sudo: bool) -> Result<()> {
     let delete_threshold = std::time::Duration::from_secs(60 * 60 * 24 * days as u64);
     debug!("Delete threshold duration: {:?} ({} days)", delete_threshold, days);
 
+    // Bundles a live incremental bkup still builds on are kept until the
+    // whole chain has expired.
+    let mut pinned = HashSet::new();
+    for entry in fs::read_dir(dir_path)?.flatten() {
+        let fresh = bundle_age(&entry.path(), now).is_some_and(|age| age <= delete_threshold);
+        if fresh {
+            let mut bundle = entry.path();
+            while let Some(parent) = bundle_parent(&bundle) {
+                bundle = bundle.with_file_name(parent);
+                if !pinned.insert(bundle.clone()) {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Pinned by incremental chains: {:?}", pinned);
+
     let entries = fs::read_dir(dir_path)?;
     debug!("Directory entries read: entries={:?}", entries);
 
@@ -188,24 +479,34 @@ fn cleanup(dir_path: &std::path::Path, days: usize, sudo: bool) -> Result<()> {
         let path = entry.path();
         debug!("Checking path: {}", path.to_string_lossy());
 
+        // Dot entries (the chunk store) are space bookkeeping, not bundles.
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        // Even with sudo, only the caller's own bundles are cleaned up.
+        if !scope.includes(&path) {
+            debug!("Skipping {}: belongs to another user", path.display());
+            continue;
+        }
+
         let metadata = fs::metadata(&path)?;
         debug!("Metadata retrieved");
 
-        let modified_time = metadata.modified()?;
-        debug!("Modified time: {:?}", modified_time);
-
-        if let Ok(duration_since_modified) = now.duration_since(modified_time) {
-            debug!(
-                "Duration since modified: {:?}, Delete threshold: {:?}",
-                duration_since_modified, delete_threshold
-            );
+        if let Some(age) = bundle_age(&path, now) {
+            debug!("Age: {:?}, Delete threshold: {:?}", age, delete_threshold);
 
-            if duration_since_modified > delete_threshold {
+            if age > delete_threshold && pinned.contains(&path) {
+                info!("Keeping {}: a newer incremental bkup builds on it", path.display());
+            } else if age > delete_threshold && is_pinned(&path) {
+                info!("Keeping {}: pinned", path.display());
+            } else if age > delete_threshold {
                 info!("Deleting path: {}", path.to_string_lossy());
 
                 if metadata.is_dir() {
                     debug!("Removing directory: {}", path.to_string_lossy());
                     remove_directory_with_sudo(&path, sudo)?;
+                    index::remove(&path);
                 } else {
                     debug!("Removing file: {}", path.to_string_lossy());
                     remove_file_with_sudo(&path, sudo)?;
@@ -214,10 +515,25 @@ fn cleanup(dir_path: &std::path::Path, days: usize, sudo: bool) -> Result<()> {
         }
     }
 
+    ChunkStore::for_space(dir_path).collect_garbage(dir_path)?;
+
     info!("Cleanup completed");
     Ok(())
 }
 
+/// Delete the chunks no bundle in `space` references any more. Nothing is
+/// lost when this fails, so the failure is reported rather than returned.
+fn collect_chunks(space: &Path) {
+    if let Err(error) = ChunkStore::for_space(space).collect_garbage(space) {
+        eprintln!(
+            "{} could not collect unused chunks in {}: {:#}",
+            "rkvr:".yellow(),
+            space.display(),
+            error
+        );
+    }
+}
+
 fn resolve_eza_path() -> Result<String> {
     // First try the normal which lookup
     if let Ok(path) = which("eza") {
@@ -244,12 +560,22 @@ fn resolve_eza_path() -> Result<String> {
     eyre::bail!("Could not find eza command. Please install eza: https://github.com/eza-community/eza")
 }
 
-fn create_metadata(base: &Path, cwd: &Path, targets: &[PathBuf]) -> Result<()> {
+fn create_metadata(
+    base: &Path,
+    cwd: &Path,
+    targets: &[PathBuf],
+    format: &PayloadFormat,
+    provenance: &Provenance,
+    increment: Option<&Increment>,
+) -> Result<()> {
     info!(
-        "fn create_metadata: base={} cwd={} targets={:?}",
+        "fn create_metadata: base={} cwd={} targets={:?} format={:?} provenance={:?} increment={:?}",
         base.display(),
         cwd.display(),
-        targets
+        targets,
+        format,
+        provenance,
+        increment
     );
 
     let eza_tree = resolve_eza_path()?;
@@ -271,19 +597,93 @@ fn create_metadata(base: &Path, cwd: &Path, targets: &[PathBuf]) -> Result<()> {
         })
         .collect();
 
+    let paths = targets.iter().map(|p| canonical_target(p)).collect();
+    let (files, bytes) = targets
+        .iter()
+        .map(|p| count_files(p))
+        .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    let links = targets
+        .iter()
+        .filter(|p| is_symlink(p))
+        .map(|p| {
+            Ok(LinkEntry {
+                path: relative_name(p, cwd),
+                target: fs::read_link(p).wrap_err_with(|| format!("Failed to read link {}", p.display()))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let specials = targets
+        .iter()
+        .filter_map(|p| {
+            let meta = fs::symlink_metadata(p).ok()?;
+            let kind = SpecialKind::of(&meta.file_type())?;
+            let device = matches!(kind, SpecialKind::Char | SpecialKind::Block);
+            Some(SpecialEntry {
+                path: relative_name(p, cwd),
+                kind,
+                mode: meta.mode() & 0o7777,
+                uid: meta.uid(),
+                gid: meta.gid(),
+                major: device.then(|| libc::major(meta.rdev() as libc::dev_t)),
+                minor: device.then(|| libc::minor(meta.rdev() as libc::dev_t)),
+            })
+        })
+        .collect();
+
+    let hardlinks = hard_link_groups(targets)
+        .iter()
+        .map(|group| group.iter().map(|p| relative_name(p, cwd)).collect())
+        .collect();
+
+    let sparse = targets
+        .iter()
+        .filter(|p| fs::symlink_metadata(p).is_ok_and(|m| is_sparse(&m)))
+        .map(|p| relative_name(p, cwd))
+        .collect();
+
     let metadata = Metadata {
+        schema_version: schema::SCHEMA_VERSION,
         cwd: cwd.to_path_buf(),
         contents: metadata_content.to_string(),
         targets: target_names,
+        links,
+        hardlinks,
+        sparse,
+        specials,
+        compression: format.compression.codec,
+        store: format.store,
+        parent: increment.map(|i| i.parent.clone()),
+        deleted: increment.map(|i| i.deleted.clone()).unwrap_or_default(),
+        paths,
+        files: Some(files),
+        bytes: Some(bytes),
+        provenance: provenance.clone(),
     };
 
+    if let Some(key) = &format.key {
+        Envelope {
+            key: key.reference.clone(),
+            parent: metadata.parent.clone(),
+            owner: metadata.provenance.user.as_ref().map(Invoker::owner_uid),
+        }
+        .save(base)?;
+    }
+
     let yaml_metadata = serde_yaml::to_string(&metadata).wrap_err("Failed to serialize metadata to YAML")?;
-    let metadata_path = base.join("metadata.yml");
-    fs::write(&metadata_path, yaml_metadata.as_bytes()).wrap_err("Failed to write metadata file")?;
+    crypt::write_bundle_file(base, "metadata.yml", yaml_metadata.as_bytes(), format.key.as_ref())
+        .wrap_err("Failed to write metadata file")?;
     Ok(())
 }
 
-fn create_tar_command(sudo: bool, tarball_path: &Path, cwd: &Path, targets: Vec<String>) -> Result<Command> {
+fn create_tar_command(
+    sudo: bool,
+    tarball_path: &Path,
+    cwd: &Path,
+    targets: Vec<String>,
+    compression: &Compression,
+) -> Result<Command> {
     let relative_targets: Vec<String> = targets
         .into_iter()
         .map(|t| {
@@ -304,26 +704,198 @@ fn create_tar_command(sudo: bool, tarball_path: &Path, cwd: &Path, targets: Vec<
         })
         .collect();
 
+    let compress_args = compression.tar_create_args()?;
+
     if sudo {
         let mut cmd = Command::new("sudo");
-        cmd.args([
-            "tar",
-            "-czf",
-            tarball_path.to_str().unwrap(),
-            "-C",
-            cwd.to_str().unwrap(),
-        ]);
+        cmd.arg("tar");
+        cmd.args(TAR_CREATE_ARGS);
+        cmd.args(&compress_args);
+        cmd.args(["-cf", tarball_path.to_str().unwrap(), "-C", cwd.to_str().unwrap()]);
         cmd.args(&relative_targets);
         Ok(cmd)
     } else {
         let mut cmd = Command::new("tar");
-        cmd.args(["-czf", tarball_path.to_str().unwrap(), "-C", cwd.to_str().unwrap()]);
+        cmd.args(TAR_CREATE_ARGS);
+        cmd.args(&compress_args);
+        cmd.args(["-cf", tarball_path.to_str().unwrap(), "-C", cwd.to_str().unwrap()]);
         cmd.args(&relative_targets);
         Ok(cmd)
     }
 }
 
-fn archive_directory(base: &Path, target: &PathBuf, sudo: bool, cwd: &Path) -> Result<()> {
+/// How the payloads and bookkeeping files of new bundles are written.
+#[derive(Debug, Default)]
+struct PayloadFormat {
+    compression: Compression,
+    store: StoreKind,
+    /// Encrypt everything but the envelope with this key.
+    key: Option<BundleKey>,
+}
+
+/// Write one tar payload of `targets` (relative to `cwd`) into the bundle at
+/// `base`: a compressed tarball named after `name` (encrypted under a random
+/// `<hex>.enc` name when the format has a key), or for a chunked space an
+/// uncompressed tar stream split into the space's chunk store.
+fn write_payload(
+    base: &Path,
+    name: &str,
+    targets: Vec<String>,
+    sudo: bool,
+    cwd: &Path,
+    format: &PayloadFormat,
+) -> Result<()> {
+    let compression = &format.compression;
+    match (format.store, &format.key) {
+        (StoreKind::Tarball, None) => {
+            let tarball_path = base.join(format!("{}.{}", name, compression.codec.extension()));
+            let mut cmd = create_tar_command(sudo, &tarball_path, cwd, targets, compression)?;
+            let status = cmd.status()?;
+            if !status.success() {
+                eyre::bail!("Failed to create {} (status {})", tarball_path.display(), status);
+            }
+        }
+        (StoreKind::Tarball, Some(key)) => {
+            let sealed_path = base.join(crypt::payload_name());
+            let mut cmd = create_tar_command(sudo, Path::new("-"), cwd, targets, compression)?;
+            let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+            let mut stdout = child
+                .stdout
+                .take()
+                .ok_or_else(|| eyre!("tar produced no output stream"))?;
+            let sealed = File::create(&sealed_path)
+                .map_err(eyre::Report::from)
+                .and_then(|file| key.encryptor(BufWriter::new(file)))
+                .and_then(|mut encryptor| {
+                    io::copy(&mut stdout, &mut encryptor)?;
+                    encryptor.finish()
+                });
+            drop(stdout);
+            let status = child.wait()?;
+            sealed.wrap_err_with(|| format!("Failed to write {}", sealed_path.display()))?;
+            if !status.success() {
+                eyre::bail!("Failed to create {} (status {})", sealed_path.display(), status);
+            }
+            // extract_sealed restores with `sudo tar --same-owner` only when
+            // the payload is not the caller's, as with a tarball `sudo tar`
+            // wrote itself. This one was written by rkvr, as the caller, so
+            // it is handed to root: otherwise files tar read under sudo
+            // would come back owned by the caller, or fail to extract.
+            if sudo {
+                let status = Command::new("sudo")
+                    .args(["chown", "0:0", sealed_path.to_str().unwrap()])
+                    .status()?;
+                if !status.success() {
+                    eyre::bail!("Failed to hand {} to root (status {})", sealed_path.display(), status);
+                }
+            }
+        }
+        (StoreKind::Chunked, None) => {
+            let uncompressed = Compression {
+                codec: Codec::None,
+                level: None,
+            };
+            let mut cmd = create_tar_command(sudo, Path::new("-"), cwd, targets, &uncompressed)?;
+            let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+            let stdout = child
+                .stdout
+                .take()
+                .ok_or_else(|| eyre!("tar produced no output stream"))?;
+            let ingested = ChunkStore::for_bundle(base)?.ingest(stdout, base, &format!("{name}.tar"), sudo);
+            let status = child.wait()?;
+            ingested?;
+            if !status.success() {
+                eyre::bail!("Failed to store {}.tar in the chunk store (status {})", name, status);
+            }
+        }
+        (StoreKind::Chunked, Some(_)) => {
+            eyre::bail!("Encryption cannot be combined with the chunked store");
+        }
+    }
+    Ok(())
+}
+
+/// Where an incremental bkup chains to and what it drops from its parent.
+#[derive(Debug)]
+struct Increment {
+    /// Name of the parent bundle in the same space.
+    parent: String,
+    deleted: Vec<String>,
+}
+
+/// The file manifest of a directory about to be bkup'd, plus what changed
+/// since its previous bkup. Without an `increment` the bkup is full and only
+/// starts a new chain.
+#[derive(Debug)]
+struct IncrementPlan {
+    manifest: FileManifest,
+    increment: Option<Increment>,
+    changed: Vec<String>,
+}
+
+/// The newest bundle in `space` holding a file manifest for `target`.
+fn find_parent_bundle(space: &Path, target: &Path) -> Result<Option<PathBuf>> {
+    let mut bundles: Vec<PathBuf> = fs::read_dir(space)?
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .filter(|p| crypt::bundle_file_exists(p, FILE_MANIFEST))
+        .collect();
+    bundles.sort_by_key(|b| std::cmp::Reverse(bundle_name::chronological(b)));
+
+    for bundle in bundles {
+        let Ok(meta) = load_metadata(&bundle) else {
+            continue;
+        };
+        if let [name] = meta.targets.as_slice() {
+            if meta.cwd.join(name) == target {
+                return Ok(Some(bundle));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn plan_increment(space: &Path, target: &Path, cwd: &Path) -> Result<IncrementPlan> {
+    let parent = find_parent_bundle(space, target)?;
+    let previous = parent.as_deref().map(FileManifest::load).transpose()?;
+    let manifest = FileManifest::scan(target, cwd, previous.as_ref())?;
+
+    let Some((parent, previous)) = parent.zip(previous) else {
+        info!("No previous bkup of {}; storing it in full", target.display());
+        return Ok(IncrementPlan {
+            manifest,
+            increment: None,
+            changed: vec![],
+        });
+    };
+
+    let delta = manifest.delta(&previous);
+    info!(
+        "Incremental bkup of {} on {}: {} changed, {} deleted",
+        target.display(),
+        parent.display(),
+        delta.changed.len(),
+        delta.deleted.len()
+    );
+    Ok(IncrementPlan {
+        manifest,
+        increment: Some(Increment {
+            parent: parent.file_name().unwrap().to_string_lossy().into_owned(),
+            deleted: delta.deleted,
+        }),
+        changed: delta.changed,
+    })
+}
+
+fn archive_directory(
+    base: &Path,
+    target: &PathBuf,
+    sudo: bool,
+    cwd: &Path,
+    format: &PayloadFormat,
+    plan: Option<&IncrementPlan>,
+) -> Result<()> {
     let owner = fs::metadata(target)?.uid();
     let need_sudo = owner != current_uid();
     if need_sudo && !sudo {
@@ -339,7 +911,6 @@ fn archive_directory(base: &Path, target: &PathBuf, sudo: bool, cwd: &Path) -> R
         .ok_or_else(|| eyre!("Failed to extract directory name"))?
         .to_string_lossy()
         .into_owned();
-    let tarball_path = base.join(format!("{}.tar.gz", dir_name));
 
     let rel = target
         .strip_prefix(cwd)
@@ -351,52 +922,116 @@ fn archive_directory(base: &Path, target: &PathBuf, sudo: bool, cwd: &Path) -> R
                 .unwrap_or_else(|| target.to_string_lossy().into_owned())
         });
 
-    let mut cmd = create_tar_command(need_sudo, &tarball_path, cwd, vec![rel])?;
-    let status = cmd.status()?;
-    if !status.success() {
-        eyre::bail!("Failed to archive {} (status {})", target.display(), status);
+    let Some(plan) = plan else {
+        return write_payload(base, &dir_name, vec![rel], need_sudo, cwd, format)
+            .wrap_err_with(|| format!("Failed to archive {}", target.display()));
+    };
+
+    plan.manifest.save(base, format.key.as_ref())?;
+    if plan.increment.is_none() {
+        return write_payload(base, &dir_name, vec![rel], need_sudo, cwd, format)
+            .wrap_err_with(|| format!("Failed to archive {}", target.display()));
     }
 
-    Ok(())
+    // Hand tar the exact entries through a NUL-separated list rather than
+    // argv, which large trees would overflow. The names are as private as
+    // the payload, so the list goes in a 0600 temp file outside the bundle,
+    // removed when dropped, never next to an encrypted payload.
+    let mut list = tempfile::Builder::new()
+        .prefix(".rkvr-files-from-")
+        .tempfile()
+        .wrap_err("Failed to create the list of changed files")?;
+    for entry in plan
+        .manifest
+        .directories()
+        .chain(plan.changed.iter().map(String::as_str))
+    {
+        list.write_all(entry.as_bytes())?;
+        list.write_all(&[0])?;
+    }
+    list.flush()?;
+    let tar_args = vec![
+        "--no-recursion".to_string(),
+        "--null".to_string(),
+        format!("--files-from={}", list.path().display()),
+    ];
+    write_payload(base, &dir_name, tar_args, need_sudo, cwd, format)
+        .wrap_err_with(|| format!("Failed to archive changes to {}", target.display()))
 }
 
 fn is_archive(path: &Path) -> bool {
     if let Some(ext) = path.extension().and_then(|e| e.to_str()).map(|s| s.to_lowercase()) {
-        matches!(ext.as_str(), "tar" | "gz" | "tgz" | "xz" | "zip" | "7z")
+        matches!(
+            ext.as_str(),
+            "tar" | "gz" | "tgz" | "xz" | "zst" | "tzst" | "lz4" | "zip" | "7z"
+        )
     } else {
         false
     }
 }
 
+/// Copy loose files into `base` with `cp -a`, which keeps mode, timestamps,
+/// ownership (where permitted), xattrs, ACLs and SELinux labels, and copies
+/// symlinks as links rather than through them. Holes in sparse files stay
+/// holes, and files that are hard links of one another are copied once and
+/// linked again at the destination.
 fn copy_files(base: &Path, loose: &[PathBuf], sudo: bool) -> Result<()> {
     let me = current_uid();
+    let mut copied: HashMap<(u64, u64), PathBuf> = HashMap::new();
     for src in loose {
         let fname = src.file_name().unwrap();
         let dest = base.join(fname);
-        let owner = fs::metadata(src)?.uid();
-        if owner == me {
-            fs::copy(src, &dest)?;
-            fs::set_permissions(&dest, fs::metadata(src)?.permissions())?;
-        } else {
-            if !sudo {
-                eyre::bail!(
-                    "Not permitted to copy {} (owned by uid={}); enable sudo in config",
-                    src.display(),
-                    owner
-                );
-            }
-            let status = Command::new("sudo")
-                .args(["cp", "-a", src.to_str().unwrap(), dest.to_str().unwrap()])
-                .status()?;
-            if !status.success() {
-                eyre::bail!("`sudo cp -a` failed with status {}", status);
+        let owner = file_uid(src)?;
+        if owner != me && !sudo {
+            eyre::bail!(
+                "Not permitted to copy {} (owned by uid={}); enable sudo in config",
+                src.display(),
+                owner
+            );
+        }
+
+        let meta = fs::symlink_metadata(src)?;
+        let inode = (meta.dev(), meta.ino());
+        if meta.file_type().is_file() && meta.nlink() > 1 {
+            if let Some(first) = copied.get(&inode) {
+                debug!("Hard linking {} to {}", dest.display(), first.display());
+                link_file(first, &dest, owner != me)?;
+                continue;
             }
+            copied.insert(inode, dest.clone());
+        }
+
+        let mut cmd = if owner == me {
+            Command::new("cp")
+        } else {
+            let mut cmd = Command::new("sudo");
+            cmd.arg("cp");
+            cmd
+        };
+        let status = cmd
+            .args(["-a", "--sparse=always", src.to_str().unwrap(), dest.to_str().unwrap()])
+            .status()?;
+        if !status.success() {
+            eyre::bail!("`cp -a {}` failed with status {}", src.display(), status);
         }
     }
     Ok(())
 }
 
-fn tar_gz_files(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path) -> Result<()> {
+fn link_file(existing: &Path, dest: &Path, sudo: bool) -> Result<()> {
+    if sudo {
+        let status = Command::new("sudo")
+            .args(["ln", existing.to_str().unwrap(), dest.to_str().unwrap()])
+            .status()?;
+        if !status.success() {
+            eyre::bail!("`sudo ln {}` failed with status {}", existing.display(), status);
+        }
+        return Ok(());
+    }
+    fs::hard_link(existing, dest).wrap_err_with(|| format!("Failed to hard link {}", dest.display()))
+}
+
+fn tar_files(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path, format: &PayloadFormat) -> Result<()> {
     let parent_name = group[0]
         .parent()
         .and_then(|p| p.file_name())
@@ -404,8 +1039,6 @@ fn tar_gz_files(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path) -> Resul
         .to_string_lossy()
         .into_owned();
 
-    let tarball_path = base.join(format!("{}.tar.gz", parent_name));
-
     let relative_targets: Vec<String> = group
         .iter()
         .map(|p| {
@@ -419,16 +1052,10 @@ fn tar_gz_files(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path) -> Resul
         })
         .collect();
 
-    let mut cmd = create_tar_command(sudo, &tarball_path, cwd, relative_targets)?;
-    let status = cmd.status()?;
-    if !status.success() {
-        eyre::bail!("Failed to create {} (status {})", tarball_path.display(), status);
-    }
-
-    Ok(())
+    write_payload(base, &parent_name, relative_targets, sudo, cwd, format)
 }
 
-fn archive_group(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path) -> Result<()> {
+fn archive_group(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path, format: &PayloadFormat) -> Result<()> {
     let need_sudo = group
         .iter()
         .map(|p| file_uid(p))
@@ -440,14 +1067,20 @@ fn archive_group(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path) -> Resu
         eyre::bail!("Found files owned by another user; re‑run with `sudo = yes` in your config");
     }
 
-    let (bundle, loose): (Vec<_>, Vec<_>) = group.iter().cloned().partition(|path| !is_archive(path));
+    // Special files live only in metadata.yml; never open them. Encrypted
+    // bundles hold no loose copies, so archives are tarred like any file.
+    let (bundle, loose): (Vec<_>, Vec<_>) = group
+        .iter()
+        .filter(|path| special_kind(path).is_none())
+        .cloned()
+        .partition(|path| format.key.is_some() || !is_archive(path));
 
     if !loose.is_empty() {
         copy_files(base, &loose, need_sudo)?;
     }
 
     if !bundle.is_empty() {
-        tar_gz_files(base, &bundle, need_sudo, cwd)?;
+        tar_files(base, &bundle, need_sudo, cwd, format)?;
     } else {
         debug!("No files to bundle for this group.");
     }
@@ -455,41 +1088,87 @@ fn archive_group(base: &Path, group: &[PathBuf], sudo: bool, cwd: &Path) -> Resu
     Ok(())
 }
 
-fn categorize_paths(targets: &[PathBuf], cwd: &Path) -> Result<(Vec<PathBuf>, Vec<Vec<PathBuf>>)> {
+/// A target that could not be archived or removed, kept so the caller can
+/// report it after processing everything else.
+#[derive(Debug)]
+struct TargetFailure {
+    target: PathBuf,
+    error: eyre::Report,
+}
+
+/// Outcome of one `archive` run: the targets that made it into a bundle and
+/// the ones that were skipped, each with its cause.
+#[derive(Debug, Default)]
+struct ArchiveReport {
+    archived: Vec<PathBuf>,
+    failures: Vec<TargetFailure>,
+}
+
+/// Targets split into directories and per-parent file groups, plus the ones
+/// that could not be categorized at all.
+#[derive(Debug, Default)]
+struct Categorized {
+    directories: Vec<PathBuf>,
+    groups: Vec<Vec<PathBuf>>,
+    failures: Vec<TargetFailure>,
+}
+
+/// Categorize `targets` for archiving. Symlinks are kept as links (and so
+/// grouped with files) unless `follow` is set. With `fail_fast` the first
+/// unusable target aborts the whole run; otherwise it is recorded as a failure
+/// and the remaining targets are still categorized.
+fn categorize_paths(targets: &[PathBuf], cwd: &Path, follow: bool, fail_fast: bool) -> Result<Categorized> {
     let mut directories = Vec::new();
     let mut file_groups_map: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut failures = Vec::new();
 
     let cwd_canonical = fs::canonicalize(cwd).wrap_err("Failed to canonicalize cwd")?;
     debug!("Canonicalized cwd: {}", cwd_canonical.display());
 
     for target in targets {
-        let canonical_path = fs::canonicalize(target).map_err(|e| {
-            if e.kind() == ErrorKind::NotFound {
-                eyre!("{}: No such file or directory", target.display())
-            } else {
-                eyre!("Failed to canonicalize target {}: {}", target.display(), e)
+        let target_path = match resolve_target(target, follow) {
+            Ok(path) => path,
+            Err(e) => {
+                let error = if e.kind() == ErrorKind::NotFound {
+                    eyre!("{}: No such file or directory", target.display())
+                } else {
+                    eyre!("Failed to resolve target {}: {}", target.display(), e)
+                };
+                if fail_fast {
+                    return Err(error);
+                }
+                failures.push(TargetFailure {
+                    target: target.clone(),
+                    error,
+                });
+                continue;
             }
-        })?;
-        debug!("Canonicalized target: {}", canonical_path.display());
+        };
+        debug!("Resolved target: {}", target_path.display());
 
-        let relative_path = match canonical_path.strip_prefix(&cwd_canonical) {
+        let relative_path = match target_path.strip_prefix(&cwd_canonical) {
             Ok(rel_path) => rel_path.to_path_buf(),
             Err(e) => {
-                debug!("Unable to strip prefix from path '{}': {}", canonical_path.display(), e);
-                canonical_path.clone()
+                debug!("Unable to strip prefix from path '{}': {}", target_path.display(), e);
+                target_path.clone()
             }
         };
         debug!("Relative path: {}", relative_path.display());
 
-        if canonical_path.is_dir() {
-            directories.push(canonical_path);
+        // symlink_metadata, so a link to a directory is archived as a link.
+        let file_type = fs::symlink_metadata(&target_path)?.file_type();
+        if file_type.is_dir() {
+            directories.push(target_path);
         } else {
+            if let Some(kind) = SpecialKind::of(&file_type) {
+                debug!("Special file ({:?}), metadata only: {}", kind, target_path.display());
+            }
             let group_key = relative_path
                 .parent()
                 .map(|p| cwd_canonical.join(p))
-                .unwrap_or_else(|| canonical_path.parent().unwrap().to_path_buf());
+                .unwrap_or_else(|| target_path.parent().unwrap().to_path_buf());
 
-            file_groups_map.entry(group_key).or_default().push(canonical_path);
+            file_groups_map.entry(group_key).or_default().push(target_path);
         }
     }
 
@@ -498,16 +1177,18 @@ fn categorize_paths(targets: &[PathBuf], cwd: &Path) -> Result<(Vec<PathBuf>, Ve
         groups.push(files);
     }
 
-    Ok((directories, groups))
+    Ok(Categorized {
+        directories,
+        groups,
+        failures,
+    })
 }
 
-fn remove_targets(targets: &[PathBuf]) -> Result<()> {
-    for target in targets {
-        if target.is_dir() {
-            fs::remove_dir_all(target)?;
-        } else {
-            fs::remove_file(target)?;
-        }
+fn remove_target(target: &Path) -> Result<()> {
+    if fs::symlink_metadata(target)?.is_dir() {
+        fs::remove_dir_all(target)?;
+    } else {
+        fs::remove_file(target)?;
     }
     Ok(())
 }
@@ -517,34 +1198,100 @@ fn remove_targets(targets: &[PathBuf]) -> Result<()> {
 /// unique both within one invocation and across two invocations that land in
 /// the same second, while preserving chronological string sorting.
 fn next_bundle_dir(path: &Path, timestamp: &str, index: &mut usize) -> PathBuf {
+    next_unreserved_bundle_dir(path, timestamp, index, &HashSet::new())
+}
+
+/// [`next_bundle_dir`] for callers that plan several names before creating
+/// any: names in `reserved` count as taken too.
+fn next_unreserved_bundle_dir(path: &Path, timestamp: &str, index: &mut usize, reserved: &HashSet<PathBuf>) -> PathBuf {
     loop {
         let candidate = path.join(format!("{timestamp}-{:03}", *index));
         *index += 1;
-        if !candidate.exists() {
+        if !candidate.exists() && !reserved.contains(&candidate) {
             return candidate;
         }
     }
 }
 
-fn archive(
-    path: &Path,
-    timestamp: &str,
-    targets: &[PathBuf],
+/// How `archive` treats its targets.
+#[derive(Debug, Clone, Default)]
+struct ArchiveOptions {
     sudo: bool,
+    /// Remove each target once it is safely in a bundle.
     remove: bool,
+    /// Run `cleanup` with this many days afterwards.
     keep: Option<i32>,
-) -> Result<()> {
+    /// Archive what symlinks point to rather than the links themselves.
+    follow: bool,
+    /// Abort on the first failing target instead of collecting failures.
+    fail_fast: bool,
+    compression: Compression,
+    store: StoreKind,
+    /// Store directories as changes against their previous bundle.
+    incremental: bool,
+    /// Encrypt new bundles with a key from this source.
+    encryption: Option<EncryptionConfig>,
+    /// Whose expired bundles the follow-up cleanup may delete.
+    scope: Scope,
+    /// The action recorded in each new bundle's provenance.
+    action: Option<&'static str>,
+}
+
+fn archive(path: &Path, timestamp: &str, targets: &[PathBuf], opts: &ArchiveOptions) -> Result<ArchiveReport> {
     debug!(
-        "fn archive: path={} timestamp={} targets={:?} sudo={} remove={} keep={:?}",
+        "fn archive: path={} timestamp={} targets={:?} opts={:?}",
         path.display(),
         timestamp,
         targets,
+        opts,
+    );
+    let ArchiveOptions {
         sudo,
         remove,
         keep,
-    );
+        follow,
+        fail_fast,
+        mut compression,
+        store,
+        incremental,
+        ref encryption,
+        scope,
+        action,
+    } = *opts;
+
+    // Chunks are compressed individually; compressing the tar stream first
+    // would defeat deduplication.
+    if store == StoreKind::Chunked {
+        compression = Compression {
+            codec: Codec::None,
+            level: None,
+        };
+        if encryption.is_some() {
+            eyre::bail!("Encryption cannot be combined with the chunked store");
+        }
+    }
+    let format = PayloadFormat {
+        compression,
+        store,
+        key: encryption.as_ref().map(BundleKey::create).transpose()?,
+    };
+    let provenance = Provenance::current(action);
     let current_cwd = env::current_dir().wrap_err("Failed to get current directory")?;
-    let (directories, groups) = categorize_paths(targets, &current_cwd)?;
+    let Categorized {
+        directories,
+        groups,
+        mut failures,
+    } = categorize_paths(targets, &current_cwd, follow, fail_fast)?;
+
+    // Map resolved paths back to the arguments they came from, so reports
+    // refer to what the user actually typed.
+    let originals: HashMap<PathBuf, PathBuf> = targets
+        .iter()
+        .filter_map(|t| resolve_target(t, follow).ok().map(|r| (r, t.clone())))
+        .collect();
+    let original = |p: &PathBuf| originals.get(p).cloned().unwrap_or_else(|| p.clone());
+
+    let mut archived = Vec::new();
 
     // Each bundle gets the shared timestamp plus a zero-padded index, so names
     // stay unique within one invocation and still sort chronologically as strings.
@@ -553,7 +1300,6 @@ fn archive(
     for group in groups.iter() {
         if !group.is_empty() {
             let base = next_bundle_dir(path, timestamp, &mut bundle_index);
-            fs::create_dir_all(&base).wrap_err("Failed to create base directory")?;
 
             let group_cwd = if let Some(first_file) = group.first() {
                 first_file.parent().unwrap_or(&current_cwd).to_path_buf()
@@ -561,11 +1307,29 @@ fn archive(
                 current_cwd.clone()
             };
 
-            create_metadata(&base, &group_cwd, group)?;
-            archive_group(&base, group, sudo, &group_cwd)?;
+            let result = space::create_private_dir(&base)
+                .wrap_err("Failed to create base directory")
+                .and_then(|_| create_metadata(&base, &group_cwd, group, &format, &provenance, None))
+                .and_then(|_| archive_group(&base, group, sudo, &group_cwd, &format));
+
+            if let Err(error) = result {
+                if fail_fast {
+                    return Err(error);
+                }
+                let _ = fs::remove_dir_all(&base);
+                for target in group {
+                    failures.push(TargetFailure {
+                        target: original(target),
+                        error: eyre!("{:#}", error),
+                    });
+                }
+                continue;
+            }
 
+            index::update(&base);
             for target in group {
-                println!("{}", target.display());
+                println!("{}", original(target).display());
+                archived.push(target.clone());
             }
             println!("-> {}/", base.display());
         }
@@ -573,25 +1337,106 @@ fn archive(
 
     for directory in directories.iter() {
         let base = next_bundle_dir(path, timestamp, &mut bundle_index);
-        fs::create_dir_all(&base).wrap_err("Failed to create base directory")?;
 
         let dir_cwd = directory.parent().unwrap_or(&current_cwd);
-        create_metadata(&base, dir_cwd, std::slice::from_ref(directory))?;
-        archive_directory(&base, directory, sudo, dir_cwd)?;
+        let result = space::create_private_dir(&base)
+            .wrap_err("Failed to create base directory")
+            .and_then(|_| {
+                incremental
+                    .then(|| plan_increment(path, directory, dir_cwd))
+                    .transpose()
+            })
+            .and_then(|plan| {
+                create_metadata(
+                    &base,
+                    dir_cwd,
+                    std::slice::from_ref(directory),
+                    &format,
+                    &provenance,
+                    plan.as_ref().and_then(|p| p.increment.as_ref()),
+                )?;
+                archive_directory(&base, directory, sudo, dir_cwd, &format, plan.as_ref())
+            });
 
-        println!("{}", directory.display());
+        if let Err(error) = result {
+            if fail_fast {
+                return Err(error);
+            }
+            let _ = fs::remove_dir_all(&base);
+            failures.push(TargetFailure {
+                target: original(directory),
+                error,
+            });
+            continue;
+        }
+
+        index::update(&base);
+        println!("{}", original(directory).display());
         println!("-> {}/", base.display());
+        archived.push(directory.clone());
     }
 
     if remove {
-        remove_targets(targets)?;
+        // Only targets that made it into a bundle are removed, and only the
+        // resolved path: without --follow that is the link, never its referent.
+        let mut removed = Vec::with_capacity(archived.len());
+        for target in archived {
+            match remove_target(&target) {
+                Ok(()) => removed.push(target),
+                Err(error) if fail_fast => return Err(error),
+                Err(error) => failures.push(TargetFailure {
+                    error: error.wrap_err(format!("archived but could not be removed: {}", target.display())),
+                    target: original(&target),
+                }),
+            }
+        }
+        archived = removed;
+    }
+
+    match keep {
+        // The targets are archived and removed by now; a failed cleanup must
+        // not hide that, so it is reported and the report still returned.
+        Some(days) => {
+            if let Err(error) = cleanup(path, days as usize, sudo, scope) {
+                info!("Cleanup of {} failed: {:#}", path.display(), error);
+                eprintln!("{} cleanup of {} failed: {:#}", "rkvr:".yellow(), path.display(), error);
+            }
+        }
+        // Chunks freed by rcvr or a purge are otherwise only collected by
+        // the next rmrf, and a bkup space may never see one.
+        None => collect_chunks(path),
+    }
+
+    Ok(ArchiveReport {
+        archived: archived.iter().map(original).collect(),
+        failures,
+    })
+}
+
+/// Print every per-target failure to stderr and turn the report into the
+/// process outcome: `Ok` when everything succeeded, exit code
+/// [`EXIT_PARTIAL`] when only some targets were processed, and an error when
+/// none were.
+fn finish_report(report: ArchiveReport) -> Result<()> {
+    if report.failures.is_empty() {
+        return Ok(());
     }
 
-    if let Some(days) = keep {
-        cleanup(path, days as usize, sudo)?;
+    for failure in &report.failures {
+        eprintln!("{} {}: {:#}", "rkvr:".red(), failure.target.display(), failure.error);
     }
 
-    Ok(())
+    if report.archived.is_empty() {
+        eyre::bail!("no targets were processed ({} failed)", report.failures.len());
+    }
+
+    eprintln!(
+        "{} {} processed, {} failed",
+        "rkvr:".yellow(),
+        report.archived.len(),
+        report.failures.len()
+    );
+    std::process::exit(EXIT_PARTIAL);
 }
 
 fn get_preferred_pager() -> String {
@@ -624,56 +1469,20 @@ where
     Ok(())
 }
 
-fn process_pattern(
-    matcher: &SkimMatcherV2,
-    dir_name: &str,
-    full_path: &Path,
-    pattern: &str,
-    threshold: i64,
-) -> Result<bool> {
-    if matcher.fuzzy_match(dir_name, pattern).is_some()
-        || matcher
-            .fuzzy_match(full_path.to_str().unwrap_or_default(), pattern)
-            .is_some()
-    {
-        return Ok(true);
-    }
-
-    let metadata_path = full_path.join("metadata.yml");
-    if metadata_path.exists() {
-        let metadata_content = std::fs::read_to_string(&metadata_path)?;
-        for line in metadata_content.lines() {
-            if let Some(score) = matcher.fuzzy_match(line, pattern) {
-                if score > threshold {
-                    return Ok(true);
-                }
-            }
-        }
-    }
-
-    Ok(false)
-}
-
-fn process_directory(matcher: &SkimMatcherV2, dir: &DirEntry, patterns: &[String], threshold: i64) -> Result<bool> {
-    let dir_name = dir.file_name().to_string_lossy().to_string();
-    let full_path = dir
-        .path()
-        .canonicalize()
-        .wrap_err("Failed to canonicalize directory path")?;
-
-    for pattern in patterns {
-        if process_pattern(matcher, &dir_name, &full_path, pattern, threshold)? {
-            return Ok(true);
-        }
-    }
-
-    Ok(patterns.is_empty())
-}
-
 fn format_directory(dir_path: &Path) -> Result<String> {
     let mut output = format!("{}", dir_path.display().to_string().bright_blue().bold());
-    let metadata_path = dir_path.join("metadata.yml");
-    if let Ok(metadata_content) = fs::read_to_string(&metadata_path) {
+    if let Some(date) = BundleName::of(dir_path).and_then(|name| name.display_date()) {
+        output += &format!(" {}", format!("({})", date).white());
+    }
+    let metadata_content = match crypt::read_bundle_file(dir_path, "metadata.yml") {
+        Ok(content) => Some(String::from_utf8_lossy(&content).into_owned()),
+        Err(error) if dir_path.join(crypt::ENVELOPE_FILE).exists() => {
+            output += &format!("\n  {}\n", format!("(encrypted: {:#})", error).yellow());
+            None
+        }
+        Err(_) => None,
+    };
+    if let Some(metadata_content) = metadata_content {
         let formatted_lines: Vec<String> = metadata_content
             .lines()
             .map(|line| {
@@ -703,105 +1512,620 @@ fn format_directory(dir_path: &Path) -> Result<String> {
     Ok(output)
 }
 
-fn list(dir_path: &Path, patterns: &[String], threshold: i64) -> Result<()> {
-    let matcher = SkimMatcherV2::default();
-    let dir_path = fs::canonicalize(dir_path).wrap_err("Failed to canonicalize directory path")?;
+fn list(spaces: &[PathBuf], args: &ListArgs, mode: MatchMode, threshold: i64, scope: Scope) -> Result<()> {
+    let patterns = &args.targets;
+    let matcher = Matcher::new(
+        args.match_mode.unwrap_or(mode),
+        patterns,
+        args.threshold.unwrap_or(threshold),
+    )?;
+
+    let mut bundles = Vec::new();
+    for space in spaces {
+        let space = fs::canonicalize(space).wrap_err("Failed to canonicalize directory path")?;
+        bundles.extend(
+            index::bundles(&space)?
+                .into_iter()
+                .filter(|bundle| scope.owns(bundle.record.uid)),
+        );
+    }
 
-    let mut dirs: Vec<_> = fs::read_dir(&dir_path)?
-        .filter_map(Result::ok)
-        .filter(|entry| entry.path().is_dir())
+    bundles.sort_by_key(|bundle| std::cmp::Reverse(bundle_name::chronological(&bundle.record.path)));
+
+    // Every bundle when there are no patterns, else those that match, with
+    // their score.
+    let filters = args.filters.resolved(&env::current_dir()?);
+    let mut records: Vec<BundleRecord> = bundles
+        .into_iter()
+        .filter_map(|bundle| {
+            let matched = if patterns.is_empty() {
+                None
+            } else {
+                Some(matcher.best(&bundle.record, bundle.contents.as_deref())?)
+            };
+            Some(BundleRecord {
+                score: matched.as_ref().map(|m| m.score),
+                matched,
+                ..bundle.record
+            })
+        })
+        .filter(|record| filters.matches(record))
         .collect();
+    listing::sort_records(&mut records, args.sort);
+    records.truncate(args.limit.unwrap_or(usize::MAX));
 
-    dirs.sort_by_key(|a| std::cmp::Reverse(a.file_name()));
+    if args.format != ListFormat::Text {
+        return listing::write_records(&records, args.format, &mut io::stdout().lock());
+    }
 
-    if atty::is(Stream::Stdout) {
-        use_pager(|writer: &mut BufWriter<ChildStdin>| -> Result<()> {
-            for dir in dirs.iter() {
-                if patterns.is_empty()
-                    || process_directory(&matcher, dir, patterns, threshold).map_err(io::Error::other)?
-                {
-                    let dir_output = format_directory(&dir.path()).map_err(io::Error::other)?;
-                    writer.write_all(dir_output.as_bytes())?;
-                    writer.write_all(b"\n")?;
-                }
-            }
-            Ok(())
-        })?;
-    } else {
-        for dir in dirs.iter() {
-            if patterns.is_empty() || process_directory(&matcher, dir, patterns, threshold)? {
-                let dir_output = format_directory(&dir.path())?;
-                println!("{}", dir_output);
+    let now = SystemTime::now();
+    let write = |writer: &mut dyn Write| -> io::Result<()> {
+        if !args.long {
+            return listing::write_table(&records, now, args.explain, &mut { writer });
+        }
+        for record in &records {
+            let mut dir_output = format_directory(&record.path).map_err(io::Error::other)?;
+            if let Some(matched) = record.matched.as_ref().filter(|_| args.explain) {
+                dir_output += &format!("  {}\n", listing::explanation(matched).dimmed());
             }
+            writer.write_all(dir_output.as_bytes())?;
+            writer.write_all(b"\n")?;
         }
+        Ok(())
+    };
+    if atty::is(Stream::Stdout) {
+        use_pager(|writer: &mut BufWriter<ChildStdin>| Ok(write(writer)?))?;
+    } else {
+        write(&mut io::stdout().lock())?;
     }
 
     Ok(())
 }
 
-fn extract_bundle(bundle: &Path, restore_to: &Path, sudo: bool) -> Result<()> {
+/// Build the tar command that extracts `source` (a path, or `-` for stdin)
+/// into `restore_to`, running under sudo and keeping owners when `use_sudo`.
+fn tar_extract_command(source: &Path, restore_to: &Path, use_sudo: bool, codec: Codec) -> Command {
+    let mut cmd = if use_sudo {
+        let mut cmd = Command::new("sudo");
+        cmd.arg("tar");
+        cmd
+    } else {
+        Command::new("tar")
+    };
+    cmd.args(TAR_EXTRACT_ARGS).args(codec.tar_extract_args()).args([
+        "-xf",
+        source.to_str().unwrap(),
+        "-C",
+        restore_to.to_str().unwrap(),
+    ]);
+    if use_sudo {
+        cmd.arg("--same-owner");
+    }
+    cmd
+}
+
+/// Extract one tarball into `restore_to`. The codec is detected from the
+/// file itself, falling back to `recorded` (from metadata.yml) when the
+/// tarball is not readable without sudo.
+fn extract_bundle(bundle: &Path, restore_to: &Path, sudo: bool, recorded: Codec) -> Result<()> {
     let owner = fs::metadata(bundle)?.uid();
     let me = current_uid();
+    let codec = Codec::detect(bundle).unwrap_or(recorded);
+    debug!("Extracting {} as {:?}", bundle.display(), codec);
 
-    let status = if owner != me {
-        if !sudo {
+    if owner != me && !sudo {
+        eyre::bail!(
+            "Cannot extract root-owned archive {} without sudo enabled",
+            bundle.display()
+        );
+    }
+    let status = tar_extract_command(bundle, restore_to, owner != me, codec).status()?;
+
+    if !status.success() {
+        eyre::bail!("tar extraction failed with status {}", status);
+    }
+    Ok(())
+}
+
+/// Decrypt one sealed tarball and stream it into tar.
+fn extract_sealed(sealed: &Path, restore_to: &Path, sudo: bool, codec: Codec, key: &BundleKey) -> Result<()> {
+    let use_sudo = fs::metadata(sealed)?.uid() != current_uid();
+    if use_sudo && !sudo {
+        eyre::bail!(
+            "Cannot extract root-owned archive {} without sudo enabled",
+            sealed.display()
+        );
+    }
+    let mut child = tar_extract_command(Path::new("-"), restore_to, use_sudo, codec)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre!("tar accepted no input stream"))?;
+    let decrypted = File::open(sealed)
+        .map_err(eyre::Report::from)
+        .and_then(|file| key.decrypt(io::BufReader::new(file), &mut stdin));
+    drop(stdin);
+    let status = child.wait()?;
+    decrypted.wrap_err_with(|| format!("Failed to decrypt {}", sealed.display()))?;
+    if !status.success() {
+        eyre::bail!("tar extraction of {} failed with status {}", sealed.display(), status);
+    }
+    Ok(())
+}
+
+/// Reassemble every payload of a chunked bundle and stream it into tar.
+fn extract_chunked(bundle: &Path, restore_to: &Path, sudo: bool) -> Result<()> {
+    let store = ChunkStore::for_bundle(bundle)?;
+    for payload in ChunkManifest::load(bundle)?.payloads {
+        info!("Extracting {} → {}", payload.name, restore_to.display());
+        if payload.privileged && !sudo {
             eyre::bail!(
-                "Cannot extract root-owned archive {} without sudo enabled",
-                bundle.display()
+                "Cannot extract {} (archived with sudo) without sudo enabled",
+                payload.name
             );
         }
-        Command::new("sudo")
+        let mut child = tar_extract_command(Path::new("-"), restore_to, payload.privileged, Codec::None)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| eyre!("tar accepted no input stream"))?;
+        let restored = store.restore(&payload, &mut stdin);
+        drop(stdin);
+        let status = child.wait()?;
+        restored?;
+        if !status.success() {
+            eyre::bail!("tar extraction of {} failed with status {}", payload.name, status);
+        }
+    }
+    Ok(())
+}
+
+/// Recreate a recorded symlink under `cwd` if extraction did not already do it.
+fn restore_link(cwd: &Path, link: &LinkEntry) -> Result<()> {
+    let dest = cwd.join(&link.path);
+    match fs::read_link(&dest) {
+        Ok(existing) if existing == link.target => return Ok(()),
+        Ok(_) => fs::remove_file(&dest)?,
+        Err(_) if fs::symlink_metadata(&dest).is_ok() => {
+            eyre::bail!("{} exists and is not a symlink; not replacing it", dest.display());
+        }
+        Err(_) => {}
+    }
+    info!("Relinking {} → {}", dest.display(), link.target.display());
+    std::os::unix::fs::symlink(&link.target, &dest)
+        .wrap_err_with(|| format!("Failed to recreate link {}", dest.display()))?;
+    Ok(())
+}
+
+/// Recreate a special file recorded in metadata. Failures are reported but
+/// not fatal: an unprivileged user cannot create device nodes, and the rest
+/// of the bundle should still be restored.
+fn restore_special(cwd: &Path, special: &SpecialEntry, sudo: bool) {
+    let dest = cwd.join(&special.path);
+    if fs::symlink_metadata(&dest).is_ok() {
+        debug!("{} already exists; leaving it alone", dest.display());
+        return;
+    }
+    info!("Recreating {:?} {}", special.kind, dest.display());
+    if let Err(e) = create_special(&dest, special, sudo) {
+        eprintln!("{} could not recreate {}: {:#}", "rkvr:".yellow(), dest.display(), e);
+    }
+}
+
+fn create_special(dest: &Path, special: &SpecialEntry, sudo: bool) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let c_path = std::ffi::CString::new(dest.as_os_str().as_encoded_bytes())?;
+    let device = |kind: libc::mode_t| {
+        let dev = libc::makedev(special.major.unwrap_or(0), special.minor.unwrap_or(0));
+        unsafe { libc::mknod(c_path.as_ptr(), kind | special.mode as libc::mode_t, dev) }
+    };
+    let rc = match special.kind {
+        SpecialKind::Fifo => unsafe { libc::mkfifo(c_path.as_ptr(), special.mode as libc::mode_t) },
+        SpecialKind::Socket => {
+            // Binding leaves the socket inode behind once the listener is dropped.
+            drop(std::os::unix::net::UnixListener::bind(dest)?);
+            0
+        }
+        SpecialKind::Char => device(libc::S_IFCHR),
+        SpecialKind::Block => device(libc::S_IFBLK),
+    };
+
+    if rc != 0 {
+        let err = io::Error::last_os_error();
+        let is_device = matches!(special.kind, SpecialKind::Char | SpecialKind::Block);
+        if !(is_device && sudo && err.kind() == ErrorKind::PermissionDenied) {
+            return Err(err.into());
+        }
+        let kind = if special.kind == SpecialKind::Char { "c" } else { "b" };
+        let status = Command::new("sudo")
             .args([
-                "tar",
-                "xpf",
-                bundle.to_str().unwrap(),
-                "-C",
-                restore_to.to_str().unwrap(),
-                "--same-owner",
+                "mknod",
+                "-m",
+                &format!("{:o}", special.mode),
+                dest.to_str().unwrap(),
+                kind,
+                &special.major.unwrap_or(0).to_string(),
+                &special.minor.unwrap_or(0).to_string(),
             ])
-            .status()?
-    } else {
-        Command::new("tar")
-            .args(["xzf", bundle.to_str().unwrap(), "-C", restore_to.to_str().unwrap()])
-            .status()?
+            .status()?;
+        if !status.success() {
+            eyre::bail!("`sudo mknod` failed with status {}", status);
+        }
+        return Ok(());
+    }
+
+    // mkfifo/mknod honour the umask, so set the recorded mode explicitly.
+    fs::set_permissions(dest, fs::Permissions::from_mode(special.mode))?;
+    if current_uid() == 0 {
+        let _ = std::os::unix::fs::lchown(dest, Some(special.uid), Some(special.gid));
+    }
+    Ok(())
+}
+
+fn load_metadata(bundle: &Path) -> Result<Metadata> {
+    let contents = crypt::read_bundle_file(bundle, "metadata.yml")?;
+    Ok(schema::parse(bundle, &contents)?.0)
+}
+
+/// The parent of an incremental bundle. Encrypted bundles carry it in their
+/// envelope, so cleanup never needs their key.
+fn bundle_parent(bundle: &Path) -> Option<String> {
+    match Envelope::load(bundle) {
+        Ok(Some(envelope)) => envelope.parent,
+        _ => load_metadata(bundle).ok()?.parent,
+    }
+}
+
+/// `bundle` preceded by every ancestor it was bkup'd incrementally against,
+/// oldest first.
+fn bundle_chain(bundle: &Path) -> Result<Vec<(PathBuf, Metadata)>> {
+    let mut chain = vec![(bundle.to_path_buf(), load_metadata(bundle)?)];
+    while let Some(parent) = chain.last().unwrap().1.parent.clone() {
+        let child = &chain.last().unwrap().0;
+        let parent_dir = child.with_file_name(&parent);
+        if !parent_dir.is_dir() || chain.iter().any(|(dir, _)| *dir == parent_dir) {
+            eyre::bail!(
+                "Parent bundle {} of {} is missing; cannot reconstruct it",
+                parent,
+                child.display()
+            );
+        }
+        let meta = load_metadata(&parent_dir)?;
+        chain.push((parent_dir, meta));
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// The uid a bundle belongs to: the recorded creator (the sudo caller under
+/// sudo), or for older bundles the owner of the bundle directory.
+/// Who a bundle belongs to: the owner its envelope or provenance records.
+/// Bundles from before provenance was recorded belong to whoever owns their
+/// directory, except root-owned ones: `sudo rkvr` wrote those for the owner
+/// of the space they are in, who can clean them up like any other.
+fn bundle_owner(bundle: &Path) -> Option<u32> {
+    let recorded = match Envelope::load(bundle) {
+        Ok(Some(envelope)) => envelope.owner,
+        _ => load_metadata(bundle)
+            .ok()
+            .and_then(|m| m.provenance.user)
+            .map(|u| u.owner_uid()),
     };
+    recorded.or_else(|| match fs::symlink_metadata(bundle).ok()?.uid() {
+        0 => bundle
+            .parent()
+            .and_then(|space| fs::metadata(space).ok())
+            .map(|m| m.uid()),
+        uid => Some(uid),
+    })
+}
 
-    if !status.success() {
-        eyre::bail!("tar extraction failed with status {}", status);
+/// Whose bundles an invocation sees and may touch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Only bundles belonging to this uid.
+    User(u32),
+    /// Everyone's; root only, via `--all-users`.
+    AllUsers,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope::User(Invoker::current().owner_uid())
+    }
+}
+
+impl Scope {
+    fn includes(self, bundle: &Path) -> bool {
+        self == Scope::AllUsers || self.owns(bundle_owner(bundle))
+    }
+
+    /// Whether a bundle belonging to `owner` is in scope.
+    fn owns(self, owner: Option<u32>) -> bool {
+        match self {
+            Scope::AllUsers => true,
+            Scope::User(uid) => owner == Some(uid),
+        }
+    }
+}
+
+/// Whether another bundle in the same space builds on `bundle`.
+fn has_children(bundle: &Path) -> bool {
+    let (Some(space), Some(name)) = (bundle.parent(), bundle.file_name()) else {
+        return false;
+    };
+    fs::read_dir(space)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path() != bundle)
+        .any(|e| bundle_parent(&e.path()).as_deref() == name.to_str())
+}
+
+/// Put one bundle's contents back under its `cwd`.
+fn restore_bundle(ts_dir: &Path, meta: &Metadata, sudo: bool) -> Result<()> {
+    let cwd = &meta.cwd;
+    let originals = &meta.targets;
+
+    let (to_copy, to_extract): (Vec<PathBuf>, Vec<PathBuf>) = fs::read_dir(ts_dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| !is_bookkeeping(p.file_name().and_then(|n| n.to_str()).unwrap_or_default()))
+        .partition(|p| {
+            let fname = p.file_name().unwrap().to_string_lossy();
+            originals.iter().any(|t| t == &fname)
+        });
+
+    if meta.store == StoreKind::Chunked {
+        extract_chunked(ts_dir, cwd, sudo)?;
+    }
+
+    let key = match Envelope::load(ts_dir)? {
+        Some(envelope) => Some(BundleKey::open(&envelope.key)?),
+        None => None,
+    };
+    for bundle in to_extract {
+        info!("Extracting {} → {}", bundle.display(), cwd.display());
+        match &key {
+            Some(key) => extract_sealed(&bundle, cwd, sudo, meta.compression, key)?,
+            None => extract_bundle(&bundle, cwd, sudo, meta.compression)?,
+        }
+    }
+
+    // One call for all loose files, so hard links between them are recreated.
+    for src in &to_copy {
+        info!("Restoring {} → {}", src.display(), cwd.display());
+    }
+    copy_files(cwd, &to_copy, sudo)?;
+
+    for link in &meta.links {
+        restore_link(cwd, link)?;
+    }
+
+    for special in &meta.specials {
+        restore_special(cwd, special, sudo);
+    }
+
+    for path in &meta.deleted {
+        let path = cwd.join(path);
+        match remove_target(&path) {
+            Ok(()) => debug!("Removed {} (deleted before this bkup)", path.display()),
+            Err(error) if !path.exists() && !is_symlink(&path) => debug!("{} already gone: {}", path.display(), error),
+            Err(error) => return Err(error.wrap_err(format!("Failed to remove deleted path {}", path.display()))),
+        }
     }
     Ok(())
 }
 
-fn recover(root: &Path, ts_dirs: &[PathBuf], sudo: bool) -> Result<()> {
-    for ts in ts_dirs {
-        let ts_path = if ts.is_absolute() { ts.clone() } else { root.join(ts) };
+/// Restore `bundle`. Incremental bundles only hold changes, so the chain is
+/// replayed from the full bkup it started with.
+fn restore_chain(bundle: &Path, sudo: bool) -> Result<()> {
+    for (dir, meta) in bundle_chain(bundle)? {
+        restore_bundle(&dir, &meta, sudo)?;
+    }
+    Ok(())
+}
+
+/// Restore the bundles `ts_dirs` names, looked up in `spaces` in order, and
+/// remove those no later incremental bkup builds on.
+fn recover(spaces: &[PathBuf], ts_dirs: &[PathBuf], sudo: bool, scope: Scope) -> Result<()> {
+    // Oldest first, so where bundles overlap the newest version wins.
+    let mut bundles = resolve_bundles(spaces, ts_dirs)?;
+    bundles.sort_by_key(|b| bundle_name::chronological(b));
+    let mut emptied = HashSet::new();
+    for ts_path in bundles {
         let ts_dir = ts_path.canonicalize().wrap_err("canonicalizing timestamp dir")?;
+        if !scope.includes(&ts_dir) {
+            eyre::bail!("{} belongs to another user; root can use --all-users", ts_dir.display());
+        }
 
-        let meta_path = ts_dir.join("metadata.yml");
-        let meta: Metadata = serde_yaml::from_reader(File::open(&meta_path).wrap_err("opening metadata.yml")?)
-            .wrap_err("parsing metadata.yml")?;
-        let cwd = meta.cwd;
-        let originals = &meta.targets;
-
-        let (to_copy, to_extract): (Vec<PathBuf>, Vec<PathBuf>) = fs::read_dir(&ts_dir)?
-            .filter_map(|e| e.ok().map(|e| e.path()))
-            .filter(|p| p.file_name().and_then(|n| n.to_str()) != Some("metadata.yml"))
-            .partition(|p| {
-                let fname = p.file_name().unwrap().to_string_lossy();
-                originals.iter().any(|t| t == &fname)
-            });
+        restore_chain(&ts_dir, sudo)?;
 
-        for bundle in to_extract {
-            info!("Extracting {} → {}", bundle.display(), cwd.display());
-            extract_bundle(&bundle, &cwd, sudo)?;
+        if has_children(&ts_dir) {
+            info!("Keeping {}: later incremental bkups build on it", ts_dir.display());
+            continue;
         }
+        fs::remove_dir_all(&ts_dir).wrap_err_with(|| format!("removing {}", ts_dir.display()))?;
+        index::remove(&ts_dir);
+        emptied.extend(ts_dir.parent().map(Path::to_path_buf));
+    }
+    for space in emptied {
+        collect_chunks(&space);
+    }
+    Ok(())
+}
+
+/// Bundle directories directly under `space`, oldest first.
+fn bundle_dirs(space: &Path) -> Result<Vec<PathBuf>> {
+    let mut bundles: Vec<PathBuf> = fs::read_dir(space)?
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    bundles.sort_by_key(|b| bundle_name::chronological(b));
+    Ok(bundles)
+}
+
+/// List a tarball, optionally sealed, to prove it decompresses and (for
+/// sealed ones) authenticates to the end.
+fn verify_tarball(path: &Path, codec: Codec, key: Option<&BundleKey>) -> Result<()> {
+    let mut child = Command::new("tar")
+        .args(codec.tar_extract_args())
+        .args(["-tf", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre!("tar accepted no input stream"))?;
+    let fed = File::open(path).map_err(eyre::Report::from).and_then(|file| match key {
+        Some(key) => key.decrypt(io::BufReader::new(file), &mut stdin),
+        None => io::copy(&mut io::BufReader::new(file), &mut stdin)
+            .map(|_| ())
+            .map_err(eyre::Report::from),
+    });
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    fed?;
+    if !output.status.success() {
+        eyre::bail!("tar cannot read it: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Check that a bundle's metadata and every payload can be read back in full.
+fn verify_bundle(bundle: &Path) -> Result<()> {
+    let meta = load_metadata(bundle)?;
+    let key = match Envelope::load(bundle)? {
+        Some(envelope) => Some(BundleKey::open(&envelope.key)?),
+        None => None,
+    };
+    if crypt::bundle_file_exists(bundle, FILE_MANIFEST) {
+        FileManifest::load(bundle)?;
+    }
 
-        for src in to_copy {
-            info!("Restoring {} → {}", src.display(), cwd.display());
-            copy_files(&cwd, &[src], sudo)?;
+    if meta.store == StoreKind::Chunked {
+        let store = ChunkStore::for_bundle(bundle)?;
+        for payload in ChunkManifest::load(bundle)?.payloads {
+            store
+                .restore(&payload, &mut io::sink())
+                .wrap_err_with(|| format!("payload {}", payload.name))?;
         }
+    }
 
-        fs::remove_dir_all(&ts_dir).wrap_err_with(|| format!("removing {}", ts_dir.display()))?;
+    for entry in fs::read_dir(bundle)?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_bookkeeping(&name) || meta.targets.contains(&name) {
+            continue;
+        }
+        let path = entry.path();
+        let codec = match key {
+            Some(_) => meta.compression,
+            None => Codec::detect(&path).unwrap_or(meta.compression),
+        };
+        verify_tarball(&path, codec, key.as_ref()).wrap_err_with(|| format!("payload {}", name))?;
+    }
+    Ok(())
+}
+
+/// Find each named bundle: absolute names as given, others in the first
+/// of `spaces` that has them.
+fn resolve_bundles(spaces: &[PathBuf], names: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    names
+        .iter()
+        .map(|name| {
+            if name.is_absolute() {
+                return Ok(name.clone());
+            }
+            spaces
+                .iter()
+                .map(|space| space.join(name))
+                .find(|p| p.is_dir())
+                .ok_or_else(|| eyre!("No bundle named {}", name.display()))
+        })
+        .collect()
+}
+
+/// The one bundle `spec` names among those of `spaces` within `scope`: an
+/// absolute path, `@N` for the Nth newest, a bundle name, or a prefix of a
+/// single bundle's name. Indexes carry the `@` because a bare number such as
+/// `2026` is a prefix of names.
+fn find_bundle(spaces: &[PathBuf], spec: &str, scope: Scope) -> Result<PathBuf> {
+    if Path::new(spec).is_absolute() {
+        return Ok(PathBuf::from(spec));
+    }
+    let mut bundles = Vec::new();
+    for space in spaces {
+        bundles.extend(bundle_dirs(space)?.into_iter().filter(|b| scope.includes(b)));
+    }
+    bundles.sort_by_key(|b| std::cmp::Reverse(bundle_name::chronological(b)));
+
+    if let Some(index) = spec.strip_prefix('@') {
+        let n: usize = index
+            .parse()
+            .map_err(|_| eyre!("{} is not an index; @N names the Nth newest bundle", spec))?;
+        return bundles
+            .get(n.wrapping_sub(1))
+            .cloned()
+            .ok_or_else(|| eyre!("No bundle matches {}", spec));
+    }
+
+    let name = |b: &PathBuf| b.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    if let Some(bundle) = bundles.iter().find(|b| name(b) == spec) {
+        return Ok(bundle.clone());
+    }
+    let matching: Vec<&PathBuf> = bundles.iter().filter(|b| name(b).starts_with(spec)).collect();
+    match matching[..] {
+        [bundle] => Ok(bundle.clone()),
+        [] => Err(eyre!("No bundle matches {}", spec)),
+        _ => Err(eyre!(
+            "{} matches {} bundles: {}",
+            spec,
+            matching.len(),
+            matching.iter().map(|b| name(b)).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Verify the named bundles (relative to any space, or absolute), or every
+/// bundle in `spaces` within `scope` when none are named.
+fn verify(spaces: &[PathBuf], names: &[PathBuf], scope: Scope) -> Result<()> {
+    let bundles = if names.is_empty() {
+        let mut all = Vec::new();
+        for space in spaces {
+            all.extend(bundle_dirs(space)?.into_iter().filter(|b| scope.includes(b)));
+        }
+        all
+    } else {
+        let bundles = resolve_bundles(spaces, names)?;
+        if let Some(foreign) = bundles.iter().find(|b| !scope.includes(b)) {
+            eyre::bail!(
+                "{} belongs to another user; root can use --all-users",
+                foreign.display()
+            );
+        }
+        bundles
+    };
+
+    let mut failed = 0;
+    for bundle in &bundles {
+        match verify_bundle(bundle) {
+            Ok(()) => println!("{} {}", "ok".green(), bundle.display()),
+            Err(error) => {
+                failed += 1;
+                eprintln!("{} {}: {:#}", "FAILED".red(), bundle.display(), error);
+            }
+        }
+    }
+    if failed > 0 {
+        eyre::bail!("{} of {} bundles failed verification", failed, bundles.len());
     }
     Ok(())
 }
@@ -815,10 +2139,7 @@ fn main() -> Result<()> {
     let current_level = log::max_level();
     debug!("Current log level: {:?}", current_level);
 
-    // YYYY-MM-DD-HHMMSS: colon-free so the directory name is safe on every
-    // filesystem (Windows/macOS included). Same-second collisions are resolved
-    // by the `-NNN` bundle suffix (see next_bundle_dir).
-    let timestamp = chrono::Local::now().format("%Y-%m-%d-%H%M%S").to_string();
+    let timestamp = chrono::Local::now().format(BUNDLE_TIMESTAMP).to_string();
     debug!("Current timestamp: {}", timestamp);
 
     let matches = Cli::parse_from(args);
@@ -843,15 +2164,13 @@ fn main() -> Result<()> {
         .wrap_err("Failed to load config")?;
     debug!("Configuration loaded: {:?}", rmrf_cfg);
 
-    let rmrf_path = rmrf_cfg
+    let rmrf_root = rmrf_cfg
         .get("DEFAULT", "rmrf_path")
         .unwrap_or("/var/tmp/rmrf".to_owned());
-    let rmrf_path = Path::new(&rmrf_path);
 
-    let bkup_path = rmrf_cfg
+    let bkup_root = rmrf_cfg
         .get("DEFAULT", "bkup_path")
         .unwrap_or("/var/tmp/bkup".to_owned());
-    let bkup_path = Path::new(&bkup_path);
 
     let sudo: bool = rmrf_cfg.get("DEFAULT", "sudo").unwrap_or("yes".to_owned()) == "yes";
     let days: i32 = rmrf_cfg
@@ -862,46 +2181,173 @@ fn main() -> Result<()> {
         .get("DEFAULT", "threshold")
         .unwrap_or("70".to_owned())
         .parse()?;
+    // Thresholds were once raw skim scores, which run well past 100; as a
+    // percentage such a value would silently match nothing.
+    let threshold = if threshold > 100 {
+        eprintln!(
+            "{} threshold = {} in {} is above 100; it is a percentage of a perfect match now, so 100 is used",
+            "rkvr:".yellow(),
+            threshold,
+            rmrf_cfg_path.display()
+        );
+        100
+    } else {
+        threshold
+    };
+    let match_mode = match rmrf_cfg.get("DEFAULT", "match") {
+        Some(mode) => MatchMode::from_str(&mode, true).map_err(|e| eyre!("match = {}: {}", mode, e))?,
+        None => MatchMode::default(),
+    };
 
     info!(
-        "Configuration - rmrf_path: {:?}, bkup_path: {:?}, sudo: {}, keep for days: {}, threshold: {}",
-        rmrf_path, bkup_path, sudo, days, threshold,
+        "Configuration - rmrf_path: {:?}, bkup_path: {:?}, sudo: {}, keep for days: {}, match: {:?}, threshold: {}",
+        rmrf_root, bkup_root, sudo, days, match_mode, threshold,
     );
 
-    fs::create_dir_all(rmrf_path)?;
-    fs::create_dir_all(bkup_path)?;
+    let invoker = Invoker::current();
+    info!("Invoked by {} (uid={})", invoker.owner_name(), invoker.owner_uid());
+    let scope = if matches.all_users {
+        if invoker.uid != 0 {
+            eyre::bail!("--all-users requires root");
+        }
+        Scope::AllUsers
+    } else {
+        Scope::User(invoker.owner_uid())
+    };
+
+    // A bad --level is reported before any space is created or touched.
+    let rmrf_compression = config.compression_for(&config.rmrf, matches.compress, matches.level)?;
+    let bkup_compression = config.compression_for(&config.bkup, matches.compress, matches.level)?;
+
+    let prepare = |root: &str| {
+        space::prepare(
+            Path::new(root),
+            config.per_user_spaces,
+            config.space_permissions,
+            invoker.owner_uid(),
+        )
+    };
+    let rmrf_path = &prepare(&rmrf_root)?;
+    let bkup_path = &prepare(&bkup_root)?;
     info!("Directories created or verified: {:?}, {:?}", rmrf_path, bkup_path);
 
+    // Where to look for existing bundles: the caller's own space, or with
+    // --all-users every user's subtree as well.
+    let visible = |root: &str, own: &Path| -> Result<Vec<PathBuf>> {
+        if scope == Scope::AllUsers && config.per_user_spaces {
+            space::user_subtrees(Path::new(root))
+        } else {
+            Ok(vec![own.to_path_buf()])
+        }
+    };
+    let rmrf_spaces = visible(&rmrf_root, rmrf_path)?;
+    let bkup_spaces = visible(&bkup_root, bkup_path)?;
+
+    let archive_opts = ArchiveOptions {
+        sudo,
+        follow: matches.follow,
+        fail_fast: matches.fail_fast,
+        scope,
+        action: Some(action.name()),
+        ..Default::default()
+    };
+    let rmrf_opts = ArchiveOptions {
+        compression: rmrf_compression,
+        store: config.rmrf.store,
+        incremental: matches.incremental || config.rmrf.incremental,
+        encryption: config.encryption_for(&config.rmrf, matches.encrypt, matches.keyfile.clone()),
+        ..archive_opts.clone()
+    };
+    let bkup_opts = ArchiveOptions {
+        compression: bkup_compression,
+        store: config.bkup.store,
+        incremental: matches.incremental || config.bkup.incremental,
+        encryption: config.encryption_for(&config.bkup, matches.encrypt, matches.keyfile.clone()),
+        ..archive_opts
+    };
+    if let Some(keyfile) = &matches.keyfile {
+        crypt::set_keyfile_override(keyfile.clone());
+    }
+
     match &matches.action {
         Some(action) => match action {
             Action::Bkup(args) => {
-                archive(bkup_path, &timestamp, &as_paths(&args.targets), sudo, false, None)?;
+                finish_report(archive(bkup_path, &timestamp, &as_paths(&args.targets), &bkup_opts)?)?;
             }
             Action::Rmrf(args) => {
-                archive(rmrf_path, &timestamp, &as_paths(&args.targets), sudo, true, Some(days))?;
+                let opts = ArchiveOptions {
+                    remove: true,
+                    keep: Some(days),
+                    ..rmrf_opts
+                };
+                finish_report(archive(rmrf_path, &timestamp, &as_paths(&args.targets), &opts)?)?;
             }
             Action::Rcvr(args) => {
-                recover(rmrf_path, &as_paths(&args.targets), sudo)?;
+                // rmrf spaces first: a name found in both is most likely
+                // something removed by mistake.
+                let spaces: Vec<PathBuf> = rmrf_spaces.iter().chain(&bkup_spaces).cloned().collect();
+                recover(&spaces, &as_paths(&args.targets), sudo, scope)?;
             }
             Action::LsBkup(args) => {
-                list(bkup_path, &args.targets, threshold)?;
+                list(&bkup_spaces, args, match_mode, threshold, scope)?;
             }
             Action::LsRmrf(args) => {
-                list(rmrf_path, &args.targets, threshold)?;
+                list(&rmrf_spaces, args, match_mode, threshold, scope)?;
             }
             Action::BkupRmrf(args) => {
-                archive(bkup_path, &timestamp, &as_paths(&args.targets), sudo, true, None)?;
+                let opts = ArchiveOptions {
+                    remove: true,
+                    ..bkup_opts
+                };
+                finish_report(archive(bkup_path, &timestamp, &as_paths(&args.targets), &opts)?)?;
+            }
+            Action::Verify(args) => {
+                let spaces = [rmrf_spaces, bkup_spaces].concat();
+                verify(&spaces, &as_paths(&args.targets), scope)?;
+            }
+            Action::Migrate(args) => {
+                let spaces = [rmrf_spaces, bkup_spaces].concat();
+                migrate::migrate(&spaces, args.apply, scope)?;
+            }
+            Action::Find(args) => {
+                let spaces = [rmrf_spaces, bkup_spaces].concat();
+                let query = search::Query {
+                    pattern: args.pattern.clone(),
+                    grep: args.grep.clone(),
+                };
+                search::find(&spaces, &query, scope)?;
+            }
+            Action::Show(args) => {
+                let spaces = [rmrf_spaces, bkup_spaces].concat();
+                show::show(&spaces, &args.bundle, scope)?;
+            }
+            Action::Diff(args) => {
+                let spaces = [rmrf_spaces, bkup_spaces].concat();
+                match &args.other {
+                    Some(other) => diff::diff_bundles(&spaces, &args.bundle, other, args.patch, scope)?,
+                    None => diff::diff_live(&spaces, &args.bundle, args.patch, scope)?,
+                }
+            }
+            Action::Browse => {
+                let spaces = rmrf_spaces
+                    .into_iter()
+                    .map(|space| (browse::Space::Rmrf, space))
+                    .chain(bkup_spaces.into_iter().map(|space| (browse::Space::Bkup, space)))
+                    .collect();
+                browse::browse(spaces, sudo, threshold, scope)?;
+            }
+            Action::Reindex => {
+                let spaces = [rmrf_spaces, bkup_spaces].concat();
+                index::reindex(&spaces)?;
             }
         },
         None => {
-            archive(
-                rmrf_path,
-                &timestamp,
-                &as_paths(&matches.targets),
-                sudo,
-                true,
-                Some(days),
-            )?;
+            let opts = ArchiveOptions {
+                remove: true,
+                keep: Some(days),
+                ..rmrf_opts
+            };
+            finish_report(archive(rmrf_path, &timestamp, &as_paths(&matches.targets), &opts)?)?;
         }
     }
 
@@ -928,8 +2374,13 @@ mod tests {
         fs::write(&file2, "error").unwrap();
 
         let targets = vec![file1, file2];
-        let (directories, groups) = categorize_paths(&targets, temp_path).unwrap();
+        let Categorized {
+            directories,
+            groups,
+            failures,
+        } = categorize_paths(&targets, temp_path, false, false).unwrap();
 
+        assert!(failures.is_empty(), "Should have no failures");
         assert_eq!(directories.len(), 0, "Should have no directories");
         assert_eq!(groups.len(), 1, "Should have one group");
         assert_eq!(groups[0].len(), 2, "Group should contain both files");
@@ -949,14 +2400,90 @@ mod tests {
         fs::write(&file1, "app").unwrap();
 
         let targets = vec![file1, dir2.clone()];
-        let (directories, groups) = categorize_paths(&targets, temp_path).unwrap();
+        let Categorized {
+            directories,
+            groups,
+            failures,
+        } = categorize_paths(&targets, temp_path, false, false).unwrap();
 
+        assert!(failures.is_empty(), "Should have no failures");
         assert_eq!(directories.len(), 1, "Should have one directory");
         assert_eq!(groups.len(), 1, "Should have one file group");
         assert_eq!(directories[0], dir2, "Directory should match");
         assert_eq!(groups[0].len(), 1, "File group should contain one file");
     }
 
+    #[test]
+    fn test_categorize_paths_missing_target_keep_going() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let file1 = temp_path.join("present.txt");
+        fs::write(&file1, "here").unwrap();
+        let missing = temp_path.join("missing.txt");
+
+        let targets = vec![file1, missing.clone()];
+        let Categorized {
+            directories,
+            groups,
+            failures,
+        } = categorize_paths(&targets, temp_path, false, false).unwrap();
+
+        assert_eq!(directories.len(), 0, "Should have no directories");
+        assert_eq!(groups.len(), 1, "Present file should still be grouped");
+        assert_eq!(failures.len(), 1, "Missing file should be reported");
+        assert_eq!(failures[0].target, missing);
+        assert!(format!("{}", failures[0].error).contains("No such file or directory"));
+    }
+
+    #[test]
+    fn test_categorize_paths_missing_target_fail_fast() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let targets = vec![temp_path.join("missing.txt")];
+        assert!(categorize_paths(&targets, temp_path, false, true).is_err());
+    }
+
+    #[test]
+    fn test_categorize_paths_keeps_directory_symlink_as_link() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let real_dir = temp_path.join("real");
+        fs::create_dir_all(&real_dir).unwrap();
+        let link = temp_path.join("link");
+        std::os::unix::fs::symlink(&real_dir, &link).unwrap();
+
+        let targets = vec![link.clone()];
+        let categorized = categorize_paths(&targets, temp_path, false, false).unwrap();
+        assert!(
+            categorized.directories.is_empty(),
+            "Link should not be treated as a directory"
+        );
+        assert_eq!(categorized.groups, vec![vec![link.clone()]]);
+
+        let followed = categorize_paths(&targets, temp_path, true, false).unwrap();
+        assert_eq!(followed.directories, vec![fs::canonicalize(&real_dir).unwrap()]);
+    }
+
+    #[test]
+    fn test_remove_target_symlink_keeps_referent() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let real_dir = temp_path.join("real");
+        fs::create_dir_all(&real_dir).unwrap();
+        fs::write(real_dir.join("inner.txt"), "inner").unwrap();
+        let link = temp_path.join("link");
+        std::os::unix::fs::symlink(&real_dir, &link).unwrap();
+
+        remove_target(&link).unwrap();
+
+        assert!(fs::symlink_metadata(&link).is_err(), "Link should be removed");
+        assert!(real_dir.join("inner.txt").exists(), "Referent should be untouched");
+    }
+
     #[test]
     fn test_create_metadata() {
         let temp_dir = TempDir::new().unwrap();
@@ -970,8 +2497,9 @@ mod tests {
         let file1 = cwd.join("test.txt");
         fs::write(&file1, "test content").unwrap();
 
-        let targets = vec![file1];
-        create_metadata(&base, &cwd, &targets).unwrap();
+        let targets = vec![file1.clone()];
+        let provenance = Provenance::current(Some("rmrf"));
+        create_metadata(&base, &cwd, &targets, &PayloadFormat::default(), &provenance, None).unwrap();
 
         let metadata_file = base.join("metadata.yml");
         assert!(metadata_file.exists(), "Metadata file should be created");
@@ -980,6 +2508,11 @@ mod tests {
         assert!(metadata_content.contains(&format!("cwd: {}", cwd.display())));
         assert!(metadata_content.contains("- test.txt"));
         assert!(metadata_content.contains("contents: |"));
+        assert!(metadata_content.contains("action: rmrf"));
+
+        let metadata = load_metadata(&base).unwrap();
+        assert_eq!(metadata.paths, vec![file1.canonicalize().unwrap()]);
+        assert_eq!(metadata.provenance, provenance);
     }
 
     #[test]
@@ -993,7 +2526,7 @@ mod tests {
 
         let targets = vec!["file1.txt".to_string(), "file2.txt".to_string()];
 
-        let command = create_tar_command(false, &tarball, &cwd, targets).unwrap();
+        let command = create_tar_command(false, &tarball, &cwd, targets, &Compression::default()).unwrap();
 
         assert_eq!(command.get_program(), "tar");
 
@@ -1014,7 +2547,7 @@ mod tests {
 
         let targets = vec!["file1.txt".to_string()];
 
-        let command = create_tar_command(true, &tarball, &cwd, targets).unwrap();
+        let command = create_tar_command(true, &tarball, &cwd, targets, &Compression::default()).unwrap();
 
         assert_eq!(command.get_program(), "sudo");
 
@@ -1023,6 +2556,616 @@ mod tests {
         assert!(args_str.contains("tar"));
     }
 
+    #[test]
+    fn test_create_tar_command_preserves_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let tarball = temp_dir.path().join("test.tar.gz");
+
+        let command = create_tar_command(
+            false,
+            &tarball,
+            temp_dir.path(),
+            vec!["file1.txt".to_string()],
+            &Compression::default(),
+        )
+        .unwrap();
+
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        for flag in ["--format=posix", "--xattrs", "--acls", "--selinux"] {
+            assert!(args.iter().any(|a| a == flag), "tar should be invoked with {flag}");
+        }
+    }
+
+    #[test]
+    fn test_copy_files_preserves_mtime_mode_and_xattrs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        let dest_dir = temp_path.join("dest");
+        fs::create_dir_all(&dest_dir).unwrap();
+
+        let src = temp_path.join("data.tar.gz");
+        fs::write(&src, "payload").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o640)).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let xattr_set = set_user_xattr(&src, "user.rkvr.test", b"kept");
+
+        copy_files(&dest_dir, std::slice::from_ref(&src), false).unwrap();
+
+        let copied = dest_dir.join("data.tar.gz");
+        let meta = fs::metadata(&copied).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o640, "Mode should be preserved");
+        assert_eq!(meta.modified().unwrap(), mtime, "mtime should be preserved");
+        if xattr_set {
+            assert_eq!(get_user_xattr(&copied, "user.rkvr.test"), Some(b"kept".to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_copy_files_keeps_hard_links_and_holes() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        let dest_dir = temp_path.join("dest");
+        fs::create_dir_all(&dest_dir).unwrap();
+
+        let first = temp_path.join("a.tar.gz");
+        let second = temp_path.join("b.tar.gz");
+        fs::write(&first, "shared").unwrap();
+        fs::hard_link(&first, &second).unwrap();
+
+        let sparse = temp_path.join("disk.img.gz");
+        let file = File::create(&sparse).unwrap();
+        file.set_len(64 * 1024 * 1024).unwrap();
+        drop(file);
+
+        let loose = vec![first.clone(), second.clone(), sparse.clone()];
+        assert_eq!(hard_link_groups(&loose), vec![vec![first.clone(), second.clone()]]);
+
+        copy_files(&dest_dir, &loose, false).unwrap();
+
+        let a = fs::metadata(dest_dir.join("a.tar.gz")).unwrap();
+        let b = fs::metadata(dest_dir.join("b.tar.gz")).unwrap();
+        assert_eq!(a.ino(), b.ino(), "Hard links should be copied once and relinked");
+
+        let copied = fs::metadata(dest_dir.join("disk.img.gz")).unwrap();
+        assert_eq!(copied.len(), 64 * 1024 * 1024);
+        if is_sparse(&fs::metadata(&sparse).unwrap()) {
+            assert!(is_sparse(&copied), "Holes should not be materialized");
+        }
+    }
+
+    #[test]
+    fn test_fifo_is_stored_as_metadata_and_recreated() {
+        use std::os::unix::fs::FileTypeExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+
+        // An archive-like name would previously route the FIFO to fs::copy and block.
+        let fifo = source_dir.join("pipe.gz");
+        let c_path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o640) }, 0);
+        fs::set_permissions(&fifo, std::os::unix::fs::PermissionsExt::from_mode(0o640)).unwrap();
+
+        let opts = ArchiveOptions {
+            remove: true,
+            ..Default::default()
+        };
+        let report = archive(&archive_dir, "2026-06-14-153045", std::slice::from_ref(&fifo), &opts).unwrap();
+        assert_eq!(report.archived.len(), 1);
+        assert!(fs::symlink_metadata(&fifo).is_err(), "FIFO should be removed");
+
+        let bundle = archive_dir.join("2026-06-14-153045-000");
+        let meta: Metadata = serde_yaml::from_str(&fs::read_to_string(bundle.join("metadata.yml")).unwrap()).unwrap();
+        assert_eq!(meta.specials.len(), 1);
+        assert_eq!(meta.specials[0].kind, SpecialKind::Fifo);
+        assert_eq!(meta.specials[0].mode, 0o640);
+        assert_eq!(
+            fs::read_dir(&bundle).unwrap().count(),
+            1,
+            "Only metadata.yml should be stored"
+        );
+
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-153045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+
+        let restored = fs::symlink_metadata(&fifo).unwrap();
+        assert!(restored.file_type().is_fifo(), "FIFO should be recreated");
+        assert_eq!(restored.mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_archive_and_recover_with_each_codec() {
+        for codec in [Codec::Zstd, Codec::Xz, Codec::Lz4, Codec::None, Codec::Gzip] {
+            let temp_dir = TempDir::new().unwrap();
+            let temp_path = temp_dir.path();
+
+            let source_dir = temp_path.join("source");
+            let archive_dir = temp_path.join("archive");
+            fs::create_dir_all(&source_dir).unwrap();
+            fs::create_dir_all(&archive_dir).unwrap();
+            let test_file = source_dir.join("test.txt");
+            fs::write(&test_file, "compressed content").unwrap();
+
+            let opts = ArchiveOptions {
+                remove: true,
+                compression: Compression { codec, level: None },
+                ..Default::default()
+            };
+            archive(
+                &archive_dir,
+                "2026-06-14-153045",
+                std::slice::from_ref(&test_file),
+                &opts,
+            )
+            .unwrap();
+
+            let bundle = archive_dir.join("2026-06-14-153045-000");
+            let tarball = bundle.join(format!("source.{}", codec.extension()));
+            assert!(
+                tarball.exists(),
+                "{:?} tarball should be named {}",
+                codec,
+                tarball.display()
+            );
+            assert_eq!(Codec::detect(&tarball).unwrap(), codec);
+            let meta: Metadata =
+                serde_yaml::from_str(&fs::read_to_string(bundle.join("metadata.yml")).unwrap()).unwrap();
+            assert_eq!(meta.compression, codec, "Codec should be recorded in metadata");
+
+            recover(
+                std::slice::from_ref(&archive_dir),
+                &[PathBuf::from("2026-06-14-153045-000")],
+                false,
+                Scope::default(),
+            )
+            .unwrap();
+            assert_eq!(fs::read_to_string(&test_file).unwrap(), "compressed content");
+        }
+    }
+
+    #[test]
+    fn test_chunked_store_dedups_repeated_bkups() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        let big: Vec<u8> = (0..1024 * 1024u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        fs::write(source_dir.join("big.bin"), &big).unwrap();
+        fs::write(source_dir.join("small.txt"), "v1").unwrap();
+
+        let opts = ArchiveOptions {
+            store: StoreKind::Chunked,
+            compression: Compression {
+                codec: Codec::Zstd,
+                level: None,
+            },
+            ..Default::default()
+        };
+        let count_chunks = || {
+            fs::read_dir(archive_dir.join(store::CHUNKS_DIR))
+                .unwrap()
+                .flatten()
+                .map(|prefix| fs::read_dir(prefix.path()).unwrap().count())
+                .sum::<usize>()
+        };
+
+        archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+        let after_first = count_chunks();
+        assert!(after_first > 1);
+
+        fs::write(source_dir.join("small.txt"), "v2").unwrap();
+        archive(
+            &archive_dir,
+            "2026-06-14-163045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+        let added = count_chunks() - after_first;
+        assert!(added <= 2, "Unchanged data should reuse chunks, {} were added", added);
+
+        let bundle = archive_dir.join("2026-06-14-163045-000");
+        assert!(bundle.join(store::MANIFEST_FILE).exists());
+        assert!(
+            !bundle.join("source.tar.zst").exists(),
+            "Chunked bundles hold no tarball"
+        );
+        let meta: Metadata = serde_yaml::from_str(&fs::read_to_string(bundle.join("metadata.yml")).unwrap()).unwrap();
+        assert_eq!(meta.store, StoreKind::Chunked);
+        assert_eq!(meta.compression, Codec::None);
+
+        // Cleanup must not treat the chunk store as an expired bundle.
+        cleanup(&archive_dir, 36500, false, Scope::default()).unwrap();
+        assert!(archive_dir.join(store::CHUNKS_DIR).is_dir());
+
+        fs::remove_dir_all(&source_dir).unwrap();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-163045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read(source_dir.join("big.bin")).unwrap(), big);
+        assert_eq!(fs::read_to_string(source_dir.join("small.txt")).unwrap(), "v2");
+    }
+
+    #[test]
+    fn test_chunks_of_removed_bundles_are_collected() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        let noise = |seed: u32| -> Vec<u8> {
+            (0..512 * 1024u32)
+                .map(|i| (i.wrapping_add(seed).wrapping_mul(2654435761) >> 13) as u8)
+                .collect()
+        };
+        fs::write(source_dir.join("shared.bin"), noise(0)).unwrap();
+
+        let opts = ArchiveOptions {
+            store: StoreKind::Chunked,
+            ..Default::default()
+        };
+        let chunks = |bundle: &str| -> HashSet<String> {
+            store::ChunkManifest::load(&archive_dir.join(bundle))
+                .unwrap()
+                .payloads
+                .into_iter()
+                .flat_map(|p| p.chunks)
+                .collect()
+        };
+        let stored = || -> HashSet<String> {
+            fs::read_dir(archive_dir.join(store::CHUNKS_DIR))
+                .unwrap()
+                .flatten()
+                .flat_map(|prefix| {
+                    let head = prefix.file_name().to_string_lossy().into_owned();
+                    fs::read_dir(prefix.path())
+                        .unwrap()
+                        .flatten()
+                        .map(move |chunk| format!("{}{}", head, chunk.file_name().to_string_lossy()))
+                })
+                .collect()
+        };
+        // Push every chunk past the grace period.
+        let age_chunks = || {
+            let old = SystemTime::now() - std::time::Duration::from_secs(2 * 24 * 60 * 60);
+            for hash in stored() {
+                let path = archive_dir.join(store::CHUNKS_DIR).join(&hash[..2]).join(&hash[2..]);
+                File::options()
+                    .write(true)
+                    .open(path)
+                    .unwrap()
+                    .set_modified(old)
+                    .unwrap();
+            }
+        };
+        let bkup = |timestamp: &str| {
+            archive(&archive_dir, timestamp, std::slice::from_ref(&source_dir), &opts).unwrap();
+        };
+
+        fs::write(source_dir.join("first.bin"), noise(1)).unwrap();
+        bkup("2026-06-14-153045");
+        fs::remove_file(source_dir.join("first.bin")).unwrap();
+        fs::write(source_dir.join("second.bin"), noise(2)).unwrap();
+        bkup("2026-06-14-163045");
+        let first = chunks("2026-06-14-153045-000");
+        let second = chunks("2026-06-14-163045-000");
+        let only_first: HashSet<_> = first.difference(&second).cloned().collect();
+        assert!(!only_first.is_empty());
+
+        // rcvr removes the first bundle and its chunks with it.
+        age_chunks();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-153045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert!(stored().is_disjoint(&only_first), "rcvr left the first bundle's chunks");
+        assert!(stored().is_superset(&second));
+
+        // A bkup collects the chunks of bundles removed by other means.
+        fs::remove_dir_all(archive_dir.join("2026-06-14-163045-000")).unwrap();
+        fs::remove_file(source_dir.join("second.bin")).unwrap();
+        fs::remove_file(source_dir.join("first.bin")).unwrap();
+        age_chunks();
+        bkup("2026-06-14-173045");
+        let third = chunks("2026-06-14-173045-000");
+        assert_eq!(stored(), third, "bkup left chunks nothing references");
+        verify_bundle(&archive_dir.join("2026-06-14-173045-000")).unwrap();
+    }
+
+    #[test]
+    fn test_incremental_bkup_chain_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(source_dir.join("sub")).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(source_dir.join("edit.txt"), "v1").unwrap();
+        fs::write(source_dir.join("gone.txt"), "gone").unwrap();
+        fs::write(source_dir.join("sub/same.txt"), "same").unwrap();
+
+        let opts = ArchiveOptions {
+            incremental: true,
+            ..Default::default()
+        };
+        archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+        let full = archive_dir.join("2026-06-14-153045-000");
+        assert!(full.join(FILE_MANIFEST).exists());
+        assert!(load_metadata(&full).unwrap().parent.is_none());
+
+        fs::write(source_dir.join("edit.txt"), "version 2").unwrap();
+        fs::remove_file(source_dir.join("gone.txt")).unwrap();
+        fs::write(source_dir.join("new.txt"), "new").unwrap();
+        archive(
+            &archive_dir,
+            "2026-06-14-163045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+
+        let incr = archive_dir.join("2026-06-14-163045-000");
+        let meta = load_metadata(&incr).unwrap();
+        assert_eq!(meta.parent.as_deref(), Some("2026-06-14-153045-000"));
+        assert_eq!(meta.deleted, vec!["source/gone.txt"]);
+        let mut names: Vec<String> = fs::read_dir(&incr)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["files.yml", "metadata.yml", "source.tar.gz"],
+            "The list of changed files is not kept in the bundle"
+        );
+
+        let listing = Command::new("tar")
+            .args(["-tzf", incr.join("source.tar.gz").to_str().unwrap()])
+            .output()
+            .unwrap();
+        let listing = String::from_utf8_lossy(&listing.stdout);
+        let files: Vec<&str> = listing.lines().filter(|l| !l.ends_with('/')).collect();
+        assert_eq!(files.len(), 2, "Only changed files should be stored: {:?}", files);
+        assert!(!listing.contains("same.txt"));
+
+        // Single files come back as of the incremental bkup, from the chain.
+        let picked = temp_path.join("picked");
+        let written = contents::extract(&incr, &["source".to_string()], &picked).unwrap();
+        assert_eq!(written, 5, "source, sub and the three files listed");
+        assert_eq!(fs::read_to_string(picked.join("source/edit.txt")).unwrap(), "version 2");
+        assert_eq!(fs::read_to_string(picked.join("source/sub/same.txt")).unwrap(), "same");
+        assert!(!picked.join("source/gone.txt").exists(), "deleted before the bkup");
+
+        // Recovering the parent keeps it, since the incremental bkup needs it.
+        fs::remove_dir_all(&source_dir).unwrap();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-153045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert!(full.exists());
+
+        fs::remove_dir_all(&source_dir).unwrap();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-163045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("edit.txt")).unwrap(), "version 2");
+        assert_eq!(fs::read_to_string(source_dir.join("new.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(source_dir.join("sub/same.txt")).unwrap(), "same");
+        assert!(!source_dir.join("gone.txt").exists(), "Deletions should be replayed");
+        assert!(!incr.exists());
+    }
+
+    #[test]
+    fn test_cleanup_keeps_parents_of_live_incremental_bkups() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(source_dir.join("file.txt"), "v1").unwrap();
+
+        let opts = ArchiveOptions {
+            incremental: true,
+            ..Default::default()
+        };
+        let days_ago = |days| (chrono::Local::now() - chrono::Duration::days(days)).format(BUNDLE_TIMESTAMP);
+        let (ten_days_ago, yesterday) = (days_ago(10).to_string(), days_ago(1).to_string());
+        archive(&archive_dir, &ten_days_ago, std::slice::from_ref(&source_dir), &opts).unwrap();
+        fs::write(source_dir.join("file.txt"), "v2, longer").unwrap();
+        archive(&archive_dir, &yesterday, std::slice::from_ref(&source_dir), &opts).unwrap();
+
+        let parent = archive_dir.join(format!("{ten_days_ago}-000"));
+        cleanup(&archive_dir, 5, false, Scope::default()).unwrap();
+        assert!(parent.exists(), "Expired parent of a live bundle must be kept");
+
+        set_pin(&parent, true).unwrap();
+        cleanup(&archive_dir, 0, false, Scope::default()).unwrap();
+        assert!(parent.exists(), "A bundle pinned by hand is kept");
+
+        set_pin(&parent, false).unwrap();
+        cleanup(&archive_dir, 0, false, Scope::default()).unwrap();
+        assert!(!parent.exists(), "A fully expired chain is removed");
+    }
+
+    #[test]
+    fn test_encrypted_bundle_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        let secret = source_dir.join("secret.env");
+        let loose = source_dir.join("old.tar.gz");
+        fs::write(&secret, "API_TOKEN=hunter2").unwrap();
+        fs::write(&loose, "not really gzip").unwrap();
+        let keyfile = temp_path.join("rkvr.key");
+        fs::write(&keyfile, "a key file with enough entropy").unwrap();
+
+        let opts = ArchiveOptions {
+            remove: true,
+            encryption: Some(EncryptionConfig {
+                keyfile: Some(keyfile.clone()),
+            }),
+            ..Default::default()
+        };
+        archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            &[secret.clone(), loose.clone()],
+            &opts,
+        )
+        .unwrap();
+
+        let bundle = archive_dir.join("2026-06-14-153045-000");
+        let mut names: Vec<String> = fs::read_dir(&bundle)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        let payloads: Vec<&String> = names.iter().filter(|name| !is_bookkeeping(name)).collect();
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert!(names.contains(&"encryption.yml".to_string()) && names.contains(&"metadata.yml.enc".to_string()));
+        assert!(
+            payloads.len() == 1 && payloads[0].ends_with(".enc") && !payloads[0].contains("source"),
+            "payload name should be opaque: {:?}",
+            names
+        );
+        for name in &names {
+            let raw = fs::read(bundle.join(name)).unwrap();
+            assert!(
+                !raw.windows(7).any(|w| w == b"hunter2" || w == b"/source"),
+                "{} leaks plaintext",
+                name
+            );
+        }
+
+        verify_bundle(&bundle).unwrap();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-153045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&secret).unwrap(), "API_TOKEN=hunter2");
+        assert_eq!(fs::read_to_string(&loose).unwrap(), "not really gzip");
+
+        let chunked = ArchiveOptions {
+            store: StoreKind::Chunked,
+            ..opts
+        };
+        assert!(archive(
+            &archive_dir,
+            "2026-06-14-163045",
+            std::slice::from_ref(&secret),
+            &chunked
+        )
+        .is_err());
+        assert!(secret.exists());
+    }
+
+    #[test]
+    fn test_bundle_dirs_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        let file = source_dir.join("notes.txt");
+        fs::write(&file, "private").unwrap();
+
+        let report = archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            &[file, source_dir.clone()],
+            &ArchiveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.archived.len(), 2);
+        for bundle in bundle_dirs(&archive_dir).unwrap() {
+            let mode = fs::metadata(&bundle).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode, 0o700, "{} should be private", bundle.display());
+        }
+    }
+
+    /// Set a `user.*` xattr, returning false where the filesystem has no support.
+    fn set_user_xattr(path: &Path, name: &str, value: &[u8]) -> bool {
+        let path = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).unwrap();
+        let name = std::ffi::CString::new(name).unwrap();
+        unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0) == 0 }
+    }
+
+    fn get_user_xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+        let path = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).unwrap();
+        let name = std::ffi::CString::new(name).unwrap();
+        let mut buf = vec![0u8; 256];
+        let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        (len >= 0).then(|| {
+            buf.truncate(len as usize);
+            buf
+        })
+    }
+
     #[test]
     fn test_is_archive() {
         let temp_dir = TempDir::new().unwrap();
@@ -1083,7 +3226,9 @@ mod tests {
 
         let targets = vec![test_file.clone(), test_dir.clone()];
 
-        remove_targets(&targets).unwrap();
+        for target in &targets {
+            remove_target(target).unwrap();
+        }
 
         assert!(!test_file.exists(), "File should be removed");
         assert!(!test_dir.exists(), "Directory should be removed");
@@ -1094,8 +3239,9 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let temp_path = temp_dir.path();
 
-        let dir1 = temp_path.join("1234567890");
-        let dir2 = temp_path.join("9876543210");
+        let now = chrono::Local::now().format(BUNDLE_TIMESTAMP);
+        let dir1 = temp_path.join(format!("{now}-000"));
+        let dir2 = temp_path.join(format!("{now}-001"));
 
         fs::create_dir_all(&dir1).unwrap();
         fs::create_dir_all(&dir2).unwrap();
@@ -1103,12 +3249,12 @@ mod tests {
         fs::write(dir1.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: |").unwrap();
         fs::write(dir2.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: |").unwrap();
 
-        cleanup(temp_path, 30, false).unwrap();
+        cleanup(temp_path, 30, false, Scope::default()).unwrap();
 
         assert!(dir1.exists(), "Recently created directory should still exist");
         assert!(dir2.exists(), "Recently created directory should still exist");
 
-        cleanup(temp_path, 365, false).unwrap();
+        cleanup(temp_path, 365, false, Scope::default()).unwrap();
 
         assert!(dir1.exists(), "Directory should exist with long threshold");
         assert!(dir2.exists(), "Directory should exist with long threshold");
@@ -1130,7 +3276,7 @@ mod tests {
         let timestamp = "2026-06-14-153045";
         let targets = vec![test_file.clone()];
 
-        archive(&archive_dir, timestamp, &targets, false, false, None).unwrap();
+        archive(&archive_dir, timestamp, &targets, &ArchiveOptions::default()).unwrap();
 
         assert!(test_file.exists(), "Original file should still exist");
 
@@ -1161,7 +3307,16 @@ mod tests {
         let timestamp = "2026-06-14-153045";
         let targets = vec![test_file.clone()];
 
-        archive(&archive_dir, timestamp, &targets, false, true, None).unwrap();
+        archive(
+            &archive_dir,
+            timestamp,
+            &targets,
+            &ArchiveOptions {
+                remove: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         assert!(!test_file.exists(), "Original file should be removed");
 
@@ -1169,6 +3324,118 @@ mod tests {
         assert!(expected_archive.exists(), "Archive directory should be created");
     }
 
+    #[test]
+    fn test_archive_partial_success_keeps_going() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+
+        let test_file = source_dir.join("test.txt");
+        fs::write(&test_file, "test content").unwrap();
+        let missing = source_dir.join("missing.txt");
+
+        let targets = vec![test_file.clone(), missing.clone()];
+        let opts = ArchiveOptions {
+            remove: true,
+            ..Default::default()
+        };
+        let report = archive(&archive_dir, "2026-06-14-153045", &targets, &opts).unwrap();
+
+        assert_eq!(report.archived, vec![test_file.clone()]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].target, missing);
+        assert!(!test_file.exists(), "Archived file should be removed");
+    }
+
+    #[test]
+    fn test_archive_reports_targets_as_given() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(source_dir.join("sub")).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(source_dir.join("test.txt"), "test content").unwrap();
+        let given = source_dir.join("sub/../test.txt");
+
+        let report = archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            std::slice::from_ref(&given),
+            &ArchiveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.archived, vec![given]);
+    }
+
+    #[test]
+    fn test_archive_reports_targets_when_cleanup_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        let test_file = source_dir.join("test.txt");
+        fs::write(&test_file, "test content").unwrap();
+
+        // An expired entry cleanup cannot stat.
+        std::os::unix::fs::symlink(temp_path.join("nowhere"), archive_dir.join("2020-01-01-000000-000")).unwrap();
+        assert!(cleanup(&archive_dir, 0, false, Scope::default()).is_err());
+
+        let opts = ArchiveOptions {
+            remove: true,
+            keep: Some(0),
+            ..Default::default()
+        };
+        let report = archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            std::slice::from_ref(&test_file),
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(report.archived, vec![test_file.clone()]);
+        assert!(report.failures.is_empty());
+        assert!(!test_file.exists());
+    }
+
+    #[test]
+    fn test_archive_fail_fast_touches_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let source_dir = temp_path.join("source");
+        let archive_dir = temp_path.join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+
+        let test_file = source_dir.join("test.txt");
+        fs::write(&test_file, "test content").unwrap();
+
+        let targets = vec![test_file.clone(), source_dir.join("missing.txt")];
+        let opts = ArchiveOptions {
+            remove: true,
+            fail_fast: true,
+            ..Default::default()
+        };
+        let result = archive(&archive_dir, "2026-06-14-153045", &targets, &opts);
+
+        assert!(result.is_err(), "Missing target should abort with --fail-fast");
+        assert!(test_file.exists(), "Nothing should be removed");
+        assert_eq!(
+            fs::read_dir(&archive_dir).unwrap().count(),
+            0,
+            "No bundle should be created"
+        );
+    }
+
     #[test]
     fn test_config_load_default() {
         let config = Config::load(None).unwrap();
@@ -1208,9 +3475,81 @@ cleanup_days: 15
         let config = Config::load(Some(config_file)).unwrap();
         assert_eq!(config.cleanup_days, 15);
         assert!(!config.auto_cleanup);
+        assert!(config.per_user_spaces, "per-user subtrees are the default");
         assert!(config.archive_location.contains("rkvr/archive"));
     }
 
+    #[test]
+    fn test_config_compression_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("compression_config.yml");
+
+        let config_content = r#"
+compression:
+  codec: xz
+  level: 6
+bkup:
+  compression:
+    codec: zstd
+    level: 19
+"#;
+        fs::write(&config_file, config_content).unwrap();
+
+        let config = Config::load(Some(config_file)).unwrap();
+        let rmrf = config.compression_for(&config.rmrf, None, None).unwrap();
+        assert_eq!((rmrf.codec, rmrf.level), (Codec::Xz, Some(6)), "Global setting applies");
+
+        let bkup = config.compression_for(&config.bkup, None, None).unwrap();
+        assert_eq!((bkup.codec, bkup.level), (Codec::Zstd, Some(19)), "Space setting wins");
+
+        let cli = config.compression_for(&config.bkup, Some(Codec::Lz4), None).unwrap();
+        assert_eq!(
+            (cli.codec, cli.level),
+            (Codec::Lz4, None),
+            "Level is not carried across codecs"
+        );
+
+        let cli_level = config.compression_for(&config.bkup, None, Some(3)).unwrap();
+        assert_eq!((cli_level.codec, cli_level.level), (Codec::Zstd, Some(3)));
+
+        assert!(
+            config.compression_for(&config.bkup, None, Some(20)).is_err(),
+            "An out-of-range level is rejected up front"
+        );
+    }
+
+    #[test]
+    fn test_config_keyfile_does_not_turn_on_encryption() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("encryption_config.yml");
+        fs::write(&config_file, "bkup:\n  encryption:\n    keyfile: /etc/rkvr.key\n").unwrap();
+        let config = Config::load(Some(config_file)).unwrap();
+        let cli_key = Some(PathBuf::from("/tmp/other.key"));
+
+        assert_eq!(config.encryption_for(&config.rmrf, false, cli_key.clone()), None);
+        assert_eq!(
+            config.encryption_for(&config.rmrf, true, None),
+            Some(EncryptionConfig::default())
+        );
+        assert_eq!(
+            config.encryption_for(&config.rmrf, true, cli_key.clone()),
+            Some(EncryptionConfig {
+                keyfile: cli_key.clone()
+            })
+        );
+        assert_eq!(
+            config.encryption_for(&config.bkup, false, None),
+            Some(EncryptionConfig {
+                keyfile: Some(PathBuf::from("/etc/rkvr.key"))
+            })
+        );
+        assert_eq!(
+            config.encryption_for(&config.bkup, false, cli_key.clone()),
+            Some(EncryptionConfig { keyfile: cli_key }),
+            "--keyfile picks the key of a configured space"
+        );
+    }
+
     #[test]
     fn test_config_load_invalid_file() {
         let temp_dir = TempDir::new().unwrap();
@@ -1259,8 +3598,9 @@ archive_location: "/tmp/integration_test"
         let temp_path = temp_dir.path();
 
         // Create directories with regular permissions
-        let dir1 = temp_path.join("1234567890");
-        let dir2 = temp_path.join("9876543210");
+        let now = chrono::Local::now().format(BUNDLE_TIMESTAMP);
+        let dir1 = temp_path.join(format!("{now}-000"));
+        let dir2 = temp_path.join(format!("{now}-001"));
 
         fs::create_dir_all(&dir1).unwrap();
         fs::create_dir_all(&dir2).unwrap();
@@ -1269,13 +3609,13 @@ archive_location: "/tmp/integration_test"
         fs::write(dir2.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: |").unwrap();
 
         // Test cleanup with sudo=false (should work for user-owned files)
-        cleanup(temp_path, 30, false).unwrap();
+        cleanup(temp_path, 30, false, Scope::default()).unwrap();
 
         assert!(dir1.exists(), "Recently created directory should still exist");
         assert!(dir2.exists(), "Recently created directory should still exist");
 
         // Test cleanup with longer threshold
-        cleanup(temp_path, 365, false).unwrap();
+        cleanup(temp_path, 365, false, Scope::default()).unwrap();
 
         assert!(dir1.exists(), "Directory should exist with long threshold");
         assert!(dir2.exists(), "Directory should exist with long threshold");
@@ -1287,8 +3627,9 @@ archive_location: "/tmp/integration_test"
         let temp_path = temp_dir.path();
 
         // Create directories with regular permissions
-        let dir1 = temp_path.join("1234567890");
-        let dir2 = temp_path.join("9876543210");
+        let now = chrono::Local::now().format(BUNDLE_TIMESTAMP);
+        let dir1 = temp_path.join(format!("{now}-000"));
+        let dir2 = temp_path.join(format!("{now}-001"));
 
         fs::create_dir_all(&dir1).unwrap();
         fs::create_dir_all(&dir2).unwrap();
@@ -1297,13 +3638,13 @@ archive_location: "/tmp/integration_test"
         fs::write(dir2.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: |").unwrap();
 
         // Test cleanup with sudo=true (should still work for user-owned files)
-        cleanup(temp_path, 30, true).unwrap();
+        cleanup(temp_path, 30, true, Scope::default()).unwrap();
 
         assert!(dir1.exists(), "Recently created directory should still exist");
         assert!(dir2.exists(), "Recently created directory should still exist");
 
         // Test cleanup with longer threshold
-        cleanup(temp_path, 365, true).unwrap();
+        cleanup(temp_path, 365, true, Scope::default()).unwrap();
 
         assert!(dir1.exists(), "Directory should exist with long threshold");
         assert!(dir2.exists(), "Directory should exist with long threshold");
@@ -1356,7 +3697,8 @@ archive_location: "/tmp/integration_test"
 
         // Create directories
         let old_dir = temp_path.join("1234567890");
-        let recent_dir = temp_path.join("9876543210");
+        let second_ago = chrono::Local::now() - chrono::Duration::seconds(1);
+        let recent_dir = temp_path.join(format!("{}-000", second_ago.format(BUNDLE_TIMESTAMP)));
 
         fs::create_dir_all(&old_dir).unwrap();
         fs::create_dir_all(&recent_dir).unwrap();
@@ -1367,10 +3709,150 @@ archive_location: "/tmp/integration_test"
         // We can't easily change file timestamps in tests without external tools,
         // so we'll test the logic by using a very short threshold (0 days)
         // This should delete all directories
-        cleanup(temp_path, 0, false).unwrap();
+        cleanup(temp_path, 0, false, Scope::default()).unwrap();
 
         // Both directories should be deleted with 0 day threshold
         assert!(!old_dir.exists(), "Old directory should be removed");
         assert!(!recent_dir.exists(), "Directory should be removed with 0 day threshold");
     }
+
+    #[test]
+    fn test_cleanup_ages_bundles_by_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+
+        // Both were just created on disk; only the names say how old they are.
+        let week_ago = chrono::Local::now() - chrono::Duration::days(7);
+        let epoch_named = space.join(week_ago.timestamp().to_string());
+        let iso_named = space.join(week_ago.format("%Y-%m-%dT%H-%M-%S-000").to_string());
+        let current = space.join(format!("{}-000", chrono::Local::now().format(BUNDLE_TIMESTAMP)));
+        for dir in [&epoch_named, &iso_named, &current] {
+            fs::create_dir(dir).unwrap();
+            fs::write(dir.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: |").unwrap();
+        }
+
+        cleanup(space, 3, false, Scope::default()).unwrap();
+        assert!(!epoch_named.exists(), "EPOCH name dates the bundle a week back");
+        assert!(!iso_named.exists(), "ISO-T name dates the bundle a week back");
+        assert!(current.exists());
+    }
+
+    #[test]
+    fn test_find_bundle_by_name_index_or_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let rmrf = temp_dir.path().join("rmrf");
+        let bkup = temp_dir.path().join("bkup");
+        for bundle in [
+            rmrf.join("2026-06-14-153045-000"),
+            rmrf.join("2026-06-15-090000-000"),
+            bkup.join("2026-06-15-090000-001"),
+        ] {
+            fs::create_dir_all(&bundle).unwrap();
+        }
+        let spaces = [rmrf.clone(), bkup.clone()];
+        let find = |spec: &str| find_bundle(&spaces, spec, Scope::AllUsers).map_err(|e| e.to_string());
+
+        assert_eq!(find("2026-06-14-153045-000"), Ok(rmrf.join("2026-06-14-153045-000")));
+        assert_eq!(find("@1"), Ok(bkup.join("2026-06-15-090000-001")), "@1 is the newest");
+        assert_eq!(find("@3"), Ok(rmrf.join("2026-06-14-153045-000")));
+        assert_eq!(find("2026-06-14"), Ok(rmrf.join("2026-06-14-153045-000")));
+        assert_eq!(
+            find("2026-06-15"),
+            Err("2026-06-15 matches 2 bundles: 2026-06-15-090000-001, 2026-06-15-090000-000".to_string())
+        );
+        assert_eq!(find("@4"), Err("No bundle matches @4".to_string()));
+        assert_eq!(find("@0"), Err("No bundle matches @0".to_string()));
+        assert!(find("@x").is_err());
+
+        // A number is a prefix, however many bundles there are.
+        assert_eq!(
+            find("2026"),
+            Err(
+                "2026 matches 3 bundles: 2026-06-15-090000-001, 2026-06-15-090000-000, 2026-06-14-153045-000"
+                    .to_string()
+            )
+        );
+        assert_eq!(find("1"), Err("No bundle matches 1".to_string()));
+        fs::create_dir_all(rmrf.join("2027-01-01-000000-000")).unwrap();
+        assert_eq!(find("2027"), Ok(rmrf.join("2027-01-01-000000-000")));
+    }
+
+    #[test]
+    fn test_bundles_are_scoped_to_their_user() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path().join("rmrf");
+        let mine = space.join("2026-06-14-153045-000");
+        let theirs = space.join("2026-06-14-163045-000");
+        fs::create_dir_all(&mine).unwrap();
+        fs::create_dir_all(&theirs).unwrap();
+
+        let cwd = temp_dir.path().join("work");
+        fs::create_dir_all(&cwd).unwrap();
+        create_metadata(
+            &mine,
+            &cwd,
+            &[],
+            &PayloadFormat::default(),
+            &Provenance::current(None),
+            None,
+        )
+        .unwrap();
+        let recorded = load_metadata(&mine).unwrap().provenance.user.unwrap();
+        assert_eq!(recorded, Invoker::current());
+
+        let other_uid = Invoker::current().owner_uid() + 4242;
+        fs::write(
+            theirs.join("metadata.yml"),
+            format!(
+                "cwd: /tmp\ntargets: []\nuser:\n  name: someone\n  uid: {}\ncontents: ''\n",
+                other_uid
+            ),
+        )
+        .unwrap();
+        assert_eq!(bundle_owner(&theirs), Some(other_uid));
+
+        let mine_only = Scope::default();
+        assert!(mine_only.includes(&mine));
+        assert!(!mine_only.includes(&theirs));
+        assert!(Scope::AllUsers.includes(&theirs));
+
+        let err = recover(
+            std::slice::from_ref(&space),
+            &[PathBuf::from("2026-06-14-163045-000")],
+            false,
+            mine_only,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("another user"), "{}", err);
+
+        cleanup(&space, 0, false, mine_only).unwrap();
+        assert!(!mine.exists(), "Own expired bundle should be removed");
+        assert!(theirs.exists(), "Another user's bundle must be left alone");
+
+        cleanup(&space, 0, false, Scope::AllUsers).unwrap();
+        assert!(!theirs.exists());
+    }
+
+    #[test]
+    fn test_legacy_bundles_belong_to_their_space() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path().join("rmrf");
+        let legacy = space.join("2020-01-01-000000-000");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("metadata.yml"), "cwd: /tmp\ntargets: []\ncontents: ''\n").unwrap();
+
+        // Without provenance, the directory's owner.
+        assert_eq!(bundle_owner(&legacy), Some(current_uid()));
+        if current_uid() != 0 {
+            // Only root can make the root-owned bundles `sudo rkvr` left.
+            return;
+        }
+
+        // Root-owned, as `sudo rkvr` left them: the space's owner.
+        let user = 4242;
+        std::os::unix::fs::chown(&space, Some(user), None).unwrap();
+        assert_eq!(bundle_owner(&legacy), Some(user));
+        cleanup(&space, 0, false, Scope::User(user)).unwrap();
+        assert!(!legacy.exists(), "its owner's cleanup removes it");
+    }
 }
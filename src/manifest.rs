@@ -0,0 +1,276 @@
+use crate::crypt::{self, BundleKey};
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Per-bundle record of every entry under an archived directory, used to
+/// decide what an incremental bkup has to store.
+pub const FILE_MANIFEST: &str = "files.yml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// The state of one path at bkup time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    /// Path relative to the bundle's `cwd`.
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Modification time in nanoseconds since the epoch.
+    pub mtime: i64,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// SHA-256 of a regular file's contents; absent when it could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<PathBuf>,
+}
+
+impl FileEntry {
+    /// The entry for `path` as it is now, recorded as `rel`, without
+    /// following a symlink or hashing contents.
+    pub fn stat(path: &Path, rel: String) -> Result<Self> {
+        let meta = fs::symlink_metadata(path).wrap_err_with(|| format!("Failed to stat {}", path.display()))?;
+        let file_type = meta.file_type();
+        let kind = if file_type.is_dir() {
+            EntryKind::Dir
+        } else if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        Ok(FileEntry {
+            path: rel,
+            kind,
+            size: if kind == EntryKind::Dir { 0 } else { meta.len() },
+            mtime: meta.mtime() * 1_000_000_000 + meta.mtime_nsec(),
+            mode: meta.mode() & 0o7777,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            hash: None,
+            link: if kind == EntryKind::Symlink {
+                fs::read_link(path).ok()
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Same size and mtime: the contents are assumed unchanged without
+    /// reading them again.
+    fn same_stat(&self, other: &FileEntry) -> bool {
+        self.kind == other.kind && self.size == other.size && self.mtime == other.mtime
+    }
+
+    /// Whether restoring `other` would give back this entry's contents and
+    /// attributes. Files hashed on both sides are compared by size and hash,
+    /// so one that was only touched is not stored again (and comes back with
+    /// its earlier mtime); anything else by size and mtime.
+    pub fn unchanged_from(&self, other: &FileEntry) -> bool {
+        let same_contents = match (&self.hash, &other.hash) {
+            (Some(hash), Some(old)) => self.kind == other.kind && self.size == other.size && hash == old,
+            _ => self.same_stat(other),
+        };
+        same_contents
+            && self.mode == other.mode
+            && self.uid == other.uid
+            && self.gid == other.gid
+            && self.link == other.link
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct FileManifest {
+    pub entries: Vec<FileEntry>,
+}
+
+/// What an incremental payload must carry relative to its parent.
+#[derive(Debug, Default, PartialEq)]
+pub struct Delta {
+    /// New or modified non-directory entries.
+    pub changed: Vec<String>,
+    /// Entries of the parent that no longer exist.
+    pub deleted: Vec<String>,
+}
+
+impl FileManifest {
+    pub fn load(bundle: &Path) -> Result<Self> {
+        let contents = crypt::read_bundle_file(bundle, FILE_MANIFEST)?;
+        serde_yaml::from_slice(&contents).wrap_err_with(|| format!("parsing {}", FILE_MANIFEST))
+    }
+
+    pub fn save(&self, bundle: &Path, key: Option<&BundleKey>) -> Result<()> {
+        let yaml = serde_yaml::to_string(self).wrap_err("Failed to serialize file manifest")?;
+        crypt::write_bundle_file(bundle, FILE_MANIFEST, yaml.as_bytes(), key)
+    }
+
+    /// Record `root` and everything below it, without following symlinks.
+    /// Regular files are hashed unless `previous` has an entry with the same
+    /// size and mtime, whose hash is reused.
+    pub fn scan(root: &Path, cwd: &Path, previous: Option<&FileManifest>) -> Result<Self> {
+        let known: HashMap<&str, &FileEntry> = previous
+            .map(|m| m.entries.iter().map(|e| (e.path.as_str(), e)).collect())
+            .unwrap_or_default();
+
+        let mut entries = Vec::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            let rel = path
+                .strip_prefix(cwd)
+                .map(|rel| rel.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| path.display().to_string());
+            let mut entry = FileEntry::stat(&path, rel)?;
+            match entry.kind {
+                EntryKind::File => {
+                    entry.hash = match known.get(entry.path.as_str()) {
+                        Some(old) if old.same_stat(&entry) && old.hash.is_some() => old.hash.clone(),
+                        _ => hash_file(&path).ok(),
+                    };
+                }
+                EntryKind::Dir => {
+                    let mut children: Vec<PathBuf> = fs::read_dir(&path)
+                        .wrap_err_with(|| format!("Failed to read {}", path.display()))?
+                        .filter_map(|e| e.ok().map(|e| e.path()))
+                        .collect();
+                    children.sort_by(|a, b| b.cmp(a));
+                    pending.extend(children);
+                }
+                EntryKind::Symlink | EntryKind::Other => {}
+            }
+            entries.push(entry);
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Entries of `self` that differ from `parent`, and those of `parent`
+    /// that are gone.
+    pub fn delta(&self, parent: &FileManifest) -> Delta {
+        let before: HashMap<&str, &FileEntry> = parent.entries.iter().map(|e| (e.path.as_str(), e)).collect();
+        let after: HashMap<&str, &FileEntry> = self.entries.iter().map(|e| (e.path.as_str(), e)).collect();
+
+        let changed = self
+            .entries
+            .iter()
+            .filter(|e| e.kind != EntryKind::Dir)
+            .filter(|e| !before.get(e.path.as_str()).is_some_and(|old| e.unchanged_from(old)))
+            .map(|e| e.path.clone())
+            .collect();
+        let deleted = parent
+            .entries
+            .iter()
+            .filter(|e| !after.contains_key(e.path.as_str()))
+            .map(|e| e.path.clone())
+            .collect();
+
+        Delta { changed, deleted }
+    }
+
+    /// Every directory, in tree order. Incremental payloads carry them all so
+    /// that restored directories keep their recorded modes and times.
+    pub fn directories(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::Dir)
+            .map(|e| e.path.as_str())
+    }
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_records_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("conf");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "alpha").unwrap();
+        fs::write(root.join("sub/b.txt"), "beta").unwrap();
+        std::os::unix::fs::symlink("a.txt", root.join("link")).unwrap();
+
+        let manifest = FileManifest::scan(&root, temp_dir.path(), None).unwrap();
+        let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["conf", "conf/a.txt", "conf/link", "conf/sub", "conf/sub/b.txt"]
+        );
+
+        let a = &manifest.entries[1];
+        assert_eq!(a.kind, EntryKind::File);
+        assert_eq!(a.size, 5);
+        assert_eq!(a.hash.as_deref().map(str::len), Some(64));
+        assert_eq!(manifest.entries[2].link, Some(PathBuf::from("a.txt")));
+    }
+
+    #[test]
+    fn test_delta_reports_changes_and_deletions() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("conf");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("same.txt"), "same").unwrap();
+        fs::write(root.join("edit.txt"), "before").unwrap();
+        fs::write(root.join("gone.txt"), "gone").unwrap();
+        let parent = FileManifest::scan(&root, temp_dir.path(), None).unwrap();
+
+        fs::write(root.join("edit.txt"), "after the edit").unwrap();
+        fs::remove_file(root.join("gone.txt")).unwrap();
+        fs::write(root.join("new.txt"), "new").unwrap();
+        let current = FileManifest::scan(&root, temp_dir.path(), Some(&parent)).unwrap();
+
+        let delta = current.delta(&parent);
+        let mut changed = delta.changed.clone();
+        changed.sort();
+        assert_eq!(changed, vec!["conf/edit.txt", "conf/new.txt"]);
+        assert_eq!(delta.deleted, vec!["conf/gone.txt"]);
+    }
+
+    #[test]
+    fn test_delta_skips_files_that_were_only_touched() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("conf");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("touched.txt"), "same").unwrap();
+        fs::write(root.join("rewritten.txt"), "abcd").unwrap();
+        let parent = FileManifest::scan(&root, temp_dir.path(), None).unwrap();
+
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(root.join("touched.txt"))
+            .and_then(|f| f.set_modified(later))
+            .unwrap();
+        fs::write(root.join("rewritten.txt"), "wxyz").unwrap();
+        File::options()
+            .write(true)
+            .open(root.join("rewritten.txt"))
+            .and_then(|f| f.set_modified(later))
+            .unwrap();
+        let current = FileManifest::scan(&root, temp_dir.path(), Some(&parent)).unwrap();
+
+        assert_eq!(current.delta(&parent).changed, vec!["conf/rewritten.txt"]);
+    }
+}
@@ -0,0 +1,306 @@
+//! How the search patterns of `ls-rmrf` and `ls-bkup` select bundles.
+//!
+//! Every pattern is tried on the same fields of every bundle: its name, each
+//! target, its `cwd` and each line of its file tree. A bundle's match is the
+//! best any pattern gets on any field. Fuzzy scores are a percentage of what
+//! the pattern scores against itself, so one `threshold` means the same for
+//! every field and every pattern length. The other modes either match, with
+//! a score of 100, or do not.
+//!
+//! The file tree is `eza --tree --long` output, so only the path part of
+//! each line is scored: mode, size, owner and date would otherwise match
+//! in every bundle.
+
+use crate::listing::BundleRecord;
+use eyre::{Context, Result};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Score of a pattern that matches outright.
+const FULL_SCORE: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MatchMode {
+    /// Skim-style fuzzy matching, scored against the threshold.
+    #[default]
+    Fuzzy,
+    /// A regular expression found anywhere in the field.
+    Regex,
+    /// The pattern appears verbatim in the field.
+    Exact,
+    /// A shell wildcard pattern matching the whole field.
+    Glob,
+}
+
+/// Which part of a bundle a pattern matched.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Name,
+    Target,
+    Cwd,
+    Contents,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Field::Name => "name",
+            Field::Target => "target",
+            Field::Cwd => "cwd",
+            Field::Contents => "contents",
+        })
+    }
+}
+
+/// The best match of a bundle, for `--explain`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Match {
+    pub field: Field,
+    /// The field's text, or the line of the file tree, that matched.
+    pub text: String,
+    pub pattern: String,
+    pub score: i64,
+}
+
+enum Pattern {
+    /// With the score the pattern gets against itself.
+    Fuzzy(String, i64),
+    Regex(Regex),
+    Exact(String),
+    Glob(String),
+}
+
+pub struct Matcher {
+    patterns: Vec<(String, Pattern)>,
+    threshold: i64,
+    fuzzy: SkimMatcherV2,
+}
+
+impl Matcher {
+    pub fn new(mode: MatchMode, patterns: &[String], threshold: i64) -> Result<Self> {
+        let fuzzy = SkimMatcherV2::default();
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let compiled = match mode {
+                    MatchMode::Fuzzy => {
+                        let perfect = fuzzy.fuzzy_match(pattern, pattern).unwrap_or(0).max(1);
+                        Pattern::Fuzzy(pattern.clone(), perfect)
+                    }
+                    MatchMode::Regex => {
+                        Pattern::Regex(Regex::new(pattern).wrap_err_with(|| format!("invalid regex {:?}", pattern))?)
+                    }
+                    MatchMode::Exact => Pattern::Exact(pattern.clone()),
+                    MatchMode::Glob => Pattern::Glob(pattern.clone()),
+                };
+                Ok((pattern.clone(), compiled))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            patterns,
+            threshold,
+            fuzzy,
+        })
+    }
+
+    fn score(&self, pattern: &Pattern, text: &str) -> Option<i64> {
+        match pattern {
+            Pattern::Fuzzy(pattern, perfect) => self
+                .fuzzy
+                .fuzzy_match(text, pattern)
+                .map(|score| (score * FULL_SCORE / perfect).min(FULL_SCORE))
+                .filter(|score| *score >= self.threshold),
+            Pattern::Regex(regex) => regex.is_match(text).then_some(FULL_SCORE),
+            Pattern::Exact(pattern) => text.contains(pattern.as_str()).then_some(FULL_SCORE),
+            Pattern::Glob(glob) => crate::listing::glob_matches(glob, text).then_some(FULL_SCORE),
+        }
+    }
+
+    /// The best match of any pattern on any field of a bundle, given its
+    /// record and file tree. Earlier fields win ties.
+    pub fn best(&self, record: &BundleRecord, contents: Option<&str>) -> Option<Match> {
+        let cwd = record.cwd.as_ref().map(|c| c.display().to_string());
+        let fields = std::iter::once((Field::Name, record.name.as_str()))
+            .chain(record.targets.iter().map(|t| (Field::Target, t.as_str())))
+            .chain(cwd.as_deref().map(|c| (Field::Cwd, c)))
+            .chain(contents.into_iter().flat_map(str::lines).filter_map(|line| {
+                let entry = tree_path(line);
+                (!entry.is_empty()).then_some((Field::Contents, entry))
+            }));
+
+        let mut best: Option<Match> = None;
+        for (field, text) in fields {
+            for (source, pattern) in &self.patterns {
+                if let Some(score) = self.score(pattern, text) {
+                    if best.as_ref().is_none_or(|b| score > b.score) {
+                        best = Some(Match {
+                            field,
+                            text: text.to_string(),
+                            pattern: source.clone(),
+                            score,
+                        });
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Columns `eza --long` prints before the name: mode, size, owner, and a
+/// date of three words ("14 Jun 15:30" or "14 Jun  2024").
+const LONG_COLUMNS: usize = 6;
+
+/// The name in one line of an `eza --tree --long` listing, without the
+/// columns before it, the tree drawing, or a symlink's `-> target`.
+fn tree_path(line: &str) -> &str {
+    tree_entry(line).1
+}
+
+/// How deep the entry on one line of an `eza --tree --long` listing lies,
+/// 0 for a target itself, and its name as [`tree_path`] gives it.
+pub fn tree_entry(line: &str) -> (usize, &str) {
+    let mut rest = line;
+    let is_mode = |word: &str| {
+        word.len() >= 10
+            && word.starts_with(['.', '-', 'd', 'l', 'c', 'b', 'p', 's'])
+            && word[1..10].chars().all(|c| "rwxsStT-".contains(c))
+    };
+    if rest.split_whitespace().next().is_some_and(is_mode) {
+        for _ in 0..LONG_COLUMNS {
+            rest = rest.trim_start();
+            rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+        }
+        rest = rest.strip_prefix(' ').unwrap_or(rest);
+    }
+    let name = rest.trim_start_matches(['│', '├', '└', '─', ' ']);
+    // Each level is drawn three columns wide, and a space precedes the name.
+    let drawn = rest[..rest.len() - name.len()].chars().count();
+    let name = name.split_once(" -> ").map_or(name, |(name, _)| name);
+    (drawn.saturating_sub(1) / 3, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn record() -> BundleRecord {
+        BundleRecord {
+            name: "2026-06-01-120000-000".into(),
+            path: PathBuf::from("/var/tmp/rmrf/2026-06-01-120000-000"),
+            timestamp: None,
+            user: None,
+            uid: None,
+            host: None,
+            cwd: Some(PathBuf::from("/home/me/projects")),
+            targets: vec!["website".into()],
+            size: 0,
+            original_size: None,
+            files: None,
+            pinned: false,
+            score: None,
+            matched: None,
+        }
+    }
+
+    /// `eza --tree --long` output, as recorded in metadata.
+    const TREE: &str = "\
+drwxr-xr-x     - ann  1 Jun 12:00 website
+.rw-r--r--@ 2.1k ann  1 Jun 12:00 ├── index.html
+lrwxrwxrwx     - ann 14 May  2025 ├── home -> index.html
+drwxr-xr-x     - ann  1 Jun 12:00 └── assets
+.rw-r--r--   512 ann  1 Jun 12:00    └── logo.svg
+";
+
+    fn best(mode: MatchMode, pattern: &str) -> Option<(Field, String, i64)> {
+        Matcher::new(mode, &[pattern.to_string()], 70)
+            .unwrap()
+            .best(&record(), Some(TREE))
+            .map(|m| (m.field, m.text, m.score))
+    }
+
+    #[test]
+    fn test_fuzzy_threshold_applies_to_every_field() {
+        assert_eq!(
+            best(MatchMode::Fuzzy, "website"),
+            Some((Field::Target, "website".into(), 100))
+        );
+        assert_eq!(
+            best(MatchMode::Fuzzy, "projects"),
+            Some((Field::Cwd, "/home/me/projects".into(), 95))
+        );
+        assert_eq!(
+            best(MatchMode::Fuzzy, "logo"),
+            Some((Field::Contents, "logo.svg".into(), 100))
+        );
+        assert_eq!(best(MatchMode::Fuzzy, "zzz"), None);
+
+        // Scattered letters score lower, and the threshold cuts them off
+        // wherever they occur.
+        assert_eq!(
+            best(MatchMode::Fuzzy, "wbst"),
+            Some((Field::Target, "website".into(), 82))
+        );
+        let strict = Matcher::new(MatchMode::Fuzzy, &["wbst".to_string()], 90).unwrap();
+        assert_eq!(strict.best(&record(), Some(TREE)), None);
+    }
+
+    #[test]
+    fn test_other_modes() {
+        assert_eq!(
+            best(MatchMode::Exact, "me/proj"),
+            Some((Field::Cwd, "/home/me/projects".into(), 100))
+        );
+        assert_eq!(best(MatchMode::Exact, "Website"), None);
+        assert_eq!(
+            best(MatchMode::Glob, "*.svg"),
+            Some((Field::Contents, "logo.svg".into(), 100))
+        );
+        assert_eq!(best(MatchMode::Glob, "web"), None);
+        assert_eq!(
+            best(MatchMode::Regex, r"^2026-06"),
+            Some((Field::Name, record().name, 100))
+        );
+        assert!(Matcher::new(MatchMode::Regex, &["(".to_string()], 70).is_err());
+    }
+
+    #[test]
+    fn test_long_listing_columns_are_not_matched() {
+        for (mode, pattern) in [
+            (MatchMode::Exact, "Jun"),
+            (MatchMode::Exact, "rw-r"),
+            (MatchMode::Exact, "2.1k"),
+            (MatchMode::Regex, "12:00"),
+            (MatchMode::Glob, "*2025*"),
+        ] {
+            assert_eq!(best(mode, pattern), None, "{:?} {}", mode, pattern);
+        }
+        assert_eq!(best(MatchMode::Exact, "ann"), None, "the owner is no match");
+        assert_eq!(
+            best(MatchMode::Glob, "home"),
+            Some((Field::Contents, "home".into(), 100)),
+            "a symlink is matched by its name"
+        );
+        assert_eq!(tree_path("website"), "website", "lines without columns are kept");
+    }
+
+    #[test]
+    fn test_tree_entry_depths() {
+        let entries: Vec<(usize, &str)> = TREE.lines().map(tree_entry).collect();
+        assert_eq!(
+            entries,
+            vec![
+                (0, "website"),
+                (1, "index.html"),
+                (1, "home"),
+                (1, "assets"),
+                (2, "logo.svg")
+            ]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! `rkvr migrate`: bring bundles written by older releases up to date.
+//!
+//! Each kind of upgrade is a [`Migration`]; they run in the order listed in
+//! [`migrate`], each over every space, so later ones see what earlier ones
+//! changed. Nothing is touched without `--apply`.
+
+use crate::bundle_name::{BundleName, Scheme};
+use crate::crypt::{self, BundleKey, Envelope};
+use crate::schema::{self, SCHEMA_VERSION};
+use crate::{Metadata, Scope, BUNDLE_TIMESTAMP};
+use colored::*;
+use eyre::{Context, Result};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One kind of upgrade `rkvr migrate` knows how to make.
+trait Migration {
+    type Step: fmt::Display;
+
+    /// Heading for this migration's report.
+    fn describe(&self) -> &'static str;
+
+    /// Work out what to change among `bundles`, all in `space`.
+    fn plan(&self, space: &Path, bundles: &[PathBuf]) -> Vec<Result<Self::Step>>;
+
+    /// Carry out one planned step.
+    fn apply(&self, step: &Self::Step) -> Result<()>;
+}
+
+/// Changes planned (or made) and failures, over all migrations.
+#[derive(Default)]
+struct Tally {
+    steps: usize,
+    failed: usize,
+}
+
+/// Upgrade bundles in `spaces`. Only reports what would change unless
+/// `apply` is set.
+pub fn migrate(spaces: &[PathBuf], apply: bool, scope: Scope) -> Result<()> {
+    let mut tally = Tally::default();
+    run(&RenameLegacyBundles, spaces, apply, scope, &mut tally)?;
+    run(&UpgradeSchema, spaces, apply, scope, &mut tally)?;
+
+    println!();
+    println!(
+        "{} change{} {}",
+        tally.steps,
+        if tally.steps == 1 { "" } else { "s" },
+        if apply {
+            "made"
+        } else {
+            "to make (dry-run; pass --apply)"
+        }
+    );
+    if tally.failed > 0 {
+        eyre::bail!("{} bundles could not be migrated", tally.failed);
+    }
+    Ok(())
+}
+
+fn run<M: Migration>(migration: &M, spaces: &[PathBuf], apply: bool, scope: Scope, tally: &mut Tally) -> Result<()> {
+    for space in spaces {
+        let bundles: Vec<PathBuf> = crate::bundle_dirs(space)?
+            .into_iter()
+            .filter(|b| scope.includes(b))
+            .collect();
+        println!("=== {}: {} ===", migration.describe(), space.display());
+        for planned in migration.plan(space, &bundles) {
+            let result = planned.and_then(|step| {
+                println!("  {}", step);
+                tally.steps += 1;
+                if apply {
+                    migration.apply(&step)?;
+                }
+                Ok(())
+            });
+            if let Err(error) = result {
+                tally.failed += 1;
+                eprintln!("  {} {:#}", "FAILED".red(), error);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> std::borrow::Cow<'_, str> {
+    path.file_name().unwrap_or_default().to_string_lossy()
+}
+
+/// Rename bundles named in the EPOCH and ISO-T schemes (see
+/// [`crate::bundle_name`]) to the current one. Names are given in
+/// chronological order, with the same `-NNN` suffix
+/// [`crate::next_bundle_dir`] uses.
+struct RenameLegacyBundles;
+
+struct Rename {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl fmt::Display for Rename {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}  ->  {}", file_name(&self.from), file_name(&self.to))
+    }
+}
+
+impl Migration for RenameLegacyBundles {
+    type Step = Rename;
+
+    fn describe(&self) -> &'static str {
+        "bundle names"
+    }
+
+    fn plan(&self, space: &Path, bundles: &[PathBuf]) -> Vec<Result<Rename>> {
+        let mut legacy: Vec<(BundleName, &PathBuf)> = bundles
+            .iter()
+            .filter_map(|b| BundleName::of(b).map(|name| (name, b)))
+            .filter(|(name, _)| name.scheme != Scheme::Current)
+            .collect();
+        legacy.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+
+        let mut reserved = HashSet::new();
+        legacy
+            .into_iter()
+            .map(|(name, bundle)| {
+                // Incremental bkups name their parent; renaming it would
+                // break the chain.
+                if crate::has_children(bundle) {
+                    eyre::bail!("{}: later bundles build on it; leaving its name", bundle.display());
+                }
+                let timestamp = name.created.format(BUNDLE_TIMESTAMP).to_string();
+                let to = crate::next_unreserved_bundle_dir(space, &timestamp, &mut 0, &reserved);
+                reserved.insert(to.clone());
+                Ok(Rename {
+                    from: bundle.clone(),
+                    to,
+                })
+            })
+            .collect()
+    }
+
+    fn apply(&self, step: &Rename) -> Result<()> {
+        if step.to.exists() {
+            eyre::bail!("{} already exists", step.to.display());
+        }
+        fs::rename(&step.from, &step.to)
+            .wrap_err_with(|| format!("renaming {} to {}", step.from.display(), step.to.display()))
+    }
+}
+
+/// Rewrite metadata written with an older schema in the current one.
+/// Encrypted metadata stays encrypted.
+struct UpgradeSchema;
+
+struct Upgrade {
+    bundle: PathBuf,
+    from: u32,
+    metadata: Metadata,
+}
+
+impl fmt::Display for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}  metadata schema {} -> {}",
+            file_name(&self.bundle),
+            self.from,
+            SCHEMA_VERSION
+        )
+    }
+}
+
+impl Migration for UpgradeSchema {
+    type Step = Upgrade;
+
+    fn describe(&self) -> &'static str {
+        "metadata schema"
+    }
+
+    fn plan(&self, _space: &Path, bundles: &[PathBuf]) -> Vec<Result<Upgrade>> {
+        bundles
+            .iter()
+            .filter_map(|bundle| {
+                let parsed = crypt::read_bundle_file(bundle, "metadata.yml")
+                    .and_then(|raw| schema::parse(bundle, &raw))
+                    .wrap_err_with(|| bundle.display().to_string());
+                match parsed {
+                    Ok((_, from)) if from >= SCHEMA_VERSION => None,
+                    Ok((metadata, from)) => Some(Ok(Upgrade {
+                        bundle: bundle.clone(),
+                        from,
+                        metadata,
+                    })),
+                    Err(error) => Some(Err(error)),
+                }
+            })
+            .collect()
+    }
+
+    fn apply(&self, step: &Upgrade) -> Result<()> {
+        let key = match Envelope::load(&step.bundle)? {
+            Some(envelope) => Some(BundleKey::open(&envelope.key)?),
+            None => None,
+        };
+        let yaml = serde_yaml::to_string(&step.metadata).wrap_err("Failed to serialize metadata to YAML")?;
+        crypt::write_bundle_file(&step.bundle, "metadata.yml", yaml.as_bytes(), key.as_ref())
+            .wrap_err("Failed to write metadata file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrate_renames_and_upgrades_only_with_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path().to_path_buf();
+        let v0 = "cwd: /home/me\ncontents: |\n  notes.txt\n";
+        let current = "schema_version: 2\ncwd: /home/me\ntargets: []\ncontents: ''\n";
+        for (name, metadata) in [
+            ("2024-03-05T07-08-09-000", v0),
+            ("2024-03-05T07-08-09-001", v0),
+            ("2024-03-05-070809-000", current),
+        ] {
+            fs::create_dir(space.join(name)).unwrap();
+            fs::write(space.join(name).join("metadata.yml"), metadata).unwrap();
+        }
+
+        migrate(std::slice::from_ref(&space), false, Scope::AllUsers).unwrap();
+        assert!(space.join("2024-03-05T07-08-09-000").is_dir());
+
+        migrate(std::slice::from_ref(&space), true, Scope::AllUsers).unwrap();
+        let names: Vec<_> = crate::bundle_dirs(&space)
+            .unwrap()
+            .iter()
+            .map(|b| file_name(b).into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "2024-03-05-070809-000",
+                "2024-03-05-070809-001",
+                "2024-03-05-070809-002"
+            ]
+        );
+        for bundle in crate::bundle_dirs(&space).unwrap() {
+            let metadata = fs::read_to_string(bundle.join("metadata.yml")).unwrap();
+            assert!(metadata.starts_with(&format!("schema_version: {}\n", SCHEMA_VERSION)));
+        }
+        assert_eq!(
+            fs::read_to_string(space.join("2024-03-05-070809-000/metadata.yml")).unwrap(),
+            current,
+            "Current bundles are left alone"
+        );
+    }
+}
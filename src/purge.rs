@@ -0,0 +1,595 @@
+//! Purging single files from a bundle. The payloads holding them are
+//! rewritten without them and what the bundle records of them is dropped,
+//! so the rest restores as before and nothing of the purged files is left.
+
+use crate::compress::Codec;
+use crate::contents::{self, under_any};
+use crate::crypt::{self, BundleKey, Envelope};
+use crate::index;
+use crate::manifest::{FileManifest, FILE_MANIFEST};
+use crate::matching;
+use crate::store::{ChunkManifest, ChunkStore, StoreKind};
+use eyre::{eyre, Context, Result};
+use log::{debug, info};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::NamedTempFile;
+
+/// What purging takes out of a bundle.
+#[derive(Debug, Default)]
+struct Purged {
+    /// Every entry removed, from payloads and records alike.
+    entries: BTreeSet<String>,
+    /// Regular files among them and their total size, as counted in the
+    /// bundle's metadata.
+    files: u64,
+    bytes: u64,
+}
+
+/// Remove the entries of `bundle` at `paths`, and everything below them,
+/// returning how many were removed. Naming nothing the bundle holds is an
+/// error, as is a pinned bundle or one later incremental bkups build on.
+///
+/// Every payload holding one of them is written anew, at its codec's default
+/// level, and only swapped in once all are, so a failure leaves the bundle
+/// as it was. Files stored sparsely come back dense. An incremental bkup
+/// records the paths as deleted too, so restoring it drops the copies its
+/// parents hold.
+pub fn purge_files(bundle: &Path, paths: &[String], sudo: bool) -> Result<usize> {
+    let name = bundle.file_name().unwrap_or_default().to_string_lossy();
+    if crate::is_pinned(bundle) {
+        eyre::bail!("{} is pinned; unpin it first", name);
+    }
+    if crate::has_children(bundle) {
+        eyre::bail!("later incremental bkups build on {}", name);
+    }
+    let mut meta = crate::load_metadata(bundle)?;
+    let key = match Envelope::load(bundle)? {
+        Some(envelope) => Some(BundleKey::open(&envelope.key)?),
+        None => None,
+    };
+    let mut purged = Purged::default();
+
+    let mut chunks = None;
+    if meta.store == StoreKind::Chunked {
+        let store = ChunkStore::for_bundle(bundle)?;
+        let mut manifest = ChunkManifest::load(bundle)?;
+        let mut changed = false;
+        for payload in manifest.payloads.iter_mut() {
+            let filtered = filter_payload(
+                bundle,
+                Codec::None,
+                |out| store.restore(payload, out),
+                paths,
+                &mut purged,
+            )
+            .wrap_err_with(|| format!("payload {}", payload.name))?;
+            if let Some(tar) = filtered {
+                *payload = store.store(BufReader::new(tar.reopen()?), &payload.name, payload.privileged)?;
+                changed = true;
+            }
+        }
+        chunks = changed.then_some(manifest);
+    }
+
+    let mut names: Vec<String> = fs::read_dir(bundle)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| !crate::is_bookkeeping(name))
+        .collect();
+    names.sort();
+    let (loose, tarballs): (Vec<String>, Vec<String>) = names.into_iter().partition(|name| meta.targets.contains(name));
+
+    let mut rewritten = Vec::new();
+    for name in tarballs {
+        let path = bundle.join(&name);
+        let owner = fs::metadata(&path)?;
+        if owner.uid() != crate::current_uid() && crate::current_uid() != 0 && !sudo {
+            eyre::bail!(
+                "{} belongs to uid {}; purging from it needs sudo",
+                path.display(),
+                owner.uid()
+            );
+        }
+        let codec = match key {
+            Some(_) => meta.compression,
+            None => Codec::detect(&path).unwrap_or(meta.compression),
+        };
+        let filtered = filter_payload(
+            bundle,
+            codec,
+            |out| {
+                let file = BufReader::new(File::open(&path)?);
+                match &key {
+                    Some(key) => key.decrypt(file, out),
+                    None => Ok(io::copy(&mut { file }, out).map(|_| ())?),
+                }
+            },
+            paths,
+            &mut purged,
+        )
+        .wrap_err_with(|| format!("payload {}", name))?;
+        if let Some(tar) = filtered {
+            let sealed = seal(bundle, tar, codec, key.as_ref()).wrap_err_with(|| format!("rewriting {}", name))?;
+            rewritten.push((path, owner, sealed));
+        }
+    }
+
+    let mut doomed = Vec::new();
+    for path in paths {
+        if loose.iter().any(|name| under_any(path, std::slice::from_ref(name))) {
+            let copy = bundle.join(path);
+            if fs::symlink_metadata(&copy).is_ok() {
+                count_loose(&copy, path, &mut purged);
+                doomed.push(copy);
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+    for link in meta.links.drain(..) {
+        match under_any(&link.path, paths) {
+            true => {
+                purged.entries.insert(link.path);
+            }
+            false => links.push(link),
+        }
+    }
+    meta.links = links;
+    let mut specials = Vec::new();
+    for special in meta.specials.drain(..) {
+        match under_any(&special.path, paths) {
+            true => {
+                purged.entries.insert(special.path);
+            }
+            false => specials.push(special),
+        }
+    }
+    meta.specials = specials;
+
+    let files = match crypt::bundle_file_exists(bundle, FILE_MANIFEST) {
+        true => Some(FileManifest::load(bundle)?),
+        false => None,
+    };
+    let files = files.map(|mut files| {
+        for entry in files.entries.iter().filter(|entry| under_any(&entry.path, paths)) {
+            purged.entries.insert(entry.path.clone());
+        }
+        files.entries.retain(|entry| !under_any(&entry.path, paths));
+        files
+    });
+
+    if purged.entries.is_empty() {
+        eyre::bail!("{} holds none of {}", name, paths.join(", "));
+    }
+
+    // Everything is ready; swap it in.
+    for (path, owner, sealed) in rewritten {
+        fs::set_permissions(sealed.path(), owner.permissions())?;
+        sealed
+            .persist(&path)
+            .wrap_err_with(|| format!("replacing {}", path.display()))?;
+        restore_owner(&path, &owner)?;
+    }
+    if let Some(manifest) = chunks {
+        manifest.save(bundle)?;
+    }
+    for copy in doomed {
+        remove(&copy, sudo)?;
+    }
+
+    let mut kept_paths = meta.paths.iter();
+    let mut targets = Vec::new();
+    let mut canonical = Vec::new();
+    for target in meta.targets.drain(..) {
+        let path = kept_paths.next();
+        if !under_any(&target, paths) {
+            canonical.extend(path.cloned());
+            targets.push(target);
+        }
+    }
+    meta.targets = targets;
+    if !meta.paths.is_empty() {
+        meta.paths = canonical;
+    }
+    for group in meta.hardlinks.iter_mut() {
+        group.retain(|name| !under_any(name, paths));
+    }
+    meta.hardlinks.retain(|group| group.len() > 1);
+    meta.sparse.retain(|name| !under_any(name, paths));
+    meta.files = meta.files.map(|files| files.saturating_sub(purged.files));
+    meta.bytes = meta.bytes.map(|bytes| bytes.saturating_sub(purged.bytes));
+    meta.contents = prune_tree(&meta.contents, &meta.cwd, paths);
+    if meta.parent.is_some() {
+        for path in paths {
+            let matched = purged.entries.iter().any(|e| under_any(e, std::slice::from_ref(path)));
+            if matched && !meta.deleted.contains(path) {
+                meta.deleted.push(path.clone());
+            }
+        }
+    }
+
+    if let Some(files) = files {
+        files.save(bundle, key.as_ref())?;
+    }
+    let yaml = serde_yaml::to_string(&meta).wrap_err("Failed to serialize metadata to YAML")?;
+    crypt::write_bundle_file(bundle, "metadata.yml", yaml.as_bytes(), key.as_ref())
+        .wrap_err("Failed to write metadata file")?;
+    index::update(bundle);
+
+    info!("Purged {} entries from {}", purged.entries.len(), bundle.display());
+    Ok(purged.entries.len())
+}
+
+/// Copy the tar stream `produce` writes, compressed with `codec`, into a
+/// plain tar file in `bundle` without the entries at `paths`. Returns
+/// `None` when the stream holds none of them.
+fn filter_payload(
+    bundle: &Path,
+    codec: Codec,
+    produce: impl FnOnce(&mut io::PipeWriter) -> Result<()> + Send,
+    paths: &[String],
+    purged: &mut Purged,
+) -> Result<Option<NamedTempFile>> {
+    let mut tar = temporary(bundle)?;
+    let dropped = contents::tar_stream(codec, produce, |stream| {
+        filter_tar(stream, BufWriter::new(tar.as_file_mut()), paths, purged)
+    })?;
+    Ok((dropped > 0).then_some(tar))
+}
+
+/// Copy the tar stream `source` to `out` without the members at `paths`,
+/// returning how many were left out.
+fn filter_tar(source: &mut dyn Read, out: impl Write, paths: &[String], purged: &mut Purged) -> Result<usize> {
+    let mut archive = tar::Archive::new(source);
+    let mut builder = tar::Builder::new(out);
+    let mut dropped = 0;
+    for member in archive.entries()? {
+        let mut member = member?;
+        let name = member.path()?.into_owned();
+        let path = contents::member_path(&name);
+        let mut header = member.header().clone();
+        let kind = header.entry_type();
+        if under_any(&path, paths) {
+            debug!("Leaving out {}", path);
+            if kind.is_file() || kind.is_gnu_sparse() || kind.is_contiguous() {
+                purged.files += 1;
+                purged.bytes += member.size();
+            }
+            purged.entries.insert(path);
+            dropped += 1;
+            continue;
+        }
+        if kind.is_symlink() || kind.is_hard_link() {
+            let link = member
+                .link_name()?
+                .ok_or_else(|| eyre!("{} is a link without a target", path))?
+                .into_owned();
+            if kind.is_hard_link() && under_any(&contents::member_path(&link), paths) {
+                eyre::bail!(
+                    "{} is a hard link to {}, which holds its data; purge both",
+                    path,
+                    link.display()
+                );
+            }
+            builder.append_link(&mut header, &name, &link)?;
+            continue;
+        }
+        if kind.is_gnu_sparse() {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(member.size());
+        }
+        builder.append_data(&mut header, &name, &mut member)?;
+    }
+    builder.into_inner()?.flush()?;
+    // Drain the end-of-archive padding so the producer finishes cleanly.
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(dropped)
+}
+
+/// `tar` compressed with `codec` and sealed with `key`, as a payload is
+/// stored.
+fn seal(bundle: &Path, tar: NamedTempFile, codec: Codec, key: Option<&BundleKey>) -> Result<NamedTempFile> {
+    let Some(mut command) = codec.compress_command() else {
+        let Some(key) = key else {
+            return Ok(tar);
+        };
+        let mut sealed = temporary(bundle)?;
+        let mut encryptor = key.encryptor(BufWriter::new(sealed.as_file_mut()))?;
+        io::copy(&mut BufReader::new(tar.reopen()?), &mut encryptor)?;
+        encryptor.finish()?.flush()?;
+        return Ok(sealed);
+    };
+
+    let mut sealed = temporary(bundle)?;
+    let mut child = command
+        .stdin(tar.reopen()?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| eyre!("compressor produced no output stream"))?;
+    let copied = match key {
+        Some(key) => key
+            .encryptor(BufWriter::new(sealed.as_file_mut()))
+            .and_then(|mut encryptor| {
+                io::copy(&mut stdout, &mut encryptor)?;
+                Ok(encryptor.finish()?.flush()?)
+            }),
+        None => io::copy(&mut stdout, sealed.as_file_mut())
+            .map(|_| ())
+            .map_err(eyre::Report::from),
+    };
+    drop(stdout);
+    let output = child.wait_with_output()?;
+    copied?;
+    if !output.status.success() {
+        eyre::bail!("compression failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(sealed)
+}
+
+/// A scratch file in `bundle`, removed unless persisted. Readers take it for
+/// a payload while it exists, so it never outlives a purge.
+fn temporary(bundle: &Path) -> Result<NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix(".rkvr-purge-")
+        .tempfile_in(bundle)
+        .wrap_err_with(|| format!("Failed to create a scratch file in {}", bundle.display()))
+}
+
+/// Give a rewritten payload back to the owner of the one it replaces, which
+/// decides whether it is extracted with sudo.
+fn restore_owner(path: &Path, owner: &fs::Metadata) -> Result<()> {
+    if owner.uid() == crate::current_uid() {
+        return Ok(());
+    }
+    if crate::current_uid() == 0 {
+        return std::os::unix::fs::chown(path, Some(owner.uid()), Some(owner.gid()))
+            .wrap_err_with(|| format!("Failed to hand {} back to uid {}", path.display(), owner.uid()));
+    }
+    let status = Command::new("sudo")
+        .arg("chown")
+        .arg(format!("{}:{}", owner.uid(), owner.gid()))
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        eyre::bail!(
+            "Failed to hand {} back to uid {} (status {})",
+            path.display(),
+            owner.uid(),
+            status
+        );
+    }
+    Ok(())
+}
+
+/// Record the loose copy at `path`, recorded as `rel`, and everything below
+/// it as purged.
+fn count_loose(path: &Path, rel: &str, purged: &mut Purged) {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return;
+    };
+    purged.entries.insert(rel.to_string());
+    if meta.is_file() {
+        purged.files += 1;
+        purged.bytes += meta.len();
+    }
+    if meta.is_dir() {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            let child = format!("{}/{}", rel, entry.file_name().to_string_lossy());
+            count_loose(&entry.path(), &child, purged);
+        }
+    }
+}
+
+/// Remove a loose copy, with sudo if it was copied with sudo.
+fn remove(path: &Path, sudo: bool) -> Result<()> {
+    match crate::remove_target(path) {
+        Err(error) if sudo => {
+            debug!("Removing {} with sudo: {:#}", path.display(), error);
+            let status = Command::new("sudo").args(["rm", "-rf"]).arg(path).status()?;
+            if !status.success() {
+                eyre::bail!("Failed to remove {} with sudo (status {})", path.display(), status);
+            }
+            Ok(())
+        }
+        result => result.wrap_err_with(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+/// The eza tree recorded in a bundle's metadata without the lines of
+/// entries at `paths`. Targets head their trees by the path eza was given.
+fn prune_tree(tree: &str, cwd: &Path, paths: &[String]) -> String {
+    let mut parents: Vec<String> = Vec::new();
+    let mut kept = String::new();
+    for line in tree.lines() {
+        let (depth, name) = matching::tree_entry(line);
+        parents.truncate(depth);
+        let path = match parents.last() {
+            Some(parent) => format!("{}/{}", parent, name),
+            None => {
+                let root = Path::new(name);
+                root.strip_prefix(cwd).unwrap_or(root).to_string_lossy().into_owned()
+            }
+        };
+        if !under_any(&path, paths) {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+        parents.push(path);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::Compression;
+    use crate::crypt::EncryptionConfig;
+    use crate::{archive, load_metadata, recover, verify_bundle, ArchiveOptions, Scope};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    /// Paths of every entry in the payloads of `bundle`.
+    fn archived(bundle: &Path) -> Vec<String> {
+        let mut paths = Vec::new();
+        contents::walk(bundle, &mut |entry| {
+            paths.push(entry.file.path.clone());
+            Ok(())
+        })
+        .unwrap();
+        paths.sort();
+        paths
+    }
+
+    /// Bkup a `source` dir holding keep.txt, secret.txt and sub/also.txt,
+    /// purge the secret and sub from it, and recover what is left.
+    fn purge_round_trip(opts: ArchiveOptions) {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let archive_dir = temp_dir.path().join("archive");
+        fs::create_dir_all(source_dir.join("sub")).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(source_dir.join("keep.txt"), "keep").unwrap();
+        fs::write(source_dir.join("secret.txt"), "hunter2").unwrap();
+        fs::write(source_dir.join("sub/also.txt"), "also").unwrap();
+        archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+        let bundle = archive_dir.join("2026-06-14-153045-000");
+
+        let paths = ["source/secret.txt".to_string(), "source/sub".to_string()];
+        assert_eq!(purge_files(&bundle, &paths, false).unwrap(), 3);
+        assert_eq!(archived(&bundle), vec!["source", "source/keep.txt"]);
+        verify_bundle(&bundle).unwrap();
+        let meta = load_metadata(&bundle).unwrap();
+        assert_eq!(meta.files, Some(1));
+        assert!(!meta.contents.contains("secret.txt") && meta.contents.contains("keep.txt"));
+        let error = purge_files(&bundle, &paths, false).unwrap_err();
+        assert!(format!("{error:#}").contains("holds none of"), "{error:#}");
+
+        fs::remove_dir_all(&source_dir).unwrap();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-153045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("keep.txt")).unwrap(), "keep");
+        assert!(!source_dir.join("secret.txt").exists());
+        assert!(!source_dir.join("sub").exists());
+    }
+
+    #[test]
+    fn test_purge_files_from_tarball() {
+        purge_round_trip(ArchiveOptions {
+            compression: Compression {
+                codec: Codec::Zstd,
+                level: Some(19),
+            },
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_purge_files_from_chunked_bundle() {
+        purge_round_trip(ArchiveOptions {
+            store: StoreKind::Chunked,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_purge_files_from_encrypted_bundle() {
+        let temp_dir = TempDir::new().unwrap();
+        let keyfile = temp_dir.path().join("rkvr.key");
+        fs::write(&keyfile, "a key file with enough entropy").unwrap();
+        purge_round_trip(ArchiveOptions {
+            encryption: Some(EncryptionConfig { keyfile: Some(keyfile) }),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_purge_files_from_incremental_bkup() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let archive_dir = temp_dir.path().join("archive");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(source_dir.join("keep.txt"), "keep").unwrap();
+        fs::write(source_dir.join("secret.txt"), "v1").unwrap();
+        let opts = ArchiveOptions {
+            incremental: true,
+            ..Default::default()
+        };
+        archive(
+            &archive_dir,
+            "2026-06-14-153045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+        fs::write(source_dir.join("secret.txt"), "version 2").unwrap();
+        archive(
+            &archive_dir,
+            "2026-06-14-163045",
+            std::slice::from_ref(&source_dir),
+            &opts,
+        )
+        .unwrap();
+        let full = archive_dir.join("2026-06-14-153045-000");
+        let incr = archive_dir.join("2026-06-14-163045-000");
+
+        let paths = ["source/secret.txt".to_string()];
+        let error = purge_files(&full, &paths, false).unwrap_err();
+        assert!(format!("{error:#}").contains("build on"), "{error:#}");
+        assert_eq!(purge_files(&incr, &paths, false).unwrap(), 1);
+        assert_eq!(load_metadata(&incr).unwrap().deleted, paths);
+
+        fs::remove_dir_all(&source_dir).unwrap();
+        recover(
+            std::slice::from_ref(&archive_dir),
+            &[PathBuf::from("2026-06-14-163045-000")],
+            false,
+            Scope::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("keep.txt")).unwrap(), "keep");
+        assert!(
+            !source_dir.join("secret.txt").exists(),
+            "the parent's copy is dropped too"
+        );
+    }
+
+    #[test]
+    fn test_prune_tree_drops_purged_lines() {
+        let tree = "\
+drwxr-xr-x     - ann  1 Jun 12:00 /home/ann/website
+.rw-r--r--@ 2.1k ann  1 Jun 12:00 ├── index.html
+drwxr-xr-x     - ann  1 Jun 12:00 ├── secrets
+.rw-------    64 ann  1 Jun 12:00 │  └── token
+drwxr-xr-x     - ann  1 Jun 12:00 └── assets
+.rw-r--r--   512 ann  1 Jun 12:00    └── logo.svg
+";
+        let pruned = prune_tree(
+            tree,
+            Path::new("/home/ann"),
+            &["website/secrets".to_string(), "website/assets/logo.svg".to_string()],
+        );
+        let names: Vec<&str> = pruned.lines().map(|line| matching::tree_entry(line).1).collect();
+        assert_eq!(names, vec!["/home/ann/website", "index.html", "assets"]);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::Metadata;
+use eyre::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the `metadata.yml` layout this rkvr writes.
+///
+/// - 0: the first releases, which recorded only `cwd` and the eza tree.
+/// - 1: unversioned metadata naming the `targets`; it grew optional fields
+///   (links, codec, store, provenance, ...) before versioning began.
+/// - 2: `schema_version` is recorded.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Version 0 metadata.
+#[derive(Deserialize)]
+struct V0 {
+    cwd: PathBuf,
+    #[serde(default)]
+    contents: String,
+}
+
+/// The schema version of raw metadata: recorded from version 2 on, and
+/// told apart by the `targets` key before that.
+fn version_of(value: &Value) -> Result<u32> {
+    match value.get("schema_version") {
+        Some(version) => serde_yaml::from_value(version.clone()).wrap_err("reading schema_version"),
+        None if value.get("targets").is_some() => Ok(1),
+        None => Ok(0),
+    }
+}
+
+/// Read the metadata of `bundle` from `raw`, whatever its version, upgraded
+/// to the current layout. Also returns the version it was written with.
+///
+/// Metadata from a newer rkvr is read as far as this version understands it;
+/// unknown fields are ignored and its version is kept, so it is never
+/// rewritten as an older one.
+pub fn parse(bundle: &Path, raw: &[u8]) -> Result<(Metadata, u32)> {
+    let value: Value = serde_yaml::from_slice(raw).wrap_err("parsing metadata.yml")?;
+    let version = version_of(&value)?;
+    let mut metadata = match version {
+        0 => upgrade_v0(
+            bundle,
+            serde_yaml::from_value(value).wrap_err("parsing version 0 metadata.yml")?,
+        )?,
+        _ => serde_yaml::from_value::<Metadata>(value)
+            .wrap_err_with(|| format!("parsing version {} metadata.yml", version))?,
+    };
+    if version > SCHEMA_VERSION {
+        warn!(
+            "{} was written by a newer rkvr (metadata schema {}, this one knows {})",
+            bundle.display(),
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    metadata.schema_version = version.max(SCHEMA_VERSION);
+    Ok((metadata, version))
+}
+
+/// Version 0 did not name its targets. Recovery only needs the loose copies
+/// among them, to tell them apart from tarballs, and those are the bundle's
+/// remaining entries.
+fn upgrade_v0(bundle: &Path, v0: V0) -> Result<Metadata> {
+    let mut targets: Vec<String> = fs::read_dir(bundle)
+        .wrap_err_with(|| format!("reading {}", bundle.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| !crate::is_bookkeeping(name) && !crate::is_archive(Path::new(name)))
+        .collect();
+    targets.sort();
+
+    Ok(Metadata {
+        cwd: v0.cwd,
+        targets,
+        contents: v0.contents,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_each_version_is_read() {
+        let temp_dir = TempDir::new().unwrap();
+        let bundle = temp_dir.path();
+        fs::write(bundle.join("notes.txt"), "loose copy").unwrap();
+        fs::write(bundle.join("project.tar.gz"), "payload").unwrap();
+
+        let (v0, version) = parse(bundle, b"cwd: /home/me\ncontents: |\n  notes.txt\n").unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(v0.cwd, PathBuf::from("/home/me"));
+        assert_eq!(v0.targets, vec!["notes.txt"]);
+        assert_eq!(v0.schema_version, SCHEMA_VERSION);
+
+        let (v1, version) = parse(bundle, b"cwd: /home/me\ntargets:\n- project\ncontents: ''\n").unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(v1.targets, vec!["project"]);
+
+        let current = serde_yaml::to_string(&v1).unwrap();
+        assert!(current.starts_with(&format!("schema_version: {}\n", SCHEMA_VERSION)));
+        assert_eq!(parse(bundle, current.as_bytes()).unwrap().1, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_newer_version_is_read_and_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let raw = b"schema_version: 99\ncwd: /home/me\ntargets: []\nfrom_the_future: true\ncontents: ''\n";
+
+        let (metadata, version) = parse(temp_dir.path(), raw).unwrap();
+        assert_eq!(version, 99);
+        assert_eq!(metadata.schema_version, 99);
+        assert_eq!(metadata.cwd, PathBuf::from("/home/me"));
+    }
+}
@@ -0,0 +1,208 @@
+//! `rkvr find`: search the files inside bundles, by path and optionally by
+//! contents.
+
+use crate::contents::{self, Entry};
+use crate::index;
+use crate::manifest::EntryKind;
+use crate::Scope;
+use colored::*;
+use eyre::Result;
+use regex::Regex;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// How much of a file is checked for NUL bytes before grepping it; files
+/// with any are taken to be binary and skipped.
+const BINARY_PROBE: usize = 8192;
+
+/// A file inside a bundle that matched, with the matching lines (1-based)
+/// when contents were searched.
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+    pub path: String,
+    pub lines: Vec<(usize, String)>,
+}
+
+/// What `find` looks for. A path `pattern` with shell wildcards must match
+/// the whole inner path; without, it matches any part of it.
+pub struct Query {
+    pub pattern: Option<String>,
+    pub grep: Option<Regex>,
+}
+
+impl Query {
+    fn path_matches(&self, path: &str) -> bool {
+        match &self.pattern {
+            None => true,
+            Some(pattern) if pattern.contains(['*', '?', '[']) => crate::listing::glob_matches(pattern, path),
+            Some(pattern) => path.contains(pattern.as_str()),
+        }
+    }
+
+    /// Whether `entry` is a hit.
+    fn check(&self, entry: &mut Entry) -> Result<Option<Hit>> {
+        if !self.path_matches(&entry.file.path) {
+            return Ok(None);
+        }
+        let Some(grep) = &self.grep else {
+            return Ok(Some(Hit {
+                path: entry.file.path.clone(),
+                lines: vec![],
+            }));
+        };
+        if entry.file.kind != EntryKind::File {
+            return Ok(None);
+        }
+        let lines = grep_lines(grep, &mut entry.contents)?;
+        Ok((!lines.is_empty()).then(|| Hit {
+            path: entry.file.path.clone(),
+            lines,
+        }))
+    }
+}
+
+/// Lines of a text stream matching `grep`. Binary contents never match.
+fn grep_lines(grep: &Regex, contents: &mut dyn Read) -> Result<Vec<(usize, String)>> {
+    let mut reader = BufReader::with_capacity(BINARY_PROBE, contents);
+    if reader.fill_buf()?.contains(&0) {
+        return Ok(vec![]);
+    }
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    let mut number = 0;
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        number += 1;
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if grep.is_match(text) {
+            lines.push((number, text.to_string()));
+        }
+        line.clear();
+    }
+    Ok(lines)
+}
+
+/// Every hit inside `bundle`.
+pub fn search_bundle(bundle: &Path, query: &Query) -> Result<Vec<Hit>> {
+    let mut hits = Vec::new();
+    contents::walk(bundle, &mut |entry| {
+        if let Some(hit) = query.check(entry)? {
+            hits.push(hit);
+        }
+        Ok(())
+    })?;
+    Ok(hits)
+}
+
+/// Print `bundle<TAB>inner/path` for every hit in the bundles of `spaces`
+/// within `scope`, oldest bundle first, followed by `:line:text` for each
+/// matching line when contents are searched. Bundles that cannot be read are
+/// reported and skipped.
+pub fn find(spaces: &[PathBuf], query: &Query, scope: Scope) -> Result<()> {
+    if query.pattern.is_none() && query.grep.is_none() {
+        eyre::bail!("Give a path pattern, --grep, or both");
+    }
+    let mut out = io::stdout().lock();
+    let mut failed = 0;
+    for space in spaces {
+        let bundles = index::bundles(space)?
+            .into_iter()
+            .filter(|b| scope.owns(b.record.uid))
+            .map(|b| b.record.path);
+        for bundle in bundles {
+            let hits = match &query.grep {
+                // Paths alone are answered from the index.
+                None => index::files(&bundle).map(|files| {
+                    files
+                        .into_iter()
+                        .filter(|file| query.path_matches(&file.path))
+                        .map(|file| Hit {
+                            path: file.path,
+                            lines: vec![],
+                        })
+                        .collect()
+                }),
+                Some(_) => search_bundle(&bundle, query),
+            };
+            let hits = match hits {
+                Ok(hits) => hits,
+                Err(error) => {
+                    failed += 1;
+                    eprintln!("{} {}: {:#}", "SKIPPED".yellow(), bundle.display(), error);
+                    continue;
+                }
+            };
+            for hit in hits {
+                if hit.lines.is_empty() {
+                    writeln!(out, "{}\t{}", bundle.display().to_string().blue(), hit.path)?;
+                }
+                for (number, text) in &hit.lines {
+                    writeln!(
+                        out,
+                        "{}\t{}:{}:{}",
+                        bundle.display().to_string().blue(),
+                        hit.path,
+                        number.to_string().green(),
+                        text
+                    )?;
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        eyre::bail!("{} bundles could not be searched", failed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn query(pattern: Option<&str>, grep: Option<&str>) -> Query {
+        Query {
+            pattern: pattern.map(String::from),
+            grep: grep.map(|g| Regex::new(g).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_search_bundle_paths_and_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let bundle = temp_dir.path();
+        fs::create_dir_all(bundle.join("conf/nested")).unwrap();
+        fs::write(
+            bundle.join("conf/nested/app.toml"),
+            "port = 80\nhost = \"x\"\nport = 81\n",
+        )
+        .unwrap();
+        fs::write(bundle.join("conf/blob.bin"), b"port\0=80").unwrap();
+        fs::write(
+            bundle.join("metadata.yml"),
+            "cwd: /etc\ntargets:\n- conf\ncontents: ''\n",
+        )
+        .unwrap();
+
+        let paths =
+            |q: &Query| -> Vec<String> { search_bundle(bundle, q).unwrap().into_iter().map(|h| h.path).collect() };
+        assert_eq!(paths(&query(Some("app"), None)), ["conf/nested/app.toml"]);
+        assert_eq!(paths(&query(Some("conf/*.toml"), None)), ["conf/nested/app.toml"]);
+        assert_eq!(paths(&query(Some("*.toml"), None)), ["conf/nested/app.toml"]);
+        assert!(paths(&query(Some("app.yml"), None)).is_empty());
+
+        let hits = search_bundle(bundle, &query(None, Some("^port"))).unwrap();
+        assert_eq!(
+            hits,
+            [Hit {
+                path: "conf/nested/app.toml".into(),
+                lines: vec![(1, "port = 80".into()), (3, "port = 81".into())],
+            }],
+            "Binary files are not grepped"
+        );
+        assert!(search_bundle(bundle, &query(Some("blob"), Some("port")))
+            .unwrap()
+            .is_empty());
+    }
+}
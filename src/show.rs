@@ -0,0 +1,363 @@
+//! `rkvr show`: one bundle in depth. Where it came from, what each payload
+//! takes up and holds, and whether it all still reads back.
+
+use crate::contents;
+use crate::crypt;
+use crate::listing;
+use crate::manifest::{EntryKind, FileEntry};
+use crate::store::{ChunkManifest, ChunkStore, StoreKind};
+use crate::Scope;
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use colored::*;
+use eyre::Result;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    Tarball,
+    /// An encrypted tarball.
+    Sealed,
+    /// A tar stream in the space's chunk store.
+    Chunked,
+    /// A target copied into the bundle as it was.
+    Loose,
+}
+
+impl fmt::Display for PayloadKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PayloadKind::Tarball => "tarball",
+            PayloadKind::Sealed => "sealed tarball",
+            PayloadKind::Chunked => "chunked",
+            PayloadKind::Loose => "loose copy",
+        })
+    }
+}
+
+/// One payload of a bundle and what it holds.
+#[derive(Debug)]
+pub struct Payload {
+    pub name: String,
+    pub kind: PayloadKind,
+    /// Bytes it takes up on disk, compressed; for chunked payloads, those
+    /// of every chunk it references, shared with other bundles or not.
+    pub stored: u64,
+    pub entries: Vec<FileEntry>,
+}
+
+impl Payload {
+    /// Bytes of the regular files inside, uncompressed.
+    pub fn size(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::File)
+            .map(|e| e.size)
+            .sum()
+    }
+}
+
+/// Every payload of `bundle` with its entries, in the order recovery
+/// restores them.
+pub fn payloads(bundle: &Path) -> Result<Vec<Payload>> {
+    let meta = crate::load_metadata(bundle)?;
+    let sealed = bundle.join(crypt::ENVELOPE_FILE).exists();
+    let mut payloads = Vec::new();
+    if meta.store == StoreKind::Chunked {
+        let store = ChunkStore::for_bundle(bundle)?;
+        for payload in ChunkManifest::load(bundle)?.payloads {
+            payloads.push(Payload {
+                stored: store.stored_size(&payload),
+                name: payload.name,
+                kind: PayloadKind::Chunked,
+                entries: vec![],
+            });
+        }
+    }
+
+    let mut names: Vec<String> = fs::read_dir(bundle)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| !crate::is_bookkeeping(name))
+        .collect();
+    names.sort();
+    for name in names {
+        let path = bundle.join(&name);
+        let (kind, stored) = if meta.targets.contains(&name) {
+            (PayloadKind::Loose, listing::disk_usage(&path))
+        } else if sealed {
+            (PayloadKind::Sealed, fs::symlink_metadata(&path)?.len())
+        } else {
+            (PayloadKind::Tarball, fs::symlink_metadata(&path)?.len())
+        };
+        payloads.push(Payload {
+            name,
+            kind,
+            stored,
+            entries: vec![],
+        });
+    }
+
+    contents::walk(bundle, &mut |entry| {
+        if let Some(payload) = payloads.iter_mut().find(|p| p.name == entry.payload) {
+            payload.entries.push(entry.file.clone());
+        }
+        Ok(())
+    })?;
+    Ok(payloads)
+}
+
+/// `ls -l` style permissions of an entry, e.g. `drwxr-xr-x`.
+fn mode_string(entry: &FileEntry) -> String {
+    let mut mode = String::from(match entry.kind {
+        EntryKind::File => '-',
+        EntryKind::Dir => 'd',
+        EntryKind::Symlink => 'l',
+        EntryKind::Other => '?',
+    });
+    for shift in [6, 3, 0] {
+        let bits = entry.mode >> shift;
+        mode.push(if bits & 4 != 0 { 'r' } else { '-' });
+        mode.push(if bits & 2 != 0 { 'w' } else { '-' });
+        mode.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    mode
+}
+
+fn format_mtime(nanos: i64) -> String {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos.max(0) as u64);
+    DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// Write everything about `bundle` to `out`. Payloads that cannot be read
+/// and failed verification are reported in place rather than as errors.
+pub fn write_bundle(bundle: &Path, out: &mut dyn Write) -> Result<()> {
+    let meta = crate::load_metadata(bundle)?;
+    let field = |out: &mut dyn Write, label: &str, value: &str| writeln!(out, "  {:<9} {}", label.white(), value);
+
+    writeln!(out, "{}", bundle.display().to_string().bright_blue().bold())?;
+    writeln!(out, "{}", "Provenance".bold())?;
+    if let Some(created) = crate::bundle_created(bundle) {
+        let created = DateTime::<Local>::from(created);
+        field(out, "created", &created.format("%Y-%m-%d %H:%M:%S").to_string())?;
+    }
+    let provenance = &meta.provenance;
+    if let Some(action) = &provenance.action {
+        field(out, "action", action)?;
+    }
+    if let Some(user) = &provenance.user {
+        let mut by = format!("{} (uid {})", user.owner_name(), user.owner_uid());
+        if user.sudo_user.is_some() {
+            by += " via sudo";
+        }
+        if let Some(host) = &provenance.host {
+            by += &format!(" on {}", host);
+        }
+        field(out, "by", &by)?;
+    }
+    if !provenance.command.is_empty() {
+        field(out, "command", &provenance.command.join(" "))?;
+    }
+    if let Some(version) = &provenance.version {
+        field(out, "version", version)?;
+    }
+    field(out, "cwd", &meta.cwd.display().to_string().bright_red().to_string())?;
+    field(out, "targets", &meta.targets.join(", "))?;
+    if let Some(parent) = &meta.parent {
+        field(out, "parent", &format!("{} (incremental)", parent))?;
+    }
+    let codec = meta
+        .compression
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default();
+    let mut store = match meta.store {
+        StoreKind::Tarball => format!("tarballs, {}", codec),
+        StoreKind::Chunked => "chunked".to_string(),
+    };
+    if bundle.join(crypt::ENVELOPE_FILE).exists() {
+        store += ", encrypted";
+    }
+    field(out, "store", &store)?;
+    let pinned = if crate::is_pinned(bundle) {
+        "yes, by hand"
+    } else if crate::has_children(bundle) {
+        "yes, a later incremental bkup builds on it"
+    } else {
+        "no"
+    };
+    field(out, "pinned", pinned)?;
+
+    writeln!(out, "{}", "Payloads".bold())?;
+    let payloads = match payloads(bundle) {
+        Ok(payloads) => payloads,
+        Err(error) => {
+            writeln!(out, "  {} {:#}", "UNREADABLE".red(), error)?;
+            vec![]
+        }
+    };
+    let width = payloads.iter().map(|p| p.name.len()).max().unwrap_or(0).max(4);
+    if !payloads.is_empty() {
+        writeln!(
+            out,
+            "  {}",
+            format!(
+                "{:<width$}  {:>7}  {:>7}  {:>7}  KIND",
+                "NAME", "STORED", "SIZE", "FILES"
+            )
+            .white()
+        )?;
+    }
+    for payload in &payloads {
+        let files = payload.entries.iter().filter(|e| e.kind == EntryKind::File).count();
+        writeln!(
+            out,
+            "  {:<width$}  {:>7}  {:>7}  {:>7}  {}",
+            payload.name,
+            listing::format_size(payload.stored),
+            listing::format_size(payload.size()),
+            files,
+            payload.kind
+        )?;
+    }
+
+    for payload in &payloads {
+        writeln!(out, "{} {}", "Files in".bold(), payload.name.bold())?;
+        let size_width = payload
+            .entries
+            .iter()
+            .map(|e| e.size.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for entry in &payload.entries {
+            let mut path = entry.path.clone();
+            if let Some(link) = &entry.link {
+                path += &format!(" -> {}", link.display());
+            }
+            writeln!(
+                out,
+                "  {} {:>5}:{:<5} {:>size_width$} {} {}",
+                mode_string(entry),
+                entry.uid,
+                entry.gid,
+                entry.size,
+                format_mtime(entry.mtime),
+                if entry.kind == EntryKind::Dir {
+                    path.blue().to_string()
+                } else {
+                    path
+                }
+            )?;
+        }
+    }
+
+    writeln!(out, "{}", "Verification".bold())?;
+    match crate::verify_bundle(bundle) {
+        Ok(()) => writeln!(out, "  {} every payload reads back in full", "ok".green())?,
+        Err(error) => writeln!(out, "  {} {:#}", "FAILED".red(), error)?,
+    }
+    Ok(())
+}
+
+/// `rkvr show`: the bundle `spec` names in `spaces`, paged on a terminal.
+pub fn show(spaces: &[PathBuf], spec: &str, scope: Scope) -> Result<()> {
+    let bundle = crate::find_bundle(spaces, spec, scope)?;
+    if !scope.includes(&bundle) {
+        eyre::bail!("{} belongs to another user; root can use --all-users", bundle.display());
+    }
+    if atty::is(atty::Stream::Stdout) {
+        crate::use_pager(|writer| write_bundle(&bundle, writer))
+    } else {
+        write_bundle(&bundle, &mut io::stdout().lock())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_payloads_and_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let cwd = temp_dir.path().join("work");
+        fs::create_dir_all(cwd.join("project")).unwrap();
+        fs::write(cwd.join("project/a.txt"), "alpha\n").unwrap();
+        fs::write(cwd.join("project/b.txt"), "bravo, longer\n").unwrap();
+
+        let bundle = temp_dir.path().join("2026-06-14-153045-000");
+        fs::create_dir_all(bundle.join("notes")).unwrap();
+        fs::write(bundle.join("notes/todo.txt"), "water plants\n").unwrap();
+        let status = Command::new("tar")
+            .args(["-czf", bundle.join("work.tar.gz").to_str().unwrap(), "-C"])
+            .arg(&cwd)
+            .arg("project")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::write(
+            bundle.join("metadata.yml"),
+            format!(
+                "cwd: {}\ntargets:\n- notes\naction: rmrf\nhost: box\ncontents: ''\n",
+                cwd.display()
+            ),
+        )
+        .unwrap();
+
+        let payloads = payloads(&bundle).unwrap();
+        let summary: Vec<(&str, PayloadKind, u64, usize)> = payloads
+            .iter()
+            .map(|p| (p.name.as_str(), p.kind, p.size(), p.entries.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("notes", PayloadKind::Loose, 13, 2),
+                ("work.tar.gz", PayloadKind::Tarball, 20, 3)
+            ]
+        );
+        assert_eq!(
+            payloads[1].stored,
+            fs::metadata(bundle.join("work.tar.gz")).unwrap().len()
+        );
+
+        colored::control::set_override(false);
+        let mut out = Vec::new();
+        write_bundle(&bundle, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.contains("  action    rmrf\n"), "{}", report);
+        assert!(report.contains("  pinned    no\n"), "{}", report);
+        assert!(report.contains("work.tar.gz"), "{}", report);
+        assert!(report.contains("Files in work.tar.gz\n  drwx"), "{}", report);
+        assert!(report.contains(" project/b.txt\n"), "{}", report);
+        assert!(report.contains("ok every payload reads back in full"), "{}", report);
+
+        fs::write(bundle.join("work.tar.gz"), b"\x1f\x8bbroken").unwrap();
+        let mut out = Vec::new();
+        write_bundle(&bundle, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.contains("UNREADABLE payload work.tar.gz"), "{}", report);
+        assert!(report.contains("FAILED payload work.tar.gz"), "{}", report);
+    }
+
+    #[test]
+    fn test_mode_string() {
+        let entry = FileEntry {
+            path: "bin".into(),
+            kind: EntryKind::Dir,
+            size: 0,
+            mtime: 0,
+            mode: 0o751,
+            uid: 0,
+            gid: 0,
+            hash: None,
+            link: None,
+        };
+        assert_eq!(mode_string(&entry), "drwxr-x--x");
+    }
+}
@@ -0,0 +1,243 @@
+use eyre::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Mode of archive spaces and bundle directories: only their owner may
+/// look inside.
+pub const PRIVATE_MODE: u32 = 0o700;
+
+/// Mode of a space shared by per-user subtrees: anyone may add their own
+/// subtree, like `/tmp`, but not touch anyone else's.
+pub const SHARED_MODE: u32 = 0o1777;
+
+/// What to do about an existing space that others can read or enter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionPolicy {
+    /// Print a warning and carry on.
+    #[default]
+    Warn,
+    /// Tighten the space to 0700.
+    Repair,
+}
+
+/// Create `path` (and any missing parents) readable by its owner only.
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(PRIVATE_MODE).create(path)
+}
+
+/// Make sure the archive space at `root` exists and is private, returning
+/// the directory bundles go into: `root` itself, or `root/<owner>` with
+/// `per_user`. Under sudo `owner` is the sudo caller, who is given the
+/// subtree root creates for them.
+pub fn prepare(root: &Path, per_user: bool, policy: PermissionPolicy, owner: u32) -> Result<PathBuf> {
+    if !per_user {
+        create_private_dir(root).wrap_err_with(|| format!("Failed to create {}", root.display()))?;
+        check_private(root, policy)?;
+        return Ok(root.to_path_buf());
+    }
+
+    if !root.exists() {
+        fs::create_dir_all(root).wrap_err_with(|| format!("Failed to create {}", root.display()))?;
+        // Set explicitly: the umask would strip the world-writable bits.
+        fs::set_permissions(root, fs::Permissions::from_mode(SHARED_MODE))?;
+    } else {
+        open_legacy_space(root)?;
+    }
+    let mode = fs::metadata(root)?.mode();
+    if mode & 0o002 != 0 && mode & 0o1000 == 0 {
+        warn!(
+            "Shared space {} is world-writable without the sticky bit",
+            root.display()
+        );
+        eprintln!(
+            "rkvr: warning: shared archive space {} is world-writable without the sticky bit (mode {:o}); \
+             other users can delete your subtree. Run `chmod +t {}`.",
+            root.display(),
+            mode & 0o7777,
+            root.display()
+        );
+    }
+
+    let own = root.join(owner.to_string());
+    create_private_dir(&own).wrap_err_with(|| format!("Failed to create {}", own.display()))?;
+    if crate::current_uid() == 0 && fs::metadata(&own)?.uid() != owner {
+        std::os::unix::fs::chown(&own, Some(owner), None)
+            .wrap_err_with(|| format!("Failed to hand {} to uid={}", own.display(), owner))?;
+    }
+    check_private(&own, policy)?;
+    Ok(own)
+}
+
+/// Make a space created private by a release without per-user subtrees
+/// shared, so other users can add theirs. One still holding bundles of its
+/// own is left alone, since opening it would expose them.
+fn open_legacy_space(root: &Path) -> Result<()> {
+    let meta = fs::metadata(root)?;
+    let me = crate::current_uid();
+    if meta.mode() & 0o7777 != PRIVATE_MODE || (meta.uid() != me && me != 0) {
+        return Ok(());
+    }
+
+    let legacy = fs::read_dir(root)?
+        .filter_map(|e| e.ok())
+        .filter(|e| crate::bundle_name::BundleName::of(&e.path()).is_some())
+        .count();
+    if legacy > 0 {
+        warn!(
+            "Space {} holds {} bundles outside per-user subtrees",
+            root.display(),
+            legacy
+        );
+        eprintln!(
+            "rkvr: warning: {} holds {} bundles from before per-user spaces, which are no longer listed; \
+             move them into {}/<uid>/, or set `per_user_spaces: false`",
+            root.display(),
+            legacy,
+            root.display()
+        );
+        return Ok(());
+    }
+
+    fs::set_permissions(root, fs::Permissions::from_mode(SHARED_MODE))
+        .wrap_err_with(|| format!("Failed to share {}", root.display()))?;
+    info!("Shared {} for per-user subtrees", root.display());
+    Ok(())
+}
+
+/// Every per-user subtree of the shared space at `root`, for root's
+/// `--all-users` view.
+pub fn user_subtrees(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut subtrees: Vec<PathBuf> = fs::read_dir(root)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    subtrees.sort();
+    Ok(subtrees)
+}
+
+/// Warn about, or repair, a space that is accessible to other users or is
+/// not owned by the caller.
+fn check_private(path: &Path, policy: PermissionPolicy) -> Result<()> {
+    let meta = fs::metadata(path)?;
+    let mode = meta.mode() & 0o7777;
+    let me = crate::current_uid();
+
+    if meta.uid() != me && me != 0 {
+        eprintln!(
+            "rkvr: warning: archive space {} is owned by uid={}, not you (uid={})",
+            path.display(),
+            meta.uid(),
+            me
+        );
+        return Ok(());
+    }
+    if mode & 0o077 == 0 {
+        return Ok(());
+    }
+
+    match policy {
+        PermissionPolicy::Repair => {
+            fs::set_permissions(path, fs::Permissions::from_mode(PRIVATE_MODE))
+                .wrap_err_with(|| format!("Failed to restrict permissions of {}", path.display()))?;
+            info!(
+                "Restricted {} from mode {:o} to {:o}",
+                path.display(),
+                mode,
+                PRIVATE_MODE
+            );
+            eprintln!(
+                "rkvr: restricted archive space {} from mode {:o} to {:o}",
+                path.display(),
+                mode,
+                PRIVATE_MODE
+            );
+        }
+        PermissionPolicy::Warn => {
+            warn!("Archive space {} has mode {:o}", path.display(), mode);
+            eprintln!(
+                "rkvr: warning: archive space {} is accessible to other users (mode {:o}); \
+                 run `chmod 700 {}` or set `space_permissions: repair`",
+                path.display(),
+                mode,
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_new_space_is_private() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("var/tmp/rmrf");
+
+        let space = prepare(&root, false, PermissionPolicy::Warn, crate::current_uid()).unwrap();
+        assert_eq!(space, root);
+        assert_eq!(mode(&root), PRIVATE_MODE);
+    }
+
+    #[test]
+    fn test_loose_space_is_warned_about_or_repaired() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("rmrf");
+        fs::create_dir(&root).unwrap();
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o755)).unwrap();
+
+        prepare(&root, false, PermissionPolicy::Warn, crate::current_uid()).unwrap();
+        assert_eq!(mode(&root), 0o755, "Warn must leave the mode alone");
+
+        prepare(&root, false, PermissionPolicy::Repair, crate::current_uid()).unwrap();
+        assert_eq!(mode(&root), PRIVATE_MODE);
+    }
+
+    #[test]
+    fn test_per_user_subtree() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("rmrf");
+
+        let space = prepare(&root, true, PermissionPolicy::Warn, crate::current_uid()).unwrap();
+        assert_eq!(space, root.join(crate::current_uid().to_string()));
+        assert_eq!(mode(&root), SHARED_MODE);
+        assert_eq!(mode(&space), PRIVATE_MODE);
+
+        fs::create_dir(root.join("lost+found")).unwrap();
+        assert_eq!(user_subtrees(&root).unwrap(), vec![space]);
+    }
+
+    #[test]
+    fn test_private_space_from_before_per_user_subtrees() {
+        let temp_dir = TempDir::new().unwrap();
+        let me = crate::current_uid();
+
+        // An empty one is shared, so other users can add their subtrees.
+        let root = temp_dir.path().join("rmrf");
+        prepare(&root, false, PermissionPolicy::Warn, me).unwrap();
+        let space = prepare(&root, true, PermissionPolicy::Warn, me).unwrap();
+        assert_eq!(mode(&root), SHARED_MODE);
+        assert_eq!(mode(&space), PRIVATE_MODE);
+
+        // One holding bundles stays private.
+        let root = temp_dir.path().join("bkup");
+        prepare(&root, false, PermissionPolicy::Warn, me).unwrap();
+        create_private_dir(&root.join("2026-06-14-153045-000")).unwrap();
+        let space = prepare(&root, true, PermissionPolicy::Warn, me).unwrap();
+        assert_eq!(mode(&root), PRIVATE_MODE);
+        assert_eq!(space, root.join(me.to_string()));
+    }
+}
@@ -0,0 +1,527 @@
+use eyre::{eyre, Context, Result};
+use fastcdc::v2020::StreamCDC;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Directory under an archive space holding the shared chunks.
+pub const CHUNKS_DIR: &str = ".chunks";
+
+/// Per-bundle manifest listing the chunks of each payload.
+pub const MANIFEST_FILE: &str = "chunks.yml";
+
+// Content-defined chunk sizes. Boundaries follow the data, so an insertion
+// early in a tar stream only changes the chunks around it.
+const MIN_CHUNK: u32 = 16 * 1024;
+const AVG_CHUNK: u32 = 64 * 1024;
+const MAX_CHUNK: u32 = 256 * 1024;
+
+/// Chunks younger than this are never collected: a bkup in progress writes
+/// its chunks before its manifest exists.
+const GC_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// How a space stores bundle payloads.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// One compressed tarball per payload inside the bundle directory.
+    #[default]
+    Tarball,
+    /// Uncompressed tar streams split into deduplicated, content-addressed
+    /// chunks shared by every bundle in the space.
+    Chunked,
+}
+
+impl StoreKind {
+    pub fn is_default(&self) -> bool {
+        *self == StoreKind::Tarball
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ChunkManifest {
+    pub payloads: Vec<Payload>,
+}
+
+/// One tar stream, stored as an ordered list of chunk hashes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Payload {
+    pub name: String,
+    pub size: u64,
+    /// The tar stream was produced under sudo and must be extracted with it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub privileged: bool,
+    pub chunks: Vec<String>,
+}
+
+impl ChunkManifest {
+    pub fn load(bundle: &Path) -> Result<Self> {
+        let path = bundle.join(MANIFEST_FILE);
+        let contents = fs::read_to_string(&path).wrap_err_with(|| format!("reading {}", path.display()))?;
+        serde_yaml::from_str(&contents).wrap_err_with(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, bundle: &Path) -> Result<()> {
+        let yaml = serde_yaml::to_string(self).wrap_err("Failed to serialize chunk manifest")?;
+        fs::write(bundle.join(MANIFEST_FILE), yaml).wrap_err("Failed to write chunk manifest")
+    }
+}
+
+pub struct ChunkStore {
+    root: PathBuf,
+}
+
+impl ChunkStore {
+    /// The store shared by all bundles directly under `space`.
+    pub fn for_space(space: &Path) -> Self {
+        Self {
+            root: space.join(CHUNKS_DIR),
+        }
+    }
+
+    /// The store a bundle directory belongs to.
+    pub fn for_bundle(bundle: &Path) -> Result<Self> {
+        let space = bundle
+            .parent()
+            .ok_or_else(|| eyre!("Bundle {} has no parent space", bundle.display()))?;
+        Ok(Self::for_space(space))
+    }
+
+    fn chunk_path(&self, hash: &str) -> PathBuf {
+        self.root.join(&hash[..2]).join(&hash[2..])
+    }
+
+    /// Split `source` into chunks, store the ones not already present, and
+    /// record the result as payload `name` in the bundle's manifest.
+    pub fn ingest(&self, source: impl Read, bundle: &Path, name: &str, privileged: bool) -> Result<Payload> {
+        let payload = self.store(source, name, privileged)?;
+        let mut manifest = if bundle.join(MANIFEST_FILE).exists() {
+            ChunkManifest::load(bundle)?
+        } else {
+            ChunkManifest::default()
+        };
+        manifest.payloads.push(payload.clone());
+        manifest.save(bundle)?;
+        Ok(payload)
+    }
+
+    /// Split `source` into chunks and store the ones not already present,
+    /// without recording the payload in any manifest.
+    pub fn store(&self, source: impl Read, name: &str, privileged: bool) -> Result<Payload> {
+        let mut payload = Payload {
+            name: name.to_string(),
+            size: 0,
+            privileged,
+            chunks: Vec::new(),
+        };
+        let mut new_chunks = 0usize;
+
+        for chunk in StreamCDC::new(source, MIN_CHUNK, AVG_CHUNK, MAX_CHUNK) {
+            let chunk = chunk.map_err(|e| eyre!("Failed to read payload {}: {}", name, e))?;
+            let (hash, written) = self.write_chunk(&chunk.data)?;
+            payload.size += chunk.length as u64;
+            payload.chunks.push(hash);
+            new_chunks += written as usize;
+        }
+
+        info!(
+            "Stored {} as {} chunks ({} new, {} bytes)",
+            name,
+            payload.chunks.len(),
+            new_chunks,
+            payload.size
+        );
+        Ok(payload)
+    }
+
+    /// Store one chunk under its SHA-256, returning the hash and whether it
+    /// was new. Chunks are zstd-compressed and written atomically.
+    ///
+    /// A reused chunk has its mtime refreshed: until this bundle's manifest
+    /// is written nothing references it, and only the grace period keeps
+    /// garbage collection from deleting it. A chunk the caller may not
+    /// touch, such as one written under sudo, is replaced by a fresh copy.
+    ///
+    /// The store's directories are private like the bundles, since chunks
+    /// hold the same data.
+    fn write_chunk(&self, data: &[u8]) -> Result<(String, bool)> {
+        let hash = format!("{:x}", Sha256::digest(data));
+        let path = self.chunk_path(&hash);
+        if path.exists() {
+            let refreshed = fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            match refreshed {
+                Ok(()) => return Ok((hash, false)),
+                Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                    debug!("Cannot refresh chunk {}, writing a copy: {}", hash, error);
+                }
+                Err(error) => return Err(error).wrap_err_with(|| format!("Failed to refresh chunk {}", hash)),
+            }
+        }
+
+        let dir = path.parent().unwrap();
+        crate::space::create_private_dir(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+        let compressed = zstd::encode_all(data, 3).wrap_err("Failed to compress chunk")?;
+        let tmp = dir.join(format!(".{}.{}", &hash[2..], std::process::id()));
+        fs::write(&tmp, compressed).wrap_err_with(|| format!("Failed to write chunk {}", hash))?;
+        fs::rename(&tmp, &path).wrap_err_with(|| format!("Failed to store chunk {}", hash))?;
+        Ok((hash, true))
+    }
+
+    /// Bytes on disk of the chunks `payload` references, shared with other
+    /// payloads or not. Missing chunks count for nothing.
+    pub fn stored_size(&self, payload: &Payload) -> u64 {
+        let chunks: HashSet<&String> = payload.chunks.iter().collect();
+        chunks
+            .into_iter()
+            .filter_map(|hash| fs::metadata(self.chunk_path(hash)).ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+
+    /// Reassemble a payload into `out`, verifying every chunk's hash.
+    pub fn restore(&self, payload: &Payload, out: &mut impl Write) -> Result<()> {
+        for hash in &payload.chunks {
+            let path = self.chunk_path(hash);
+            let compressed = fs::read(&path).wrap_err_with(|| format!("Missing chunk {} of {}", hash, payload.name))?;
+            let data = zstd::decode_all(compressed.as_slice()).wrap_err_with(|| format!("Corrupt chunk {}", hash))?;
+            if format!("{:x}", Sha256::digest(&data)) != *hash {
+                eyre::bail!("Chunk {} of {} does not match its hash", hash, payload.name);
+            }
+            out.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Delete every chunk not referenced by a bundle manifest under `space`,
+    /// returning how many were removed. Nothing is removed while any bundle
+    /// in the space cannot be read, such as another user's private one: its
+    /// manifest may reference any chunk.
+    pub fn collect_garbage(&self, space: &Path) -> Result<usize> {
+        if !self.root.is_dir() {
+            return Ok(0);
+        }
+
+        let mut referenced = HashSet::new();
+        for entry in fs::read_dir(space)?.flatten() {
+            let bundle = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') || !bundle.is_dir() {
+                continue;
+            }
+            let manifest = bundle.join(MANIFEST_FILE);
+            match fs::read_dir(&bundle).and_then(|_| manifest.try_exists()) {
+                Ok(true) => {
+                    let manifest = ChunkManifest::load(&bundle)?;
+                    referenced.extend(manifest.payloads.into_iter().flat_map(|p| p.chunks));
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    warn!(
+                        "Not collecting chunks in {}: cannot read {}: {}",
+                        self.root.display(),
+                        bundle.display(),
+                        error
+                    );
+                    return Ok(0);
+                }
+            }
+        }
+
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for prefix in fs::read_dir(&self.root)?.flatten() {
+            for chunk in fs::read_dir(prefix.path())?.flatten() {
+                let hash = format!(
+                    "{}{}",
+                    prefix.file_name().to_string_lossy(),
+                    chunk.file_name().to_string_lossy()
+                );
+                if referenced.contains(&hash) {
+                    continue;
+                }
+                let age = chunk
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .unwrap_or_default();
+                if age < GC_GRACE {
+                    continue;
+                }
+                debug!("Removing unreferenced chunk {}", hash);
+                fs::remove_file(chunk.path())?;
+                removed += 1;
+            }
+        }
+
+        info!(
+            "Chunk store {}: removed {} unreferenced chunks",
+            self.root.display(),
+            removed
+        );
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use tempfile::TempDir;
+
+    fn sample(len: usize, seed: u64) -> Vec<u8> {
+        // Deterministic, incompressible-looking bytes so chunk boundaries vary.
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ingest_and_restore_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+        let bundle = space.join("2026-06-14-153045-000");
+        fs::create_dir_all(&bundle).unwrap();
+
+        let data = sample(1024 * 1024, 42);
+        let store = ChunkStore::for_space(space);
+        let payload = store.ingest(data.as_slice(), &bundle, "dir.tar", false).unwrap();
+        assert_eq!(payload.size, data.len() as u64);
+        assert!(payload.chunks.len() > 1, "1 MiB should span several chunks");
+
+        let manifest = ChunkManifest::load(&bundle).unwrap();
+        assert_eq!(manifest.payloads, vec![payload.clone()]);
+        let chunk = store.chunk_path(&payload.chunks[0]);
+        for dir in [chunk.parent().unwrap(), store.root.as_path()] {
+            assert_eq!(fs::metadata(dir).unwrap().mode() & 0o777, 0o700, "{}", dir.display());
+        }
+        assert!(store.stored_size(&payload) > 0);
+
+        let mut restored = Vec::new();
+        store.restore(&payload, &mut restored).unwrap();
+        assert_eq!(restored, data);
+    }
+
+    #[test]
+    fn test_repeated_ingest_shares_chunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+        let store = ChunkStore::for_space(space);
+
+        let mut data = sample(2 * 1024 * 1024, 7);
+        let first = space.join("a");
+        fs::create_dir_all(&first).unwrap();
+        let a = store.ingest(data.as_slice(), &first, "dir.tar", false).unwrap();
+
+        // Change a few bytes near the end; most chunks should be reused.
+        let len = data.len();
+        data[len - 10..].copy_from_slice(b"0123456789");
+        let second = space.join("b");
+        fs::create_dir_all(&second).unwrap();
+        let b = store.ingest(data.as_slice(), &second, "dir.tar", false).unwrap();
+
+        let shared = b.chunks.iter().filter(|h| a.chunks.contains(h)).count();
+        assert!(shared >= b.chunks.len() - 2, "Only the changed chunk should differ");
+    }
+
+    #[test]
+    fn test_collect_garbage_keeps_referenced_chunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+        let store = ChunkStore::for_space(space);
+
+        let kept = space.join("kept");
+        let dropped = space.join("dropped");
+        fs::create_dir_all(&kept).unwrap();
+        fs::create_dir_all(&dropped).unwrap();
+        let kept_payload = store
+            .ingest(sample(300 * 1024, 1).as_slice(), &kept, "a.tar", false)
+            .unwrap();
+        let dropped_payload = store
+            .ingest(sample(300 * 1024, 2).as_slice(), &dropped, "b.tar", false)
+            .unwrap();
+        fs::remove_dir_all(&dropped).unwrap();
+
+        // Inside the grace period nothing is collected.
+        assert_eq!(store.collect_garbage(space).unwrap(), 0);
+
+        // Age every chunk past the grace period.
+        let old = SystemTime::now() - GC_GRACE * 2;
+        for hash in kept_payload.chunks.iter().chain(&dropped_payload.chunks) {
+            fs::File::options()
+                .write(true)
+                .open(store.chunk_path(hash))
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+
+        let removed = store.collect_garbage(space).unwrap();
+        assert_eq!(removed, dropped_payload.chunks.len());
+        let mut restored = Vec::new();
+        store.restore(&kept_payload, &mut restored).unwrap();
+        assert!(!store.chunk_path(&dropped_payload.chunks[0]).exists());
+    }
+
+    /// Run `check` without root's privileges: directly for anyone else,
+    /// and for root in a child process that drops to nobody.
+    pub(crate) fn unprivileged(check: impl FnOnce() -> bool) -> bool {
+        if unsafe { libc::getuid() } != 0 {
+            return check();
+        }
+        match unsafe { libc::fork() } {
+            0 => {
+                let passed = unsafe { libc::setgid(NOBODY) == 0 && libc::setuid(NOBODY) == 0 }
+                    && std::panic::catch_unwind(std::panic::AssertUnwindSafe(check)).unwrap_or(false);
+                unsafe { libc::_exit(if passed { 0 } else { 1 }) }
+            }
+            -1 => panic!("fork failed"),
+            child => {
+                let mut status = 0;
+                unsafe { libc::waitpid(child, &mut status, 0) };
+                libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+            }
+        }
+    }
+
+    const NOBODY: u32 = 65534;
+
+    /// Run `check` as someone who cannot read `bundle` but may change
+    /// everything open to all: the caller with the bundle at mode 000, or
+    /// for root, nobody with the bundle at 0700.
+    fn without_access(bundle: &Path, check: impl FnOnce() -> bool) -> bool {
+        let root = unsafe { libc::getuid() } == 0;
+        fs::set_permissions(bundle, fs::Permissions::from_mode(if root { 0o700 } else { 0o000 })).unwrap();
+        let passed = unprivileged(check);
+        fs::set_permissions(bundle, fs::Permissions::from_mode(0o700)).unwrap();
+        passed
+    }
+
+    #[test]
+    fn test_collect_garbage_spares_chunks_of_unreadable_bundles() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+        let store = ChunkStore::for_space(space);
+
+        let theirs = space.join("theirs");
+        let dropped = space.join("dropped");
+        fs::create_dir_all(&theirs).unwrap();
+        fs::create_dir_all(&dropped).unwrap();
+        let their_payload = store
+            .ingest(sample(300 * 1024, 4).as_slice(), &theirs, "a.tar", false)
+            .unwrap();
+        let dropped_payload = store
+            .ingest(sample(300 * 1024, 5).as_slice(), &dropped, "b.tar", false)
+            .unwrap();
+        fs::remove_dir_all(&dropped).unwrap();
+
+        // Everything but their bundle is open to all, so only reading it
+        // would keep a collection from deleting its chunks.
+        let old = SystemTime::now() - GC_GRACE * 2;
+        for hash in their_payload.chunks.iter().chain(&dropped_payload.chunks) {
+            let path = store.chunk_path(hash);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+            fs::set_permissions(path.parent().unwrap(), fs::Permissions::from_mode(0o777)).unwrap();
+        }
+        for dir in [space, store.root.as_path()] {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o777)).unwrap();
+        }
+        assert!(without_access(&theirs, || store
+            .collect_garbage(space)
+            .is_ok_and(|removed| removed == 0)));
+        for hash in &their_payload.chunks {
+            assert!(store.chunk_path(hash).exists(), "their chunk {} was collected", hash);
+        }
+
+        // Whoever can read every bundle still collects.
+        assert_eq!(store.collect_garbage(space).unwrap(), dropped_payload.chunks.len());
+        let mut restored = Vec::new();
+        store.restore(&their_payload, &mut restored).unwrap();
+    }
+
+    #[test]
+    fn test_reused_chunks_outlive_the_grace_period() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path();
+        let store = ChunkStore::for_space(space);
+        let data = sample(300 * 1024, 3);
+
+        let old = space.join("old");
+        fs::create_dir_all(&old).unwrap();
+        let payload = store.ingest(data.as_slice(), &old, "a.tar", false).unwrap();
+        fs::remove_dir_all(&old).unwrap();
+        let aged = SystemTime::now() - GC_GRACE * 2;
+        for hash in &payload.chunks {
+            fs::File::options()
+                .write(true)
+                .open(store.chunk_path(hash))
+                .unwrap()
+                .set_modified(aged)
+                .unwrap();
+        }
+
+        // A bkup still writing reuses the old chunks before its manifest
+        // exists; a collection running meanwhile must leave them alone.
+        for hash in &payload.chunks {
+            let (_, written) = store.write_chunk(&chunk_data(&store, hash)).unwrap();
+            assert!(!written);
+        }
+        assert_eq!(store.collect_garbage(space).unwrap(), 0);
+        let mut restored = Vec::new();
+        store.restore(&payload, &mut restored).unwrap();
+        assert_eq!(restored, data);
+    }
+
+    #[test]
+    fn test_chunks_the_caller_cannot_touch_are_copied() {
+        let temp_dir = TempDir::new().unwrap();
+        let space = temp_dir.path().join("space");
+        let bundle = space.join("bundle");
+        fs::create_dir_all(&bundle).unwrap();
+        let store = ChunkStore::for_space(&space);
+        let data = sample(100 * 1024, 6);
+        let payload = store.ingest(data.as_slice(), &bundle, "a.tar", false).unwrap();
+
+        // The chunk is read-only, and for root's run it belongs to root while
+        // the space belongs to nobody, as after a `sudo rkvr bkup`.
+        let chunk = store.chunk_path(&payload.chunks[0]);
+        fs::set_permissions(&chunk, fs::Permissions::from_mode(0o444)).unwrap();
+        let aged = SystemTime::now() - GC_GRACE * 2;
+        fs::File::open(&chunk).unwrap().set_modified(aged).unwrap();
+        if unsafe { libc::getuid() } == 0 {
+            for dir in [space.as_path(), &bundle, &store.root, chunk.parent().unwrap()] {
+                std::os::unix::fs::chown(dir, Some(NOBODY), Some(NOBODY)).unwrap();
+            }
+        }
+
+        assert!(unprivileged(|| {
+            let data = chunk_data(&store, &payload.chunks[0]);
+            store.write_chunk(&data).is_ok() && fs::metadata(&chunk).unwrap().modified().unwrap() > aged + GC_GRACE
+        }));
+        let mut restored = Vec::new();
+        store.restore(&payload, &mut restored).unwrap();
+        assert_eq!(restored, data);
+    }
+
+    fn chunk_data(store: &ChunkStore, hash: &str) -> Vec<u8> {
+        zstd::decode_all(fs::read(store.chunk_path(hash)).unwrap().as_slice()).unwrap()
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::CStr;
+
+/// Who ran rkvr: the real user, plus the user behind `sudo` when root was
+/// reached that way. Bundles belong to the latter, so `sudo rkvr` does not
+/// hand a user's files to root's namespace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Invoker {
+    pub name: String,
+    pub uid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sudo_user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sudo_uid: Option<u32>,
+}
+
+impl Invoker {
+    pub fn current() -> Self {
+        let uid = crate::current_uid();
+        // SUDO_* are only trustworthy when we really are root; anyone can
+        // set them in their own environment.
+        let sudo_uid = (uid == 0)
+            .then(|| std::env::var("SUDO_UID").ok()?.parse().ok())
+            .flatten();
+        let sudo_user = sudo_uid.and_then(|_| std::env::var("SUDO_USER").ok());
+        Self {
+            name: user_name(uid).unwrap_or_else(|| uid.to_string()),
+            uid,
+            sudo_user,
+            sudo_uid,
+        }
+    }
+
+    /// The uid whose namespace this invocation works in.
+    pub fn owner_uid(&self) -> u32 {
+        self.sudo_uid.unwrap_or(self.uid)
+    }
+
+    pub fn owner_name(&self) -> &str {
+        self.sudo_user.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Where a bundle came from and how it was made, so listings and searches
+/// can answer "who deleted this and how". All optional: older bundles have
+/// none of it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Provenance {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<Invoker>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// rkvr's command line, as invoked.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// The action that created the bundle, e.g. `rmrf` or `bkup`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// The rkvr version that wrote the bundle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl Provenance {
+    pub fn current(action: Option<&str>) -> Self {
+        Self {
+            user: Some(Invoker::current()),
+            host: host_name(),
+            command: std::env::args().collect(),
+            action: action.map(str::to_owned),
+            version: Some(env!("GIT_DESCRIBE").to_owned()),
+        }
+    }
+}
+
+/// This machine's host name.
+pub fn host_name() -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+    *buf.last_mut()? = 0;
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned()).filter(|n| !n.is_empty())
+}
+
+/// Login name of `uid` from the password database.
+pub fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_invoker() {
+        let invoker = Invoker::current();
+        assert_eq!(invoker.uid, crate::current_uid());
+        if invoker.sudo_uid.is_none() {
+            assert_eq!(invoker.owner_uid(), invoker.uid);
+            assert_eq!(invoker.owner_name(), invoker.name);
+        }
+    }
+
+    #[test]
+    fn test_current_provenance() {
+        let provenance = Provenance::current(Some("bkup"));
+        assert_eq!(provenance.user, Some(Invoker::current()));
+        assert!(provenance.host.is_some());
+        assert!(!provenance.command.is_empty());
+        assert_eq!(provenance.action.as_deref(), Some("bkup"));
+        assert_eq!(provenance.version.as_deref(), Some(env!("GIT_DESCRIBE")));
+    }
+
+    #[test]
+    fn test_root_user_name() {
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+    }
+}
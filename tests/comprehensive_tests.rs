@@ -21,7 +21,16 @@ fn get_binary_path() -> std::path::PathBuf {
     std::env::current_dir().unwrap().join("target/debug/rkvr")
 }
 
+/// These tests expect bundles directly in the spaces they configure, so
+/// they turn off per-user subtrees instead of reading the sample rkvr.yml.
+fn create_rkvr_config(temp_path: &Path) {
+    let config_dir = temp_path.join(".config").join("rkvr");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(config_dir.join("rkvr.yml"), "per_user_spaces: false\n").unwrap();
+}
+
 fn create_config(temp_path: &Path, rmrf_dir: &Path, bkup_dir: &Path) -> std::path::PathBuf {
+    create_rkvr_config(temp_path);
     let config_dir = temp_path.join(".config").join("rmrf");
     fs::create_dir_all(&config_dir).unwrap();
     let config_file = config_dir.join("rmrf.cfg");
@@ -183,7 +192,7 @@ fn test_list_rmrf_functionality() {
     );
 
     // List archived files
-    let list_output = run_rkvr_command(&["ls-rmrf"], temp_path);
+    let list_output = run_rkvr_command(&["ls-rmrf", "--long"], temp_path);
     assert_success(&list_output, "List rmrf files");
 
     let output_str = String::from_utf8_lossy(&list_output.stdout);
@@ -229,7 +238,7 @@ fn test_list_bkup_functionality() {
     run_rkvr_command(&["bkup", test_file.to_str().unwrap()], temp_path);
 
     // List backup files
-    let list_output = run_rkvr_command(&["ls-bkup"], temp_path);
+    let list_output = run_rkvr_command(&["ls-bkup", "--long"], temp_path);
     assert_success(&list_output, "List backup files");
 
     let output_str = String::from_utf8_lossy(&list_output.stdout);
@@ -331,6 +340,7 @@ fn create_config_with_sudo(
     bkup_dir: &Path,
     sudo_enabled: bool,
 ) -> std::path::PathBuf {
+    create_rkvr_config(temp_path);
     let config_dir = temp_path.join(".config").join("rmrf");
     fs::create_dir_all(&config_dir).unwrap();
     let config_file = config_dir.join("rmrf.cfg");
@@ -432,6 +442,7 @@ fn test_rmrf_with_cleanup_preserves_recent_archives() {
     fs::create_dir_all(&bkup_dir).unwrap();
 
     // Create config with sudo enabled and keep for 30 days
+    create_rkvr_config(temp_path);
     let config_dir = temp_path.join(".config").join("rmrf");
     fs::create_dir_all(&config_dir).unwrap();
     let config_file = config_dir.join("rmrf.cfg");
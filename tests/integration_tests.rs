@@ -21,7 +21,16 @@ fn get_binary_path() -> std::path::PathBuf {
     std::env::current_dir().unwrap().join("target/debug/rkvr")
 }
 
+/// These tests expect bundles directly in the spaces they configure, so
+/// they turn off per-user subtrees instead of reading the sample rkvr.yml.
+fn create_rkvr_config(temp_path: &Path) {
+    let config_dir = temp_path.join(".config").join("rkvr");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(config_dir.join("rkvr.yml"), "per_user_spaces: false\n").unwrap();
+}
+
 fn create_config(temp_path: &Path, rmrf_dir: &Path, bkup_dir: &Path) -> std::path::PathBuf {
+    create_rkvr_config(temp_path);
     let config_dir = temp_path.join(".config").join("rmrf");
     fs::create_dir_all(&config_dir).unwrap();
     let config_file = config_dir.join("rmrf.cfg");
@@ -400,3 +409,684 @@ fn test_recovery_functionality() {
         "Archive directory should be removed after recovery"
     );
 }
+
+#[test]
+fn test_recover_incremental_bkup_by_name() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let project = temp_path.join("work/project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("notes.txt"), "first").unwrap();
+    fs::write(project.join("kept.txt"), "kept").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let bkup = ["bkup", "--incremental", project.to_str().unwrap()];
+    assert_success(&run_rkvr_command(&bkup, temp_path), "full bkup");
+    fs::write(project.join("notes.txt"), "second").unwrap();
+    assert_success(&run_rkvr_command(&bkup, temp_path), "incremental bkup");
+    fs::remove_dir_all(&project).unwrap();
+
+    let bundles = get_archive_dirs(&bkup_dir);
+    assert_eq!(bundles.len(), 2);
+    let newest = bundles
+        .iter()
+        .map(|b| b.file_name().unwrap().to_str().unwrap())
+        .max()
+        .unwrap();
+    let output = run_rkvr_command(&["rcvr", newest], temp_path);
+    assert_success(&output, "rcvr of a bkup bundle by name");
+    assert_eq!(fs::read_to_string(project.join("notes.txt")).unwrap(), "second");
+    assert_eq!(fs::read_to_string(project.join("kept.txt")).unwrap(), "kept");
+}
+
+#[test]
+fn test_recovery_round_trip_preserves_file_metadata() {
+    use std::os::unix::fs::PermissionsExt;
+
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let test_dir = temp_path.join("meta_round_trip");
+    fs::create_dir_all(&test_dir).unwrap();
+    let test_file = test_dir.join("secret.conf");
+    fs::write(&test_file, "key = value").unwrap();
+    fs::set_permissions(&test_file, fs::Permissions::from_mode(0o600)).unwrap();
+    let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&test_file)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+
+    let c_path = std::ffi::CString::new(test_file.to_str().unwrap()).unwrap();
+    let c_name = std::ffi::CString::new("user.rkvr.test").unwrap();
+    let xattr_set = unsafe { libc::setxattr(c_path.as_ptr(), c_name.as_ptr(), b"kept".as_ptr().cast(), 4, 0) } == 0;
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", test_dir.to_str().unwrap()], temp_path);
+    assert_success(&output, "Archive for metadata round trip");
+    assert!(!test_dir.exists(), "Directory should be removed after rmrf");
+
+    let archive_dirs = get_archive_dirs(&rmrf_dir);
+    let archive_timestamp = archive_dirs[0].file_name().unwrap().to_str().unwrap();
+    let recover_output = run_rkvr_command(&["rcvr", archive_timestamp], temp_path);
+    assert_success(&recover_output, "Metadata round trip recovery");
+
+    let meta = fs::metadata(&test_file).unwrap();
+    assert_eq!(
+        meta.permissions().mode() & 0o777,
+        0o600,
+        "Mode should survive the round trip"
+    );
+    assert_eq!(meta.modified().unwrap(), mtime, "mtime should survive the round trip");
+
+    if xattr_set {
+        let mut buf = [0u8; 16];
+        let len = unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        assert_eq!(len, 4, "xattr should survive the round trip");
+        assert_eq!(&buf[..4], b"kept");
+    }
+}
+
+#[test]
+fn test_hard_links_and_sparse_files_are_stored_once() {
+    use std::os::unix::fs::MetadataExt;
+
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let test_dir = temp_path.join("vm");
+    fs::create_dir_all(&test_dir).unwrap();
+    let image = test_dir.join("disk.img");
+    fs::File::create(&image).unwrap().set_len(256 * 1024 * 1024).unwrap();
+    let first = test_dir.join("data.bin");
+    let second = test_dir.join("data-link.bin");
+    fs::write(&first, vec![7u8; 1024 * 1024]).unwrap();
+    fs::hard_link(&first, &second).unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", test_dir.to_str().unwrap()], temp_path);
+    assert_success(&output, "Archiving sparse and hard-linked files");
+
+    let archive_dirs = get_archive_dirs(&rmrf_dir);
+    let bundle_size: u64 = fs::read_dir(&archive_dirs[0])
+        .unwrap()
+        .map(|e| e.unwrap().metadata().unwrap().len())
+        .sum();
+    assert!(
+        bundle_size < 4 * 1024 * 1024,
+        "Bundle should not contain the holes or a second copy: {} bytes",
+        bundle_size
+    );
+
+    let archive_timestamp = archive_dirs[0].file_name().unwrap().to_str().unwrap();
+    let recover_output = run_rkvr_command(&["rcvr", archive_timestamp], temp_path);
+    assert_success(&recover_output, "Sparse and hard link recovery");
+
+    assert_eq!(fs::metadata(&image).unwrap().len(), 256 * 1024 * 1024);
+    assert_eq!(
+        fs::metadata(&first).unwrap().ino(),
+        fs::metadata(&second).unwrap().ino(),
+        "Hard links should be recreated"
+    );
+}
+
+#[test]
+fn test_symlink_to_directory_is_archived_as_link() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let real_dir = temp_path.join("real_dir");
+    fs::create_dir_all(&real_dir).unwrap();
+    fs::write(real_dir.join("keep.txt"), "do not delete").unwrap();
+
+    let link_parent = temp_path.join("links");
+    fs::create_dir_all(&link_parent).unwrap();
+    let link = link_parent.join("dir_link");
+    std::os::unix::fs::symlink(&real_dir, &link).unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", link.to_str().unwrap()], temp_path);
+    assert_success(&output, "Archiving a directory symlink");
+
+    assert!(fs::symlink_metadata(&link).is_err(), "Link should be removed");
+    assert!(real_dir.join("keep.txt").exists(), "Link target must be untouched");
+
+    let archive_dirs = get_archive_dirs(&rmrf_dir);
+    assert_eq!(archive_dirs.len(), 1, "Should have one archive directory");
+    let metadata = read_metadata(&archive_dirs[0]);
+    assert!(
+        metadata.contains(&format!("target: {}", real_dir.display())),
+        "Metadata should record the link target. Content:\n{}",
+        metadata
+    );
+
+    let archive_timestamp = archive_dirs[0].file_name().unwrap().to_str().unwrap();
+    let recover_output = run_rkvr_command(&["rcvr", archive_timestamp], temp_path);
+    assert_success(&recover_output, "Symlink recovery");
+
+    assert_eq!(
+        fs::read_link(&link).unwrap(),
+        real_dir,
+        "Link should be recreated exactly"
+    );
+}
+
+#[test]
+fn test_bad_level_fails_before_touching_anything() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let target = temp_path.join("keep.txt");
+    fs::write(&target, "keep me").unwrap();
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["--level", "99", "rmrf", target.to_str().unwrap()], temp_path);
+    assert!(!output.status.success(), "An out-of-range level should fail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("compression level"), "{}", stderr);
+    assert!(target.exists());
+    assert!(!rmrf_dir.exists() && !bkup_dir.exists(), "No space should be created");
+}
+
+#[test]
+fn test_partial_success_exit_code() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let test_dir = temp_path.join("partial");
+    fs::create_dir_all(&test_dir).unwrap();
+    let present = test_dir.join("present.txt");
+    fs::write(&present, "still here").unwrap();
+    let missing = test_dir.join("missing.txt");
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(
+        &["rmrf", missing.to_str().unwrap(), present.to_str().unwrap()],
+        temp_path,
+    );
+    assert_eq!(output.status.code(), Some(2), "Partial success should exit with 2");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("missing.txt"),
+        "Failure should name the missing target. Stderr:\n{}",
+        stderr
+    );
+    assert!(!present.exists(), "Present target should still be archived and removed");
+    assert_eq!(
+        get_archive_dirs(&rmrf_dir).len(),
+        1,
+        "Should have one archive directory"
+    );
+
+    // With --fail-fast nothing is touched when any target is missing.
+    let kept = test_dir.join("kept.txt");
+    fs::write(&kept, "kept").unwrap();
+    let output = run_rkvr_command(
+        &["--fail-fast", "rmrf", missing.to_str().unwrap(), kept.to_str().unwrap()],
+        temp_path,
+    );
+    assert_eq!(output.status.code(), Some(1), "Fail-fast should exit with 1");
+    assert!(kept.exists(), "Fail-fast should not remove anything");
+}
+
+#[test]
+fn test_encrypted_rmrf_verify_and_recover() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let project = temp_path.join("project");
+    fs::create_dir_all(&project).unwrap();
+    let env_file = project.join(".env");
+    fs::write(&env_file, "DATABASE_PASSWORD=correct-horse").unwrap();
+    let keyfile = temp_path.join("rkvr.key");
+    fs::write(&keyfile, "integration test key material").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(
+        &[
+            "--encrypt",
+            "--keyfile",
+            keyfile.to_str().unwrap(),
+            "rmrf",
+            env_file.to_str().unwrap(),
+        ],
+        temp_path,
+    );
+    assert_success(&output, "Encrypted rmrf");
+    assert!(!env_file.exists());
+
+    let archive_dirs = get_archive_dirs(&rmrf_dir);
+    let bundle = &archive_dirs[0];
+    assert!(bundle.join("encryption.yml").exists());
+    assert!(
+        !bundle.join("metadata.yml").exists(),
+        "Metadata must not be stored in plaintext"
+    );
+    for entry in fs::read_dir(bundle).unwrap() {
+        let raw = fs::read(entry.unwrap().path()).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("correct-horse"));
+    }
+
+    // A mistyped --keyfile is an error, not a fallback to the passphrase.
+    let missing = temp_path.join("rkvr.kye");
+    let output = Command::new(get_binary_path())
+        .args(["--keyfile", missing.to_str().unwrap(), "verify"])
+        .env("HOME", temp_path)
+        .env("RKVR_PASSPHRASE", "integration test key material")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(missing.to_str().unwrap()), "{}", stderr);
+
+    // The recorded key file is found without --keyfile.
+    let verify_output = run_rkvr_command(&["verify"], temp_path);
+    assert_success(&verify_output, "Verifying encrypted bundle");
+
+    let archive_timestamp = bundle.file_name().unwrap().to_str().unwrap();
+    let recover_output = run_rkvr_command(&["rcvr", archive_timestamp], temp_path);
+    assert_success(&recover_output, "Encrypted recovery");
+    assert_eq!(
+        fs::read_to_string(&env_file).unwrap(),
+        "DATABASE_PASSWORD=correct-horse"
+    );
+}
+
+#[test]
+fn test_ls_rmrf_json_records() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let test_dir = temp_path.join("test");
+    fs::create_dir_all(&test_dir).unwrap();
+    let notes = test_dir.join("notes.txt");
+    fs::write(&notes, "twelve bytes").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", notes.to_str().unwrap()], temp_path);
+    assert_success(&output, "rmrf");
+
+    let output = run_rkvr_command(&["ls-rmrf", "--format", "json"], temp_path);
+    assert_success(&output, "ls-rmrf --format json");
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let records = records.as_array().unwrap();
+    assert_eq!(records.len(), 1);
+
+    let record = &records[0];
+    let bundle = &get_archive_dirs(&rmrf_dir)[0];
+    assert_eq!(record["name"], bundle.file_name().unwrap().to_str().unwrap());
+    assert_eq!(record["cwd"], test_dir.to_str().unwrap());
+    assert_eq!(record["targets"], serde_json::json!(["notes.txt"]));
+    assert_eq!(record["files"], 1);
+    assert_eq!(record["original_size"], 12);
+    assert!(record["size"].as_u64().unwrap() > 0);
+    assert!(record["timestamp"].is_string());
+    assert_eq!(record["pinned"], false);
+    assert!(record["score"].is_null());
+}
+
+#[test]
+fn test_find_inside_bundles() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let test_dir = temp_path.join("test");
+    let project = test_dir.join("project");
+    fs::create_dir_all(project.join("deep/deeper")).unwrap();
+    fs::write(project.join("deep/deeper/buried.txt"), "first\nthe needle\nlast\n").unwrap();
+    let notes = test_dir.join("notes.txt");
+    fs::write(&notes, "no needle here? a needle!\n").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", project.to_str().unwrap(), notes.to_str().unwrap()], temp_path);
+    assert_success(&output, "rmrf");
+    // Each hit is `bundle<TAB>inner path[:line:text]`.
+    let hits = |output: &std::process::Output| -> Vec<String> {
+        let mut hits: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| {
+                let (bundle, hit) = line.split_once('\t').unwrap();
+                assert!(Path::new(bundle).starts_with(&rmrf_dir), "{}", line);
+                hit.to_string()
+            })
+            .collect();
+        hits.sort();
+        hits
+    };
+
+    let output = run_rkvr_command(&["find", "buried"], temp_path);
+    assert_success(&output, "find buried");
+    assert_eq!(hits(&output), ["project/deep/deeper/buried.txt"]);
+
+    let output = run_rkvr_command(&["find", "--grep", "ne+dle"], temp_path);
+    assert_success(&output, "find --grep");
+    assert_eq!(
+        hits(&output),
+        [
+            "notes.txt:1:no needle here? a needle!",
+            "project/deep/deeper/buried.txt:2:the needle"
+        ]
+    );
+
+    let output = run_rkvr_command(&["find", "*.rs"], temp_path);
+    assert_success(&output, "find with no hits");
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_index_follows_archive_and_recover() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let test_dir = temp_path.join("test");
+    fs::create_dir_all(&test_dir).unwrap();
+    let notes = test_dir.join("notes.txt");
+    fs::write(&notes, "indexed").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", notes.to_str().unwrap()], temp_path);
+    assert_success(&output, "rmrf");
+    assert!(rmrf_dir.join(".index.db").is_file(), "archive creates the index");
+
+    let names = |home: &Path| -> Vec<String> {
+        let output = run_rkvr_command(&["ls-rmrf", "--format", "jsonl"], home);
+        assert_success(&output, "ls-rmrf");
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["name"].to_string())
+            .collect()
+    };
+    assert_eq!(names(temp_path).len(), 1);
+
+    let output = run_rkvr_command(&["reindex"], temp_path);
+    assert_success(&output, "reindex");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 bundles indexed"));
+
+    let bundle = get_archive_dirs(&rmrf_dir)[0].clone();
+    let output = run_rkvr_command(&["rcvr", bundle.to_str().unwrap()], temp_path);
+    assert_success(&output, "rcvr");
+    assert_eq!(fs::read_to_string(&notes).unwrap(), "indexed");
+    assert!(names(temp_path).is_empty(), "recovered bundles leave the index");
+}
+
+#[test]
+fn test_show_bundle() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let project = temp_path.join("test/project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/lib.rs"), "pub fn answer() -> u32 { 42 }\n").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["rmrf", project.to_str().unwrap()], temp_path);
+    assert_success(&output, "rmrf");
+    let bundle = get_archive_dirs(&rmrf_dir).remove(0);
+    let name = bundle.file_name().unwrap().to_str().unwrap();
+
+    // By index, by prefix and by name alike.
+    for spec in ["@1", &name[..10], name] {
+        let output = run_rkvr_command(&["show", spec], temp_path);
+        assert_success(&output, "show");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with(&bundle.display().to_string()), "{}", stdout);
+        assert!(stdout.contains("action    rmrf"), "{}", stdout);
+        assert!(stdout.contains("project/src/lib.rs"), "{}", stdout);
+        assert!(stdout.contains("ok every payload reads back in full"), "{}", stdout);
+    }
+
+    let output = run_rkvr_command(&["show", "1999"], temp_path);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No bundle matches 1999"));
+}
+
+#[test]
+fn test_diff_bundle_against_live_files() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let project = temp_path.join("work/project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("notes.txt"), "one\ntwo\nthree\n").unwrap();
+    fs::write(project.join("old.txt"), "old\n").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+    let output = run_rkvr_command(&["bkup", project.to_str().unwrap()], temp_path);
+    assert_success(&output, "bkup");
+
+    let output = run_rkvr_command(&["diff", "@1"], temp_path);
+    assert_success(&output, "diff of an unchanged tree");
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("No changes\n"));
+
+    fs::write(project.join("notes.txt"), "one\n2\nthree\n").unwrap();
+    fs::remove_file(project.join("old.txt")).unwrap();
+    fs::write(project.join("new.txt"), "new\n").unwrap();
+
+    let output = run_rkvr_command(&["diff", "@1", "--patch"], temp_path);
+    assert_success(&output, "diff --patch");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("A  project/new.txt\n"), "{}", stdout);
+    assert!(stdout.contains("M  project/notes.txt"), "{}", stdout);
+    assert!(stdout.contains("D  project/old.txt\n"), "{}", stdout);
+    assert!(
+        stdout.contains("--- a/project/notes.txt\n+++ b/project/notes.txt\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("\n-two\n+2\n"), "{}", stdout);
+}
+
+#[test]
+fn test_diff_two_bkups() {
+    build_binary();
+
+    // Plain bkups are compared by their tar indexes, incremental ones by
+    // their file manifests.
+    for incremental in [false, true] {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let project = temp_path.join("work/project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("notes.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(project.join("kept.txt"), "same\n").unwrap();
+
+        let rmrf_dir = temp_path.join("rmrf");
+        let bkup_dir = temp_path.join("bkup");
+        fs::create_dir_all(&rmrf_dir).unwrap();
+        fs::create_dir_all(&bkup_dir).unwrap();
+        create_config(temp_path, &rmrf_dir, &bkup_dir);
+
+        let mut bkup = vec!["bkup", project.to_str().unwrap()];
+        if incremental {
+            bkup.push("--incremental");
+        }
+        assert_success(&run_rkvr_command(&bkup, temp_path), "first bkup");
+        fs::write(project.join("notes.txt"), "one\n2\nthree\n").unwrap();
+        fs::write(project.join("added.txt"), "new\n").unwrap();
+        assert_success(&run_rkvr_command(&bkup, temp_path), "second bkup");
+
+        let output = run_rkvr_command(&["diff", "@2", "@1"], temp_path);
+        assert_success(&output, "diff of two bkups");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("A  project/added.txt\n"), "{}", stdout);
+        assert!(stdout.contains("M  project/notes.txt  (size 14 → 12"), "{}", stdout);
+        assert!(!stdout.contains("kept.txt"), "{}", stdout);
+        assert!(
+            !stdout.contains("+2"),
+            "contents are only diffed on request: {}",
+            stdout
+        );
+
+        let output = run_rkvr_command(&["diff", "@2", "@1", "--patch"], temp_path);
+        assert_success(&output, "diff --patch of two bkups");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("\n-two\n+2\n"), "{}", stdout);
+    }
+}
+
+#[test]
+fn test_raw_score_threshold_is_capped() {
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let notes = temp_path.join("notes.txt");
+    fs::write(&notes, "twelve bytes").unwrap();
+
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    fs::create_dir_all(&rmrf_dir).unwrap();
+    fs::create_dir_all(&bkup_dir).unwrap();
+    let config = create_config(temp_path, &rmrf_dir, &bkup_dir);
+    let raw = fs::read_to_string(&config)
+        .unwrap()
+        .replace("threshold = 70", "threshold = 250");
+    fs::write(&config, raw).unwrap();
+
+    let output = run_rkvr_command(&["rmrf", notes.to_str().unwrap()], temp_path);
+    assert_success(&output, "rmrf");
+
+    // A raw skim score from an old rmrf.cfg still lets a perfect match through.
+    let output = run_rkvr_command(&["ls-rmrf", "notes.txt", "--format", "json"], temp_path);
+    assert_success(&output, "ls-rmrf with threshold = 250");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("threshold = 250"));
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(records.as_array().unwrap().len(), 1);
+
+    let output = run_rkvr_command(&["ls-rmrf", "notes", "--threshold", "250"], temp_path);
+    assert!(!output.status.success(), "--threshold is a percentage");
+}
+
+#[test]
+fn test_spaces_default_to_per_user_subtrees() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    build_binary();
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let notes = temp_path.join("notes.txt");
+    fs::write(&notes, "mine").unwrap();
+
+    // Only rmrf.cfg, and no rkvr.yml anywhere rkvr looks.
+    let rmrf_dir = temp_path.join("rmrf");
+    let bkup_dir = temp_path.join("bkup");
+    let config_dir = temp_path.join(".config").join("rmrf");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(
+        config_dir.join("rmrf.cfg"),
+        format!(
+            "[DEFAULT]\nrmrf_path = {}\nbkup_path = {}\nsudo = no\n",
+            rmrf_dir.display(),
+            bkup_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["rmrf", notes.to_str().unwrap()])
+        .env("HOME", temp_path)
+        .current_dir(temp_path)
+        .output()
+        .unwrap();
+    assert_success(&output, "rmrf with default configuration");
+
+    let uid = fs::metadata(temp_path).unwrap().uid();
+    for root in [&rmrf_dir, &bkup_dir] {
+        assert_eq!(fs::metadata(root).unwrap().permissions().mode() & 0o7777, 0o1777);
+        assert_eq!(
+            fs::metadata(root.join(uid.to_string())).unwrap().permissions().mode() & 0o7777,
+            0o700
+        );
+    }
+    assert_eq!(get_archive_dirs(&rmrf_dir.join(uid.to_string())).len(), 1);
+}